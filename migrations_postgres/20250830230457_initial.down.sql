@@ -0,0 +1,4 @@
+-- Add migration script here
+
+DROP TABLE UserCredentials;
+DROP TABLE Users;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+DROP TABLE WebhookDeliveries;
+DROP TABLE Webhooks;
@@ -0,0 +1,37 @@
+-- Add migration script here
+
+CREATE TABLE Tasks (
+    user_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    task_group TEXT NOT NULL,
+    config TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    closed_at TIMESTAMPTZ,
+    PRIMARY KEY (user_id, name, task_group),
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE TaskRatios (
+    user_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    task_group TEXT NOT NULL,
+    ratio REAL NOT NULL,
+    PRIMARY KEY (user_id, name, task_group),
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE TaskRecords (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    position INTEGER NOT NULL,
+    origin_name TEXT NOT NULL,
+    origin_group TEXT NOT NULL,
+    time DOUBLE PRECISION NOT NULL,
+    planned DOUBLE PRECISION,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    interruptions TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX TaskRecordsByUser ON TaskRecords (user_id, position);
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- No foreign key to Users, the trail stays after a user
+-- is deleted
+CREATE TABLE AuditLog (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    actor_id UUID,
+    action TEXT NOT NULL,
+    ip TEXT,
+    before_summary TEXT,
+    after_summary TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX AuditLogByUser ON AuditLog (user_id, created_at);
@@ -0,0 +1,15 @@
+-- Add migration script here
+
+CREATE TABLE Goals (
+    user_id UUID NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    task_name TEXT NOT NULL,
+    task_group TEXT NOT NULL,
+    target DOUBLE PRECISION NOT NULL,
+    scope TEXT NOT NULL,
+    start_at TIMESTAMPTZ NOT NULL,
+    deadline_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, position),
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
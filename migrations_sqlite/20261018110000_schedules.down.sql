@@ -0,0 +1,6 @@
+-- Add migration script here
+
+DROP INDEX TaskRecordsByUser;
+DROP TABLE TaskRecords;
+DROP TABLE TaskRatios;
+DROP TABLE Tasks;
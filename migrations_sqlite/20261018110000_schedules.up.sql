@@ -0,0 +1,37 @@
+-- Add migration script here
+
+CREATE TABLE Tasks (
+    user_id CHAR(36) NOT NULL,
+    name TEXT NOT NULL,
+    task_group TEXT NOT NULL,
+    config TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    closed_at DATETIME,
+    PRIMARY KEY (user_id, name, task_group),
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE TaskRatios (
+    user_id CHAR(36) NOT NULL,
+    name TEXT NOT NULL,
+    task_group TEXT NOT NULL,
+    ratio REAL NOT NULL,
+    PRIMARY KEY (user_id, name, task_group),
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE TaskRecords (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    position INTEGER NOT NULL,
+    origin_name TEXT NOT NULL,
+    origin_group TEXT NOT NULL,
+    time REAL NOT NULL,
+    planned REAL,
+    started_at DATETIME,
+    finished_at DATETIME,
+    interruptions TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX TaskRecordsByUser ON TaskRecords (user_id, position);
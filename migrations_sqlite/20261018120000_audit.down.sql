@@ -0,0 +1,4 @@
+-- Add migration script here
+
+DROP INDEX AuditLogByUser;
+DROP TABLE AuditLog;
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+-- No foreign key to Users, the trail stays after a user
+-- is deleted
+CREATE TABLE AuditLog (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    actor_id CHAR(36),
+    action TEXT NOT NULL,
+    ip TEXT,
+    before_summary TEXT,
+    after_summary TEXT,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX AuditLogByUser ON AuditLog (user_id, created_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+
+DROP TABLE Goals;
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- The live session of a user, kept as JSON so a restart
+-- picks up the running block
+CREATE TABLE Sessions (
+    user_id CHAR(36) PRIMARY KEY,
+    state TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
@@ -2,9 +2,16 @@ use async_trait::async_trait;
 use sqlx::migrate::MigrateError;
 use uuid::Uuid;
 
-use super::data::{Credentials, User};
+use super::config::DatabaseConfiguration;
+use super::data::{AuditContext, AuditEntry, Credentials, User, Webhook, WebhookDelivery};
 use super::error::Error;
+use super::migration::MigrationStatus;
+use crate::goal::Goal;
+use crate::session::SessionState;
+use crate::task::{Task, TaskRecord};
 
+// Every change to an account writes its audit entry together
+// with it, made by whoever `context` names
 #[async_trait]
 pub trait DatabaseUser {
     ///
@@ -14,28 +21,115 @@ pub trait DatabaseUser {
     /// // TODO: Add example
     /// ```
     ///
-    async fn create_user(&self, user: User, creds: Credentials) -> Result<(), Error>;
+    async fn create_user(
+        &self,
+        user: User,
+        creds: Credentials,
+        context: &AuditContext,
+    ) -> Result<(), Error>;
 
     async fn get_user_by_email<'s>(&self, email: &'s str) -> Result<User, Error>;
     async fn get_user_by_username<'s>(&self, username: &'s str) -> Result<User, Error>;
     async fn get_user_creds<'s>(&self, user_id: &'s str) -> Result<Credentials, Error>;
 
-    async fn update_user_email(&self, user_id: Uuid, email: String) -> Result<(), Error>;
-    async fn update_user_username(&self, user_id: Uuid, username: String) -> Result<(), Error>;
+    async fn update_user_email(
+        &self,
+        user_id: Uuid,
+        email: String,
+        context: &AuditContext,
+    ) -> Result<(), Error>;
+    async fn update_user_username(
+        &self,
+        user_id: Uuid,
+        username: String,
+        context: &AuditContext,
+    ) -> Result<(), Error>;
     async fn update_user_password_hash(
         &self,
         user_id: Uuid,
         password_hash: String,
+        context: &AuditContext,
+    ) -> Result<(), Error>;
+
+    async fn delete_user(&self, user_id: Uuid, context: &AuditContext) -> Result<(), Error>;
+}
+
+#[async_trait]
+pub trait DatabaseWebhook {
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error>;
+    async fn get_user_webhooks(&self, user_id: Uuid) -> Result<Vec<Webhook>, Error>;
+    async fn delete_webhook(&self, user_id: Uuid, webhook_id: Uuid) -> Result<(), Error>;
+
+    async fn log_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error>;
+    async fn get_webhook_deliveries(&self, webhook_id: Uuid)
+    -> Result<Vec<WebhookDelivery>, Error>;
+}
+
+// Everything a user's scheduler is built from, every set
+// replaces what was stored before
+#[async_trait]
+pub trait DatabaseSchedule {
+    async fn get_user_tasks(&self, user_id: Uuid) -> Result<Vec<Task>, Error>;
+    async fn set_user_tasks(&self, user_id: Uuid, tasks: Vec<Task>) -> Result<(), Error>;
+
+    async fn get_user_ratios(&self, user_id: Uuid) -> Result<Vec<((String, String), f32)>, Error>;
+    async fn set_user_ratios(
+        &self,
+        user_id: Uuid,
+        ratios: Vec<((String, String), f32)>,
+    ) -> Result<(), Error>;
+
+    // In the order the scheduler keeps them
+    async fn get_user_records(&self, user_id: Uuid) -> Result<Vec<TaskRecord>, Error>;
+    async fn set_user_records(&self, user_id: Uuid, records: Vec<TaskRecord>) -> Result<(), Error>;
+    // Only writes the records that changed, each with its new
+    // position, and removes the deleted ones
+    async fn update_user_records(
+        &self,
+        user_id: Uuid,
+        changed: Vec<(usize, TaskRecord)>,
+        deleted: Vec<Uuid>,
     ) -> Result<(), Error>;
 
-    async fn delete_user(&self, user_id: Uuid) -> Result<(), Error>;
+    async fn get_user_goals(&self, user_id: Uuid) -> Result<Vec<Goal>, Error>;
+    async fn set_user_goals(&self, user_id: Uuid, goals: Vec<Goal>) -> Result<(), Error>;
+
+    async fn get_user_session(&self, user_id: Uuid) -> Result<Option<SessionState>, Error>;
+    async fn set_user_session(&self, user_id: Uuid, state: SessionState) -> Result<(), Error>;
+}
+
+// Entries are only ever added, they outlive the user they
+// are about
+#[async_trait]
+pub trait DatabaseAudit {
+    async fn log_audit(&self, entry: AuditEntry) -> Result<(), Error>;
+    // Newest first
+    async fn get_user_audit(&self, user_id: Uuid, limit: u32) -> Result<Vec<AuditEntry>, Error>;
 }
 
 #[async_trait]
-pub trait Database: Send + Sync + DatabaseUser {
-    async fn connect() -> Self
+pub trait Database:
+    Send + Sync + DatabaseUser + DatabaseWebhook + DatabaseSchedule + DatabaseAudit
+{
+    async fn connect(config: &DatabaseConfiguration) -> Result<Self, Error>
     where
         Self: Sized;
 
+    // Applies every pending migration
     async fn migrate(&self) -> Result<(), MigrateError>;
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, MigrateError>;
+    // Reverts every applied migration newer than `version`
+    async fn revert(&self, version: i64) -> Result<(), MigrateError>;
+
+    // Everything done through the returned handle happens at
+    // once on commit, dropping it without one rolls back
+    async fn begin(&self) -> Result<Box<dyn Transaction>, Error>;
+}
+
+#[async_trait]
+pub trait Transaction:
+    Send + Sync + DatabaseUser + DatabaseWebhook + DatabaseSchedule + DatabaseAudit
+{
+    async fn commit(self: Box<Self>) -> Result<(), Error>;
+    async fn rollback(self: Box<Self>) -> Result<(), Error>;
 }
@@ -0,0 +1,70 @@
+use derivative::Derivative;
+use std::time::Duration;
+
+pub const IN_MEMORY: &str = ":memory:";
+pub const DEFAULT_PATH: &str = "scheduler.db";
+
+#[derive(Derivative)]
+#[derivative(Debug, Clone, Default)]
+pub struct DatabaseConfiguration {
+    // Database file for SQLite or a connection URL for
+    // Postgres. `IN_MEMORY` is gone once the process exits
+    // and is meant for tests
+    #[derivative(Default(value = "String::from(DEFAULT_PATH)"))]
+    pub path: String,
+    #[derivative(Default(value = "true"))]
+    pub create_if_missing: bool,
+    // Write-ahead logging, lets readers work while a write
+    // is going on
+    #[derivative(Default(value = "true"))]
+    pub wal: bool,
+    #[derivative(Default(value = "5"))]
+    pub pool_size: u32,
+    // How long a query waits for a locked database
+    #[derivative(Default(value = "Duration::from_secs(5)"))]
+    pub busy_timeout: Duration,
+}
+
+impl DatabaseConfiguration {
+    pub fn file(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::file(IN_MEMORY)
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        self.path == IN_MEMORY
+    }
+
+    pub fn is_postgres(&self) -> bool {
+        self.path.starts_with("postgres://") || self.path.starts_with("postgresql://")
+    }
+
+    // SCHEDULER_DATABASE, SCHEDULER_DATABASE_CREATE,
+    // SCHEDULER_DATABASE_WAL, SCHEDULER_DATABASE_POOL_SIZE
+    // and SCHEDULER_DATABASE_BUSY_TIMEOUT (in seconds),
+    // anything unset or unreadable keeps its default
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+
+        let default = Self::default();
+
+        Self {
+            path: var("SCHEDULER_DATABASE").unwrap_or(default.path),
+            create_if_missing: var("SCHEDULER_DATABASE_CREATE")
+                .unwrap_or(default.create_if_missing),
+            wal: var("SCHEDULER_DATABASE_WAL").unwrap_or(default.wal),
+            pool_size: var("SCHEDULER_DATABASE_POOL_SIZE").unwrap_or(default.pool_size),
+            busy_timeout: var("SCHEDULER_DATABASE_BUSY_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(default.busy_timeout),
+        }
+    }
+}
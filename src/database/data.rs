@@ -3,9 +3,10 @@ use argon2::{
     password_hash::{PasswordHasher, SaltString, rand_core::OsRng},
 };
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct User {
     pub id: Uuid,
     pub name: String,
@@ -58,7 +59,7 @@ impl CredentialsNoPassword {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Credentials {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -101,4 +102,176 @@ impl Credentials {
 
         Ok(password_hash == self.password_hash)
     }
+
+    // How the account shows up in the audit log
+    pub fn summary(&self) -> String {
+        format!("{} <{}>", self.username, self.email)
+    }
+}
+
+// Events a webhook can subscribe to, ticks are left out on purpose
+pub const WEBHOOK_EVENTS: [&str; 6] = [
+    "block_started",
+    "block_ended",
+    "break_started",
+    "changed",
+    "ending_soon",
+    "replanned",
+];
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn new(user_id: Uuid, url: impl Into<String>, events: Vec<String>) -> Self {
+        let secret: [u8; 32] = rand::random();
+
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            url: url.into(),
+            secret: hex::encode(secret),
+            events,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn wants(&self, event: &str) -> bool {
+        self.events.iter().any(|e| e == event)
+    }
+}
+
+// One attempt at delivering an event to a webhook
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: String,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    UserCreated,
+    EmailChanged,
+    UsernameChanged,
+    PasswordChanged,
+    UserDeleted,
+    LoggedIn,
+    LoginFailed,
+    TasksChanged,
+    RatiosChanged,
+    GoalsChanged,
+    RecordLogged,
+    RecordEdited,
+    RecordDeleted,
+    RecordSplit,
+    RecordsMerged,
+}
+
+impl AuditAction {
+    const ALL: [AuditAction; 15] = [
+        AuditAction::UserCreated,
+        AuditAction::EmailChanged,
+        AuditAction::UsernameChanged,
+        AuditAction::PasswordChanged,
+        AuditAction::UserDeleted,
+        AuditAction::LoggedIn,
+        AuditAction::LoginFailed,
+        AuditAction::TasksChanged,
+        AuditAction::RatiosChanged,
+        AuditAction::GoalsChanged,
+        AuditAction::RecordLogged,
+        AuditAction::RecordEdited,
+        AuditAction::RecordDeleted,
+        AuditAction::RecordSplit,
+        AuditAction::RecordsMerged,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::UserCreated => "user_created",
+            AuditAction::EmailChanged => "email_changed",
+            AuditAction::UsernameChanged => "username_changed",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::UserDeleted => "user_deleted",
+            AuditAction::LoggedIn => "logged_in",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::TasksChanged => "tasks_changed",
+            AuditAction::RatiosChanged => "ratios_changed",
+            AuditAction::GoalsChanged => "goals_changed",
+            AuditAction::RecordLogged => "record_logged",
+            AuditAction::RecordEdited => "record_edited",
+            AuditAction::RecordDeleted => "record_deleted",
+            AuditAction::RecordSplit => "record_split",
+            AuditAction::RecordsMerged => "records_merged",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == value)
+    }
+}
+
+// Who made a change and where the request came from
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor: Option<Uuid>,
+    pub ip: Option<String>,
+}
+
+impl AuditContext {
+    pub fn with_actor(mut self, actor: Uuid) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+}
+
+// One change to a user's account or data. Summaries are short
+// and readable, never secrets like password hashes
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub ip: Option<String>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    pub fn new(user_id: Uuid, action: AuditAction, context: &AuditContext) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            actor_id: context.actor,
+            action,
+            ip: context.ip.clone(),
+            before: None,
+            after: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn change(mut self, before: Option<String>, after: Option<String>) -> Self {
+        self.before = before;
+        self.after = after;
+        self
+    }
 }
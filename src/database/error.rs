@@ -7,6 +7,7 @@ pub enum Error {
     Uuid(uuid::Error),
     Chrono(chrono::ParseError),
     Argon2Hasher(argon2::password_hash::Error),
+    Json(serde_json::Error),
     Message(String),
     Cache,
 }
@@ -25,6 +26,7 @@ impl fmt::Display for Error {
             Error::Uuid(error) => write!(f, "{}", error),
             Error::Chrono(parse_error) => write!(f, "{}", parse_error),
             Error::Argon2Hasher(error) => write!(f, "{}", error),
+            Error::Json(error) => write!(f, "{}", error),
             Error::Message(msg) => write!(f, "{}", msg),
             Error::Cache => write!(f, "Cache error"),
         }
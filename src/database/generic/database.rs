@@ -0,0 +1,757 @@
+use super::{Columns, Decodable, Dialect, FromRow, Scope, query};
+use crate::database::{
+    client::{DatabaseAudit, DatabaseSchedule, DatabaseUser, DatabaseWebhook, Transaction},
+    data::{AuditAction, AuditContext, AuditEntry, Credentials, User, Webhook, WebhookDelivery},
+    error::Error,
+};
+use crate::goal::Goal;
+use crate::session::SessionState;
+use crate::task::{Task, TaskRecord};
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+// Every query of every backend, written once. The dialect only
+// decides how values are bound and what the placeholders are
+pub struct SqlDatabase<DB: Dialect> {
+    scope: Scope<DB>,
+}
+
+impl<DB: Dialect> SqlDatabase<DB> {
+    pub fn new(scope: Scope<DB>) -> Self {
+        Self { scope }
+    }
+
+    pub fn scope(&self) -> &Scope<DB> {
+        &self.scope
+    }
+
+    // A handle inside a new transaction, or inside the running
+    // one if there is one
+    pub async fn transaction(&self) -> Result<Self, Error> {
+        Ok(Self {
+            scope: self.scope.begin().await?,
+        })
+    }
+}
+
+impl<DB> SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+{
+    // Credentials of a user about to change, a missing user
+    // has nothing to change
+    async fn existing_creds(&self, id: Uuid) -> Result<Credentials, Error> {
+        match self.get_user_creds(&id.to_string()).await {
+            Err(Error::DB(sqlx::Error::RowNotFound)) => Err(Error::DbNoEffect),
+            result => result,
+        }
+    }
+}
+
+#[async_trait]
+impl<DB> Transaction for SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+    i32: Decodable<DB>,
+    f32: Decodable<DB>,
+    f64: Decodable<DB>,
+{
+    async fn commit(self: Box<Self>) -> Result<(), Error> {
+        self.scope.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<(), Error> {
+        self.scope.rollback().await
+    }
+}
+
+#[async_trait]
+impl<DB> DatabaseUser for SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+{
+    async fn create_user(
+        &self,
+        user: User,
+        creds: Credentials,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let entry = AuditEntry::new(user.id, AuditAction::UserCreated, context)
+            .change(None, Some(creds.summary()));
+
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        query(
+            r#"
+        INSERT INTO Users (id, name)
+            VALUES (?,?);
+        "#,
+        )
+        .bind(user.id)
+        .bind(user.name)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        let affected = query(r#"
+        INSERT INTO UserCredentials (id, user_id, email, username, password_hash, password_salt, created_at, updated_at)
+            VALUES (?,?,?,?,?,?,?,?);
+        "#)
+        .bind(creds.id)
+        .bind(creds.user_id)
+        .bind(creds.email)
+        .bind(creds.username)
+        .bind(creds.password_hash)
+        .bind(creds.password_salt)
+        .bind(creds.created_at)
+        .bind(creds.updated_at)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        insert_audit::<DB>(&mut conn, entry).await?;
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn get_user_by_email<'s>(&self, email: &'s str) -> Result<User, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let row = query(
+            r#"
+        SELECT id, name FROM Users
+        WHERE id = (SELECT user_id FROM UserCredentials WHERE email = ?);
+        "#,
+        )
+        .bind(email)
+        .fetch_one::<DB>(&mut conn)
+        .await?;
+
+        User::from_row(&row)
+    }
+
+    async fn get_user_by_username<'s>(&self, username: &'s str) -> Result<User, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let row = query(
+            r#"
+        SELECT id, name FROM Users
+        WHERE id = (SELECT user_id FROM UserCredentials WHERE username = ?);
+        "#,
+        )
+        .bind(username)
+        .fetch_one::<DB>(&mut conn)
+        .await?;
+
+        User::from_row(&row)
+    }
+
+    async fn get_user_creds<'s>(&self, user_id: &'s str) -> Result<Credentials, Error> {
+        let user_id = Uuid::parse_str(user_id).map_err(Error::Uuid)?;
+        let mut conn = self.scope.conn().await?;
+
+        let row = query(
+            r#"
+        SELECT id, user_id, email, username, password_hash, password_salt, created_at, updated_at FROM UserCredentials
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_one::<DB>(&mut conn)
+        .await?;
+
+        Credentials::from_row(&row)
+    }
+
+    async fn update_user_email(
+        &self,
+        id: Uuid,
+        email: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let tx = self.transaction().await?;
+        let before = tx.existing_creds(id).await?.email;
+        let entry = AuditEntry::new(id, AuditAction::EmailChanged, context)
+            .change(Some(before), Some(email.clone()));
+        let mut conn = tx.scope.conn().await?;
+
+        let affected = query(
+            r#"
+        UPDATE UserCredentials
+            SET email = ?, updated_at = ?
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(email)
+        .bind(Utc::now())
+        .bind(id)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        insert_audit::<DB>(&mut conn, entry).await?;
+
+        drop(conn);
+        tx.scope.commit().await
+    }
+
+    async fn update_user_username(
+        &self,
+        id: Uuid,
+        username: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let tx = self.transaction().await?;
+        let before = tx.existing_creds(id).await?.username;
+        let entry = AuditEntry::new(id, AuditAction::UsernameChanged, context)
+            .change(Some(before), Some(username.clone()));
+        let mut conn = tx.scope.conn().await?;
+
+        let affected = query(
+            r#"
+        UPDATE UserCredentials
+            SET username = ?, updated_at = ?
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(username)
+        .bind(Utc::now())
+        .bind(id)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        insert_audit::<DB>(&mut conn, entry).await?;
+
+        drop(conn);
+        tx.scope.commit().await
+    }
+
+    async fn update_user_password_hash(
+        &self,
+        id: Uuid,
+        password_hash: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let tx = self.transaction().await?;
+        let entry = AuditEntry::new(id, AuditAction::PasswordChanged, context);
+        let mut conn = tx.scope.conn().await?;
+
+        let affected = query(
+            r#"
+        UPDATE UserCredentials
+            SET password_hash = ?, updated_at = ?
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(password_hash)
+        .bind(Utc::now())
+        .bind(id)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        insert_audit::<DB>(&mut conn, entry).await?;
+
+        drop(conn);
+        tx.scope.commit().await
+    }
+
+    // Credentials are deleted first, not every backend
+    // cascades the user's deletion to them
+    async fn delete_user(&self, id: Uuid, context: &AuditContext) -> Result<(), Error> {
+        let tx = self.transaction().await?;
+        let before = tx.existing_creds(id).await?.summary();
+        let entry =
+            AuditEntry::new(id, AuditAction::UserDeleted, context).change(Some(before), None);
+        let mut conn = tx.scope.conn().await?;
+
+        query("DELETE FROM UserCredentials WHERE user_id = ?;")
+            .bind(id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        let affected = query("DELETE FROM Users WHERE id = ?;")
+            .bind(id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        insert_audit::<DB>(&mut conn, entry).await?;
+
+        drop(conn);
+        tx.scope.commit().await
+    }
+}
+
+#[async_trait]
+impl<DB> DatabaseWebhook for SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+    i32: Decodable<DB>,
+{
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let affected = query(
+            r#"
+        INSERT INTO Webhooks (id, user_id, url, secret, events, created_at)
+            VALUES (?,?,?,?,?,?);
+        "#,
+        )
+        .bind(webhook.id)
+        .bind(webhook.user_id)
+        .bind(webhook.url)
+        .bind(webhook.secret)
+        .bind(webhook.events.join(","))
+        .bind(webhook.created_at)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        Ok(())
+    }
+
+    async fn get_user_webhooks(&self, user_id: Uuid) -> Result<Vec<Webhook>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT id, user_id, url, secret, events, created_at FROM Webhooks
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(Webhook::from_row).collect()
+    }
+
+    async fn delete_webhook(&self, user_id: Uuid, webhook_id: Uuid) -> Result<(), Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let affected = query("DELETE FROM Webhooks WHERE id = ? AND user_id = ?;")
+            .bind(webhook_id)
+            .bind(user_id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        Ok(())
+    }
+
+    async fn log_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let affected = query(
+            r#"
+        INSERT INTO WebhookDeliveries (id, webhook_id, event, payload, attempt, status_code, error, created_at)
+            VALUES (?,?,?,?,?,?,?,?);
+        "#,
+        )
+        .bind(delivery.id)
+        .bind(delivery.webhook_id)
+        .bind(delivery.event)
+        .bind(delivery.payload)
+        .bind(delivery.attempt as i32)
+        .bind(delivery.status_code.map(i32::from))
+        .bind(delivery.error)
+        .bind(delivery.created_at)
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        if affected == 0 {
+            return Err(Error::DbNoEffect);
+        }
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT id, webhook_id, event, payload, attempt, status_code, error, created_at FROM WebhookDeliveries
+        WHERE webhook_id = ?
+        ORDER BY created_at;
+        "#,
+        )
+        .bind(webhook_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(WebhookDelivery::from_row).collect()
+    }
+}
+
+#[async_trait]
+impl<DB> DatabaseSchedule for SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+    f32: Decodable<DB>,
+    f64: Decodable<DB>,
+{
+    async fn get_user_tasks(&self, user_id: Uuid) -> Result<Vec<Task>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT name, task_group, config, created_at, closed_at FROM Tasks
+        WHERE user_id = ?
+        ORDER BY created_at;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(Task::from_row).collect()
+    }
+
+    async fn set_user_tasks(&self, user_id: Uuid, tasks: Vec<Task>) -> Result<(), Error> {
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        query("DELETE FROM Tasks WHERE user_id = ?;")
+            .bind(user_id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        for task in tasks {
+            query(
+                r#"
+            INSERT INTO Tasks (user_id, name, task_group, config, created_at, closed_at)
+                VALUES (?,?,?,?,?,?);
+            "#,
+            )
+            .bind(user_id)
+            .bind(task.name)
+            .bind(task.group)
+            .bind(serde_json::to_string(&task.config).map_err(Error::Json)?)
+            .bind(task.created)
+            .bind(task.closed)
+            .execute::<DB>(&mut conn)
+            .await?;
+        }
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn get_user_ratios(&self, user_id: Uuid) -> Result<Vec<((String, String), f32)>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT name, task_group, ratio FROM TaskRatios
+        WHERE user_id = ?;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(FromRow::from_row).collect()
+    }
+
+    async fn set_user_ratios(
+        &self,
+        user_id: Uuid,
+        ratios: Vec<((String, String), f32)>,
+    ) -> Result<(), Error> {
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        query("DELETE FROM TaskRatios WHERE user_id = ?;")
+            .bind(user_id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        for ((name, group), ratio) in ratios {
+            query(
+                r#"
+            INSERT INTO TaskRatios (user_id, name, task_group, ratio)
+                VALUES (?,?,?,?);
+            "#,
+            )
+            .bind(user_id)
+            .bind(name)
+            .bind(group)
+            .bind(ratio)
+            .execute::<DB>(&mut conn)
+            .await?;
+        }
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn get_user_records(&self, user_id: Uuid) -> Result<Vec<TaskRecord>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT id, origin_name, origin_group, time, planned, started_at, finished_at, interruptions FROM TaskRecords
+        WHERE user_id = ?
+        ORDER BY position;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(TaskRecord::from_row).collect()
+    }
+
+    async fn set_user_records(&self, user_id: Uuid, records: Vec<TaskRecord>) -> Result<(), Error> {
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        query("DELETE FROM TaskRecords WHERE user_id = ?;")
+            .bind(user_id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        for (position, record) in records.into_iter().enumerate() {
+            put_record::<DB>(&mut conn, user_id, position, record).await?;
+        }
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn update_user_records(
+        &self,
+        user_id: Uuid,
+        changed: Vec<(usize, TaskRecord)>,
+        deleted: Vec<Uuid>,
+    ) -> Result<(), Error> {
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        for id in deleted {
+            query("DELETE FROM TaskRecords WHERE id = ? AND user_id = ?;")
+                .bind(id)
+                .bind(user_id)
+                .execute::<DB>(&mut conn)
+                .await?;
+        }
+
+        for (position, record) in changed {
+            put_record::<DB>(&mut conn, user_id, position, record).await?;
+        }
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn get_user_goals(&self, user_id: Uuid) -> Result<Vec<Goal>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT name, task_name, task_group, target, scope, start_at, deadline_at FROM Goals
+        WHERE user_id = ?
+        ORDER BY position;
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(Goal::from_row).collect()
+    }
+
+    async fn set_user_goals(&self, user_id: Uuid, goals: Vec<Goal>) -> Result<(), Error> {
+        let tx = self.scope.begin().await?;
+        let mut conn = tx.conn().await?;
+
+        query("DELETE FROM Goals WHERE user_id = ?;")
+            .bind(user_id)
+            .execute::<DB>(&mut conn)
+            .await?;
+
+        for (position, goal) in goals.into_iter().enumerate() {
+            query(
+                r#"
+            INSERT INTO Goals (user_id, position, name, task_name, task_group, target, scope, start_at, deadline_at)
+                VALUES (?,?,?,?,?,?,?,?,?);
+            "#,
+            )
+            .bind(user_id)
+            .bind(position as i32)
+            .bind(goal.name)
+            .bind(goal.task_name)
+            .bind(goal.task_group)
+            .bind(goal.target.as_secs_f64())
+            .bind(serde_json::to_string(&goal.scope).map_err(Error::Json)?)
+            .bind(goal.start)
+            .bind(goal.deadline)
+            .execute::<DB>(&mut conn)
+            .await?;
+        }
+
+        drop(conn);
+        tx.commit().await
+    }
+
+    async fn get_user_session(&self, user_id: Uuid) -> Result<Option<SessionState>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let row = query("SELECT state FROM Sessions WHERE user_id = ?;")
+            .bind(user_id)
+            .fetch_optional::<DB>(&mut conn)
+            .await?;
+
+        row.map(|row| row.json("state")).transpose()
+    }
+
+    async fn set_user_session(&self, user_id: Uuid, state: SessionState) -> Result<(), Error> {
+        let mut conn = self.scope.conn().await?;
+
+        query(
+            r#"
+        INSERT INTO Sessions (user_id, state, updated_at)
+            VALUES (?,?,?)
+        ON CONFLICT (user_id) DO UPDATE
+            SET state = excluded.state, updated_at = excluded.updated_at;
+        "#,
+        )
+        .bind(user_id)
+        .bind(serde_json::to_string(&state).map_err(Error::Json)?)
+        .bind(Utc::now())
+        .execute::<DB>(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<DB> DatabaseAudit for SqlDatabase<DB>
+where
+    DB: Dialect,
+    String: Decodable<DB>,
+{
+    async fn log_audit(&self, entry: AuditEntry) -> Result<(), Error> {
+        let mut conn = self.scope.conn().await?;
+
+        insert_audit::<DB>(&mut conn, entry).await
+    }
+
+    async fn get_user_audit(&self, user_id: Uuid, limit: u32) -> Result<Vec<AuditEntry>, Error> {
+        let mut conn = self.scope.conn().await?;
+
+        let rows = query(
+            r#"
+        SELECT id, user_id, actor_id, action, ip, before_summary, after_summary, created_at FROM AuditLog
+        WHERE user_id = ?
+        ORDER BY created_at DESC
+        LIMIT ?;
+        "#,
+        )
+        .bind(user_id)
+        .bind(limit as i64)
+        .fetch_all::<DB>(&mut conn)
+        .await?;
+
+        rows.iter().map(AuditEntry::from_row).collect()
+    }
+}
+
+// Inserts the record or moves it to `position`, records of
+// other users are left alone
+async fn put_record<DB: Dialect>(
+    conn: &mut DB::Connection,
+    user_id: Uuid,
+    position: usize,
+    record: TaskRecord,
+) -> Result<(), Error> {
+    query(
+        r#"
+    INSERT INTO TaskRecords (id, user_id, position, origin_name, origin_group, time, planned, started_at, finished_at, interruptions)
+        VALUES (?,?,?,?,?,?,?,?,?,?)
+    ON CONFLICT (id) DO UPDATE
+        SET position = excluded.position, origin_name = excluded.origin_name,
+            origin_group = excluded.origin_group, time = excluded.time, planned = excluded.planned,
+            started_at = excluded.started_at, finished_at = excluded.finished_at,
+            interruptions = excluded.interruptions
+        WHERE TaskRecords.user_id = excluded.user_id;
+    "#,
+    )
+    .bind(record.id)
+    .bind(user_id)
+    .bind(position as i32)
+    .bind(record.origin_name)
+    .bind(record.origin_group)
+    .bind(record.time.as_secs_f64())
+    .bind(record.planned.map(|p| p.as_secs_f64()))
+    .bind(record.started)
+    .bind(record.finished)
+    .bind(serde_json::to_string(&record.interruptions).map_err(Error::Json)?)
+    .execute::<DB>(conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_audit<DB: Dialect>(
+    conn: &mut DB::Connection,
+    entry: AuditEntry,
+) -> Result<(), Error> {
+    let affected = query(
+        r#"
+    INSERT INTO AuditLog (id, user_id, actor_id, action, ip, before_summary, after_summary, created_at)
+        VALUES (?,?,?,?,?,?,?,?);
+    "#,
+    )
+    .bind(entry.id)
+    .bind(entry.user_id)
+    .bind(entry.actor_id)
+    .bind(entry.action.as_str())
+    .bind(entry.ip)
+    .bind(entry.before)
+    .bind(entry.after)
+    .bind(entry.created_at)
+    .execute::<DB>(conn)
+    .await?;
+
+    if affected == 0 {
+        return Err(Error::DbNoEffect);
+    }
+
+    Ok(())
+}
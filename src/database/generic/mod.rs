@@ -0,0 +1,483 @@
+use super::{
+    data::{AuditAction, AuditEntry, Credentials, User, Webhook, WebhookDelivery},
+    error::Error,
+};
+use crate::goal::Goal;
+use crate::task::{Task, TaskRecord};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use sqlx::{Decode, Pool, Transaction, Type, pool::PoolConnection};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+mod database;
+
+pub use database::SqlDatabase;
+
+type Shared<DB> = Arc<Mutex<Option<Transaction<'static, DB>>>>;
+
+// Where a backend runs its statements, either straight on the
+// pool or inside one transaction shared by every handle to it
+pub struct Scope<DB: sqlx::Database> {
+    pool: Pool<DB>,
+    transaction: Option<Shared<DB>>,
+    // Only the handle that began the transaction ends it,
+    // the others joined it
+    owner: bool,
+}
+
+impl<DB: sqlx::Database> Scope<DB> {
+    pub fn new(pool: Pool<DB>) -> Self {
+        Self {
+            pool,
+            transaction: None,
+            owner: false,
+        }
+    }
+
+    pub fn pool(&self) -> &Pool<DB> {
+        &self.pool
+    }
+
+    // A new transaction, or the running one when this scope
+    // already is inside of it
+    pub async fn begin(&self) -> Result<Self, Error> {
+        if let Some(transaction) = &self.transaction {
+            return Ok(Self {
+                pool: self.pool.clone(),
+                transaction: Some(transaction.clone()),
+                owner: false,
+            });
+        }
+
+        let transaction = self.pool.begin().await.map_err(Error::DB)?;
+
+        Ok(Self {
+            pool: self.pool.clone(),
+            transaction: Some(Arc::new(Mutex::new(Some(transaction)))),
+            owner: true,
+        })
+    }
+
+    // Inside a transaction there is only its one connection.
+    // Asking for it while another handle still holds it would
+    // wait forever, so that is an error instead
+    pub async fn conn(&self) -> Result<Conn<DB>, Error> {
+        let Some(transaction) = &self.transaction else {
+            return Ok(Conn::Pool(self.pool.acquire().await.map_err(Error::DB)?));
+        };
+
+        let guard = transaction.clone().try_lock_owned().map_err(|_| in_use())?;
+
+        if guard.is_none() {
+            return Err(Error::Message(String::from("Transaction already ended")));
+        }
+
+        Ok(Conn::Transaction(guard))
+    }
+
+    pub async fn commit(&self) -> Result<(), Error> {
+        match self.take()? {
+            Some(transaction) => transaction.commit().await.map_err(Error::DB),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn rollback(&self) -> Result<(), Error> {
+        match self.take()? {
+            Some(transaction) => transaction.rollback().await.map_err(Error::DB),
+            None => Ok(()),
+        }
+    }
+
+    // Same as `conn`, ending it while a connection is still
+    // held can't wait for it
+    fn take(&self) -> Result<Option<Transaction<'static, DB>>, Error> {
+        match &self.transaction {
+            Some(transaction) if self.owner => {
+                Ok(transaction.try_lock().map_err(|_| in_use())?.take())
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn in_use() -> Error {
+    Error::Message(String::from("Transaction connection is already in use"))
+}
+
+pub enum Conn<DB: sqlx::Database> {
+    Pool(PoolConnection<DB>),
+    Transaction(OwnedMutexGuard<Option<Transaction<'static, DB>>>),
+}
+
+impl<DB: sqlx::Database> Deref for Conn<DB> {
+    type Target = DB::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Conn::Pool(conn) => conn,
+            // Checked when the connection was handed out
+            Conn::Transaction(guard) => guard.as_ref().unwrap(),
+        }
+    }
+}
+
+impl<DB: sqlx::Database> DerefMut for Conn<DB> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Conn::Pool(conn) => conn,
+            Conn::Transaction(guard) => guard.as_mut().unwrap(),
+        }
+    }
+}
+
+// A value bound to a statement. Ids and timestamps are kept
+// apart so every dialect can store them its own way
+#[derive(Debug, Clone)]
+pub enum Value {
+    Text(Option<String>),
+    Int(Option<i32>),
+    BigInt(Option<i64>),
+    Real(Option<f32>),
+    Double(Option<f64>),
+    Id(Option<Uuid>),
+    Time(Option<DateTime<Utc>>),
+}
+
+macro_rules! value_from {
+    ($($variant:ident($type:ty)),* $(,)?) => {
+        $(
+            impl From<$type> for Value {
+                fn from(value: $type) -> Self {
+                    Value::$variant(Some(value.into()))
+                }
+            }
+
+            impl From<Option<$type>> for Value {
+                fn from(value: Option<$type>) -> Self {
+                    Value::$variant(value.map(Into::into))
+                }
+            }
+        )*
+    };
+}
+
+value_from!(
+    Text(String),
+    Text(&str),
+    Int(i32),
+    BigInt(i64),
+    Real(f32),
+    Double(f64),
+    Id(Uuid),
+    Time(DateTime<Utc>),
+);
+
+// A query written once for every backend, with `?` for each
+// value. The dialect turns it into its own syntax
+pub struct Statement {
+    sql: &'static str,
+    values: Vec<Value>,
+}
+
+pub fn query(sql: &'static str) -> Statement {
+    Statement {
+        sql,
+        values: vec![],
+    }
+}
+
+impl Statement {
+    pub fn bind(mut self, value: impl Into<Value>) -> Self {
+        self.values.push(value.into());
+        self
+    }
+
+    // Number of rows changed
+    pub async fn execute<DB: Dialect>(self, conn: &mut DB::Connection) -> Result<u64, Error> {
+        DB::execute(conn, self.sql, self.values).await
+    }
+
+    pub async fn fetch_all<DB: Dialect>(
+        self,
+        conn: &mut DB::Connection,
+    ) -> Result<Vec<DB::Row>, Error> {
+        DB::fetch_all(conn, self.sql, self.values).await
+    }
+
+    pub async fn fetch_optional<DB: Dialect>(
+        self,
+        conn: &mut DB::Connection,
+    ) -> Result<Option<DB::Row>, Error> {
+        DB::fetch_optional(conn, self.sql, self.values).await
+    }
+
+    pub async fn fetch_one<DB: Dialect>(self, conn: &mut DB::Connection) -> Result<DB::Row, Error> {
+        self.fetch_optional::<DB>(conn)
+            .await?
+            .ok_or(Error::DB(sqlx::Error::RowNotFound))
+    }
+}
+
+// What a backend does differently, everything else is written
+// once in `SqlDatabase`
+#[async_trait]
+pub trait Dialect: sqlx::Database<Row: Columns> {
+    async fn execute(
+        conn: &mut Self::Connection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<u64, Error>;
+
+    async fn fetch_all(
+        conn: &mut Self::Connection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Vec<Self::Row>, Error>;
+
+    async fn fetch_optional(
+        conn: &mut Self::Connection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Option<Self::Row>, Error>;
+}
+
+// Any value a backend can read straight out of a column
+pub trait Decodable<DB: sqlx::Database>: for<'r> Decode<'r, DB> + Type<DB> {}
+
+impl<DB, T> Decodable<DB> for T
+where
+    DB: sqlx::Database,
+    T: for<'r> Decode<'r, DB> + Type<DB>,
+{
+}
+
+// How a backend's rows are read. Plain values decode the same
+// everywhere, ids and timestamps are what each dialect stores
+// its own way
+pub trait Columns: sqlx::Row {
+    fn value<T: Decodable<Self::Database>>(&self, column: &str) -> Result<T, Error>;
+
+    fn optional_uuid(&self, column: &str) -> Result<Option<Uuid>, Error>;
+
+    fn optional_datetime(&self, column: &str) -> Result<Option<DateTime<Utc>>, Error>;
+
+    fn uuid(&self, column: &str) -> Result<Uuid, Error> {
+        self.optional_uuid(column)?
+            .ok_or_else(|| Error::Message(format!("Missing {}", column)))
+    }
+
+    fn datetime(&self, column: &str) -> Result<DateTime<Utc>, Error> {
+        self.optional_datetime(column)?
+            .ok_or_else(|| Error::Message(format!("Missing {}", column)))
+    }
+
+    // Nested values are kept as JSON text
+    fn json<T: DeserializeOwned>(&self, column: &str) -> Result<T, Error>
+    where
+        String: Decodable<Self::Database>,
+    {
+        serde_json::from_str(&self.value::<String>(column)?).map_err(Error::Json)
+    }
+
+    // Durations are kept as fractional seconds
+    fn seconds(&self, column: &str) -> Result<Option<Duration>, Error>
+    where
+        f64: Decodable<Self::Database>,
+    {
+        self.value::<Option<f64>>(column)?
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds).map_err(|err| {
+                    Error::Message(format!(
+                        "Invalid {} of {} seconds: {}",
+                        column, seconds, err
+                    ))
+                })
+            })
+            .transpose()
+    }
+}
+
+// Turns a row of any backend into one of our types, written
+// once against `Columns`
+pub trait FromRow<R: Columns>: Sized {
+    fn from_row(row: &R) -> Result<Self, Error>;
+}
+
+impl<R> FromRow<R> for User
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(User {
+            id: row.uuid("id")?,
+            name: row.value("name")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for Credentials
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(Credentials {
+            id: row.uuid("id")?,
+            user_id: row.uuid("user_id")?,
+            email: row.value("email")?,
+            username: row.value("username")?,
+            password_hash: row.value("password_hash")?,
+            password_salt: row.value("password_salt")?,
+            created_at: row.datetime("created_at")?,
+            updated_at: row.datetime("updated_at")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for Webhook
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        let events = row
+            .value::<String>("events")?
+            .split(',')
+            .filter(|e| !e.is_empty())
+            .map(String::from)
+            .collect();
+
+        Ok(Webhook {
+            id: row.uuid("id")?,
+            user_id: row.uuid("user_id")?,
+            url: row.value("url")?,
+            secret: row.value("secret")?,
+            events,
+            created_at: row.datetime("created_at")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for WebhookDelivery
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+    i32: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(WebhookDelivery {
+            id: row.uuid("id")?,
+            webhook_id: row.uuid("webhook_id")?,
+            event: row.value("event")?,
+            payload: row.value("payload")?,
+            attempt: row.value::<i32>("attempt")? as u32,
+            status_code: row.value::<Option<i32>>("status_code")?.map(|c| c as u16),
+            error: row.value("error")?,
+            created_at: row.datetime("created_at")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for Task
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(Task {
+            name: row.value("name")?,
+            group: row.value("task_group")?,
+            config: row.json("config")?,
+            created: row.datetime("created_at")?,
+            closed: row.optional_datetime("closed_at")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for ((String, String), f32)
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+    f32: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok((
+            (row.value("name")?, row.value("task_group")?),
+            row.value("ratio")?,
+        ))
+    }
+}
+
+impl<R> FromRow<R> for TaskRecord
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+    f64: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(TaskRecord {
+            id: row.uuid("id")?,
+            origin_name: row.value("origin_name")?,
+            origin_group: row.value("origin_group")?,
+            time: row
+                .seconds("time")?
+                .ok_or_else(|| Error::Message(String::from("Missing time")))?,
+            planned: row.seconds("planned")?,
+            started: row.optional_datetime("started_at")?,
+            finished: row.optional_datetime("finished_at")?,
+            interruptions: row.json("interruptions")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for Goal
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+    f64: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        Ok(Goal {
+            name: row.value("name")?,
+            task_name: row.value("task_name")?,
+            task_group: row.value("task_group")?,
+            target: row
+                .seconds("target")?
+                .ok_or_else(|| Error::Message(String::from("Missing target")))?,
+            scope: row.json("scope")?,
+            start: row.datetime("start_at")?,
+            deadline: row.datetime("deadline_at")?,
+        })
+    }
+}
+
+impl<R> FromRow<R> for AuditEntry
+where
+    R: Columns,
+    String: Decodable<R::Database>,
+{
+    fn from_row(row: &R) -> Result<Self, Error> {
+        let action: String = row.value("action")?;
+
+        Ok(AuditEntry {
+            id: row.uuid("id")?,
+            user_id: row.uuid("user_id")?,
+            actor_id: row.optional_uuid("actor_id")?,
+            action: AuditAction::parse(&action)
+                .ok_or_else(|| Error::Message(format!("Unknown audit action {}", action)))?,
+            ip: row.value("ip")?,
+            before: row.value("before_summary")?,
+            after: row.value("after_summary")?,
+            created_at: row.datetime("created_at")?,
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    // Whether `down` can take it back
+    pub reversible: bool,
+}
+
+// Every migration the backend knows of, oldest first
+pub(crate) async fn status<C: Migrate>(
+    migrator: &Migrator,
+    conn: &mut C,
+) -> Result<Vec<MigrationStatus>, MigrateError> {
+    conn.ensure_migrations_table().await?;
+
+    let applied = conn.list_applied_migrations().await?;
+
+    Ok(migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.iter().any(|a| a.version == m.version),
+            reversible: m.migration_type.is_reversible(),
+        })
+        .collect())
+}
+
+// What to revert down to when only the latest migration should
+// be taken back, zero reverts everything
+pub fn previous_version(status: &[MigrationStatus]) -> Option<i64> {
+    let mut applied = status.iter().filter(|m| m.applied).rev();
+
+    applied.next()?;
+
+    Some(applied.next().map(|m| m.version).unwrap_or(0))
+}
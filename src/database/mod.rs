@@ -1,6 +1,10 @@
 pub mod client;
+pub mod config;
 pub mod data;
 pub mod error;
 pub mod generic;
+pub mod migration;
 pub mod postgres;
+pub mod seed;
 pub mod sqlite;
+pub mod storage;
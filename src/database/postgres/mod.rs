@@ -0,0 +1,143 @@
+use super::{
+    client::{Database, Transaction},
+    config::DatabaseConfiguration,
+    error::Error,
+    generic::{Columns, Decodable, Dialect, Scope, SqlDatabase, Value},
+    migration::{self, MigrationStatus},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    Row,
+    migrate::{MigrateError, Migrator},
+    postgres::{PgArguments, PgConnection, PgPoolOptions, PgRow},
+    query::Query,
+};
+use uuid::Uuid;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_postgres");
+
+pub type Postgres = SqlDatabase<sqlx::Postgres>;
+
+// Ids and timestamps have native column types
+impl Columns for PgRow {
+    fn value<T: Decodable<sqlx::Postgres>>(&self, column: &str) -> Result<T, Error> {
+        self.try_get(column).map_err(Error::DB)
+    }
+
+    fn optional_uuid(&self, column: &str) -> Result<Option<Uuid>, Error> {
+        self.value(column)
+    }
+
+    fn optional_datetime(&self, column: &str) -> Result<Option<DateTime<Utc>>, Error> {
+        self.value(column)
+    }
+}
+
+// `?` becomes `$1`, `$2`, ... in the order they come
+fn numbered(sql: &str) -> String {
+    let mut count = 0;
+    let mut numbered = String::with_capacity(sql.len());
+
+    for c in sql.chars() {
+        if c == '?' {
+            count += 1;
+            numbered.push_str(&format!("${}", count));
+        } else {
+            numbered.push(c);
+        }
+    }
+
+    numbered
+}
+
+fn prepare(sql: &str, values: Vec<Value>) -> Query<'_, sqlx::Postgres, PgArguments> {
+    values
+        .into_iter()
+        .fold(sqlx::query(sql), |query, value| match value {
+            Value::Text(value) => query.bind(value),
+            Value::Int(value) => query.bind(value),
+            Value::BigInt(value) => query.bind(value),
+            Value::Real(value) => query.bind(value),
+            Value::Double(value) => query.bind(value),
+            Value::Id(value) => query.bind(value),
+            Value::Time(value) => query.bind(value),
+        })
+}
+
+#[async_trait]
+impl Dialect for sqlx::Postgres {
+    async fn execute(conn: &mut PgConnection, sql: &str, values: Vec<Value>) -> Result<u64, Error> {
+        let sql = numbered(sql);
+        let result = prepare(&sql, values)
+            .execute(conn)
+            .await
+            .map_err(Error::DB)?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn fetch_all(
+        conn: &mut PgConnection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Vec<PgRow>, Error> {
+        let sql = numbered(sql);
+        prepare(&sql, values)
+            .fetch_all(conn)
+            .await
+            .map_err(Error::DB)
+    }
+
+    async fn fetch_optional(
+        conn: &mut PgConnection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Option<PgRow>, Error> {
+        let sql = numbered(sql);
+        prepare(&sql, values)
+            .fetch_optional(conn)
+            .await
+            .map_err(Error::DB)
+    }
+}
+
+#[async_trait]
+impl Database for Postgres {
+    // Only the path, pool size and busy timeout apply, the
+    // path being a connection URL
+    async fn connect(config: &DatabaseConfiguration) -> Result<Self, Error> {
+        if config.is_in_memory() {
+            return Err(Error::Message(String::from(
+                "Postgres needs a connection URL!",
+            )));
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(config.pool_size.max(1))
+            .acquire_timeout(config.busy_timeout)
+            .connect(&config.path)
+            .await
+            .map_err(Error::DB)?;
+
+        Ok(Postgres::new(Scope::new(pool)))
+    }
+
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(self.scope().pool()).await
+    }
+
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        let mut conn = self.scope().pool().acquire().await?;
+
+        migration::status(&MIGRATOR, &mut *conn).await
+    }
+
+    async fn revert(&self, version: i64) -> Result<(), MigrateError> {
+        MIGRATOR.undo(self.scope().pool(), version).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, Error> {
+        Ok(Box::new(self.transaction().await?))
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use super::{
+    client::Database,
+    data::{AuditContext, Credentials, User},
+    error::Error,
+};
+use crate::task::{Task, TaskConfiguration};
+
+pub const DEMO_EMAIL: &str = "demo@example.com";
+pub const DEMO_USERNAME: &str = "demo";
+pub const DEMO_PASSWORD: &str = "demo";
+
+fn demo_tasks() -> Vec<(Task, f32)> {
+    let task = |name, group, minutes: u64, ratio| {
+        let config = TaskConfiguration {
+            time: Duration::from_secs(60 * minutes),
+            ..TaskConfiguration::default()
+        };
+
+        (Task::new(name, group, config), ratio)
+    };
+
+    vec![
+        task("Deep work", "work", 50, 0.5),
+        task("Email", "work", 20, 0.15),
+        task("Reading", "learning", 30, 0.2),
+        task("Exercise", "health", 30, 0.15),
+    ]
+}
+
+// Adds a demo user with a few tasks and their ratios, all or
+// nothing. None when the demo user is already there
+pub async fn seed(database: &dyn Database) -> Result<Option<User>, Error> {
+    if database.get_user_by_username(DEMO_USERNAME).await.is_ok() {
+        return Ok(None);
+    }
+
+    let user = User::new("Demo");
+    let creds = Credentials::new(user.id, DEMO_EMAIL, DEMO_USERNAME)
+        .add_password_and_salt(DEMO_PASSWORD.as_bytes())?;
+
+    let (tasks, ratios) = demo_tasks()
+        .into_iter()
+        .map(|(task, ratio)| {
+            let key = (task.name.clone(), task.group.clone());
+            (task, (key, ratio))
+        })
+        .unzip();
+
+    let transaction = database.begin().await?;
+    transaction
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await?;
+    transaction.set_user_tasks(user.id, tasks).await?;
+    transaction.set_user_ratios(user.id, ratios).await?;
+    transaction.commit().await?;
+
+    Ok(Some(user))
+}
@@ -1,246 +1,154 @@
 use super::{
-    client::{Database, DatabaseUser},
-    data::{Credentials, User},
+    client::{Database, Transaction},
+    config::{DatabaseConfiguration, IN_MEMORY},
     error::Error,
+    generic::{Columns, Decodable, Dialect, Scope, SqlDatabase, Value},
+    migration::{self, MigrationStatus},
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sqlx::{Row, migrate::MigrateError, sqlite::SqlitePool};
+use sqlx::{
+    Row,
+    migrate::{MigrateError, Migrator},
+    query::Query,
+    sqlite::{
+        SqliteArguments, SqliteConnectOptions, SqliteConnection, SqliteJournalMode,
+        SqlitePoolOptions, SqliteRow,
+    },
+};
+use std::str::FromStr;
 use uuid::Uuid;
 
-pub struct Sqlite {
-    pool: SqlitePool,
-}
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
 
-#[async_trait]
-impl Database for Sqlite {
-    async fn connect() -> Self {
-        let pool = SqlitePool::connect(":memory:")
-            .await
-            .expect("Failed to connect sqlite database.");
+pub type Sqlite = SqlDatabase<sqlx::Sqlite>;
 
-        Sqlite { pool }
+// Ids and timestamps are kept as text
+impl Columns for SqliteRow {
+    fn value<T: Decodable<sqlx::Sqlite>>(&self, column: &str) -> Result<T, Error> {
+        self.try_get(column).map_err(Error::DB)
     }
 
-    async fn migrate(&self) -> Result<(), MigrateError> {
-        sqlx::migrate!("./migrations_sqlite").run(&self.pool).await
+    fn optional_uuid(&self, column: &str) -> Result<Option<Uuid>, Error> {
+        self.value::<Option<String>>(column)?
+            .map(|value| Uuid::parse_str(&value).map_err(Error::Uuid))
+            .transpose()
+    }
+
+    fn optional_datetime(&self, column: &str) -> Result<Option<DateTime<Utc>>, Error> {
+        self.value::<Option<String>>(column)?
+            .map(|value| {
+                DateTime::parse_from_rfc3339(&value)
+                    .map(|d| d.to_utc())
+                    .map_err(Error::Chrono)
+            })
+            .transpose()
     }
 }
 
+// Queries keep their `?` placeholders
+fn prepare(sql: &str, values: Vec<Value>) -> Query<'_, sqlx::Sqlite, SqliteArguments<'_>> {
+    values
+        .into_iter()
+        .fold(sqlx::query(sql), |query, value| match value {
+            Value::Text(value) => query.bind(value),
+            Value::Int(value) => query.bind(value),
+            Value::BigInt(value) => query.bind(value),
+            Value::Real(value) => query.bind(value),
+            Value::Double(value) => query.bind(value),
+            Value::Id(value) => query.bind(value.map(|id| id.to_string())),
+            Value::Time(value) => query.bind(value.map(|time| time.to_rfc3339())),
+        })
+}
+
 #[async_trait]
-impl DatabaseUser for Sqlite {
-    async fn create_user(&self, user: User, creds: Credentials) -> Result<(), Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let result = sqlx::query(r#"
-        INSERT INTO Users (id, name) 
-            VALUES (?,?);
-        INSERT INTO UserCredentials (id, user_id, email, username, password_hash, password_salt, created_at, updated_at)
-            VALUES (?,?,?,?,?,?,?,?);
-        "#)
-            .bind(user.id.to_string())
-            .bind(user.name)
-
-            .bind(creds.id.to_string())
-            .bind(creds.user_id.to_string())
-            .bind(creds.email)
-            .bind(creds.username)
-            .bind(creds.password_hash)
-            .bind(creds.password_salt)
-            .bind(creds.created_at.to_rfc3339())
-            .bind(creds.updated_at.to_rfc3339())
-            .execute(&mut conn)
+impl Dialect for sqlx::Sqlite {
+    async fn execute(
+        conn: &mut SqliteConnection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<u64, Error> {
+        let result = prepare(sql, values)
+            .execute(conn)
             .await
-            .map_err(|err| Error::DB(err))?;
-
-        if result.rows_affected() == 0 {
-            return Err(Error::DbNoEffect);
-        }
+            .map_err(Error::DB)?;
 
-        Ok(())
+        Ok(result.rows_affected())
     }
 
-    async fn get_user_by_email<'s>(&self, email: &'s str) -> Result<User, Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let row = sqlx::query(
-            r#"
-        SELECT id,name FROM Users
-        WHERE id = (SELECT user_id FROM UserCredentials WHERE email = ?);
-        "#,
-        )
-        .bind(email)
-        .fetch_one(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        let id_str: String = row.try_get("id").map_err(|err| Error::DB(err))?;
-        let name: String = row.try_get("name").map_err(|err| Error::DB(err))?;
-
-        let id = Uuid::parse_str(&id_str).map_err(|err| Error::Uuid(err))?;
-
-        Ok(User { id, name })
-    }
-    async fn get_user_by_username<'s>(&self, username: &'s str) -> Result<User, Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let row = sqlx::query(
-            r#"
-        SELECT id, name FROM Users
-        WHERE id = (SELECT user_id FROM UserCredentials WHERE username = ?)
-        "#,
-        )
-        .bind(username)
-        .fetch_one(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        let id_str: String = row.try_get("id").map_err(|err| Error::DB(err))?;
-        let name: String = row.try_get("name").map_err(|err| Error::DB(err))?;
-
-        let id = Uuid::parse_str(&id_str).map_err(|err| Error::Uuid(err))?;
-
-        Ok(User { id, name })
+    async fn fetch_all(
+        conn: &mut SqliteConnection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Vec<SqliteRow>, Error> {
+        prepare(sql, values)
+            .fetch_all(conn)
+            .await
+            .map_err(Error::DB)
     }
 
-    async fn get_user_creds<'s>(&self, user_id: &'s str) -> Result<Credentials, Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let row = sqlx::query(
-            r#"
-        SELECT id, user_id, email, username, password_hash, password_salt, created_at, updated_at FROM UserCredentials;
-        WHERE user_id = ?;
-        "#,
-        )
-        .bind(user_id)
-        .fetch_one(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        let id_str: String = row.try_get("id").map_err(|err| Error::DB(err))?;
-        let user_id_str: String = row.try_get("user_id").map_err(|err| Error::DB(err))?;
-        let email: String = row.try_get("email").map_err(|err| Error::DB(err))?;
-        let username: String = row.try_get("username").map_err(|err| Error::DB(err))?;
-        let password_hash: String = row.try_get("password_hash").map_err(|err| Error::DB(err))?;
-        let password_salt: String = row.try_get("password_salt").map_err(|err| Error::DB(err))?;
-        let created_at_str: String = row.try_get("created_at").map_err(|err| Error::DB(err))?;
-        let updated_at_str: String = row.try_get("updated_at").map_err(|err| Error::DB(err))?;
-
-        let id = Uuid::parse_str(&id_str).map_err(|err| Error::Uuid(err))?;
-        let user_id = Uuid::parse_str(&user_id_str).map_err(|err| Error::Uuid(err))?;
-
-        let created_at: DateTime<Utc> = DateTime::parse_from_rfc3339(&created_at_str)
-            .map_err(|err| Error::Chrono(err))?
-            .to_utc();
-
-        let updated_at: DateTime<Utc> = DateTime::parse_from_rfc3339(&updated_at_str)
-            .map_err(|err| Error::Chrono(err))?
-            .to_utc();
-
-        Ok(Credentials {
-            id,
-            user_id,
-            email,
-            username,
-            password_hash,
-            password_salt,
-            created_at,
-            updated_at,
-        })
+    async fn fetch_optional(
+        conn: &mut SqliteConnection,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Option<SqliteRow>, Error> {
+        prepare(sql, values)
+            .fetch_optional(conn)
+            .await
+            .map_err(Error::DB)
     }
+}
 
-    async fn update_user_email(&self, id: Uuid, email: String) -> Result<(), Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let result = sqlx::query(
-            r#"
-        UPDATE UserCredentials
-            SET email = ?
-        WHERE user_id = ?;
-        "#,
-        )
-        .bind(email)
-        .bind(id.to_string())
-        .execute(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        if result.rows_affected() == 0 {
-            return Err(Error::DbNoEffect);
+#[async_trait]
+impl Database for Sqlite {
+    async fn connect(config: &DatabaseConfiguration) -> Result<Self, Error> {
+        let options = if config.is_in_memory() {
+            SqliteConnectOptions::from_str(IN_MEMORY).map_err(Error::DB)?
+        } else {
+            SqliteConnectOptions::new()
+                .filename(&config.path)
+                .create_if_missing(config.create_if_missing)
+                .journal_mode(if config.wal {
+                    SqliteJournalMode::Wal
+                } else {
+                    SqliteJournalMode::Delete
+                })
+        }
+        .foreign_keys(true)
+        .busy_timeout(config.busy_timeout);
+
+        let mut pool = SqlitePoolOptions::new().max_connections(config.pool_size.max(1));
+
+        // Every connection to `:memory:` opens a database of its
+        // own, so there is exactly one and it's kept open for good
+        if config.is_in_memory() {
+            pool = pool
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None);
         }
 
-        Ok(())
-    }
+        let pool = pool.connect_with(options).await.map_err(Error::DB)?;
 
-    async fn update_user_username(&self, id: Uuid, username: String) -> Result<(), Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let result = sqlx::query(
-            r#"
-        UPDATE UserCredentials
-            set username = ?
-        WHERE user_id = ?;
-        "#,
-        )
-        .bind(username)
-        .bind(id.to_string())
-        .execute(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        if result.rows_affected() == 0 {
-            return Err(Error::DbNoEffect);
-        }
+        Ok(Sqlite::new(Scope::new(pool)))
+    }
 
-        Ok(())
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(self.scope().pool()).await
     }
 
-    async fn update_user_password_hash(
-        &self,
-        id: Uuid,
-        password_hash: String,
-    ) -> Result<(), Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let result = sqlx::query(
-            r#"
-        UPDATE UserCredentials
-            SET password_hash = ?
-        WHERE user_id = ?;
-        "#,
-        )
-        .bind(password_hash)
-        .bind(id.to_string())
-        .execute(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        if result.rows_affected() == 0 {
-            return Err(Error::DbNoEffect);
-        }
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        let mut conn = self.scope().pool().acquire().await?;
 
-        Ok(())
+        migration::status(&MIGRATOR, &mut *conn).await
     }
 
-    async fn delete_user(&self, id: Uuid) -> Result<(), Error> {
-        let mut conn = self.pool.acquire().await.map_err(|err| Error::DB(err))?;
-
-        let result = sqlx::query(
-            r#"
-        DELETE FROM Users WHERE id = ?;
-        DELETE FROM UserCredentials WHERE user_id = ?;
-        "#,
-        )
-        .bind(id.to_string())
-        .bind(id.to_string())
-        .execute(&mut conn)
-        .await
-        .map_err(|err| Error::DB(err))?;
-
-        if result.rows_affected() == 0 {
-            return Err(Error::DbNoEffect);
-        }
+    async fn revert(&self, version: i64) -> Result<(), MigrateError> {
+        MIGRATOR.undo(self.scope().pool(), version).await
+    }
 
-        Ok(())
+    async fn begin(&self) -> Result<Box<dyn Transaction>, Error> {
+        Ok(Box::new(self.transaction().await?))
     }
 }
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+use super::{client::Database, data::AuditEntry, error::Error};
+use crate::{
+    goal::Goal,
+    session::SessionState,
+    storage::{Storable, StorageError},
+    task::{Task, TaskRecord},
+};
+
+// A user's collections loaded from the database, so the
+// scheduler and sessions can use it like a `FileStorage`.
+// Reads are served from memory, stores wait for the database
+// and only change the copy in memory once it was written.
+// Stores block, so like file locks they must not run on the
+// async threads
+pub struct DatabaseStorage {
+    database: Arc<dyn Database>,
+    user_id: Uuid,
+    runtime: Handle,
+    tasks: Mutex<Vec<Task>>,
+    ratios: Mutex<Vec<((String, String), f32)>>,
+    records: Mutex<Vec<TaskRecord>>,
+    goals: Mutex<Vec<Goal>>,
+    session: Mutex<Vec<SessionState>>,
+    audit: Option<PendingAudit>,
+}
+
+// Audit entries of history edits, written in the same
+// transaction as the next history store of their user
+#[derive(Clone, Default)]
+pub struct PendingAudit(Arc<Mutex<HashMap<Uuid, Vec<AuditEntry>>>>);
+
+impl PendingAudit {
+    pub fn push(&self, entry: AuditEntry) {
+        let mut pending = self.0.lock().unwrap();
+        pending.entry(entry.user_id).or_default().push(entry);
+    }
+
+    fn take(&self, user_id: Uuid) -> Vec<AuditEntry> {
+        self.0.lock().unwrap().remove(&user_id).unwrap_or_default()
+    }
+}
+
+// Records of `after` that are new or moved since `before`,
+// and the ids of the ones that are gone
+pub(crate) fn history_changes(
+    before: &[TaskRecord],
+    after: &[TaskRecord],
+) -> (Vec<(usize, TaskRecord)>, Vec<Uuid>) {
+    let changed = after
+        .iter()
+        .enumerate()
+        .filter(|(position, record)| before.get(*position) != Some(*record))
+        .map(|(position, record)| (position, record.clone()))
+        .collect();
+
+    let kept: HashSet<Uuid> = after.iter().map(|record| record.id).collect();
+    let deleted = before
+        .iter()
+        .map(|record| record.id)
+        .filter(|id| !kept.contains(id))
+        .collect();
+
+    (changed, deleted)
+}
+
+impl DatabaseStorage {
+    pub async fn load(database: Arc<dyn Database>, user_id: Uuid) -> Result<Self, Error> {
+        let tasks = database.get_user_tasks(user_id).await?;
+        let ratios = database.get_user_ratios(user_id).await?;
+        let records = database.get_user_records(user_id).await?;
+        let goals = database.get_user_goals(user_id).await?;
+        let session = database.get_user_session(user_id).await?;
+
+        Ok(Self {
+            database,
+            user_id,
+            runtime: Handle::current(),
+            tasks: Mutex::new(tasks),
+            ratios: Mutex::new(ratios),
+            records: Mutex::new(records),
+            goals: Mutex::new(goals),
+            session: Mutex::new(session.into_iter().collect()),
+            audit: None,
+        })
+    }
+
+    // History stores write the entries pushed for this user
+    pub fn with_audit(mut self, audit: PendingAudit) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    fn write(&self, write: impl Future<Output = Result<(), Error>>) -> Result<(), StorageError> {
+        self.runtime.block_on(write).map_err(|e| {
+            StorageError::Io(std::io::Error::other(format!(
+                "Failed to store schedule of {}: {}",
+                self.user_id, e
+            )))
+        })
+    }
+}
+
+impl Storable<Task> for DatabaseStorage {
+    fn store(&self, data: &[Task]) -> Result<(), StorageError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        self.write(self.database.set_user_tasks(self.user_id, data.to_vec()))?;
+        *tasks = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<Task>, StorageError> {
+        Ok(self.tasks.lock().unwrap().clone())
+    }
+}
+
+impl Storable<((String, String), f32)> for DatabaseStorage {
+    fn store(&self, data: &[((String, String), f32)]) -> Result<(), StorageError> {
+        let mut ratios = self.ratios.lock().unwrap();
+        self.write(self.database.set_user_ratios(self.user_id, data.to_vec()))?;
+        *ratios = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<((String, String), f32)>, StorageError> {
+        Ok(self.ratios.lock().unwrap().clone())
+    }
+}
+
+// Sessions store the whole history on every change, only
+// what differs from the last store is written
+impl Storable<TaskRecord> for DatabaseStorage {
+    fn store(&self, data: &[TaskRecord]) -> Result<(), StorageError> {
+        let mut records = self.records.lock().unwrap();
+        let (changed, deleted) = history_changes(&records, data);
+
+        // Taken either way, an edit that changed nothing or
+        // wasn't written has nothing to audit
+        let entries = match &self.audit {
+            Some(audit) => audit.take(self.user_id),
+            None => vec![],
+        };
+
+        if !changed.is_empty() || !deleted.is_empty() {
+            self.write(async {
+                let transaction = self.database.begin().await?;
+                transaction
+                    .update_user_records(self.user_id, changed, deleted)
+                    .await?;
+                for entry in entries {
+                    transaction.log_audit(entry).await?;
+                }
+                transaction.commit().await
+            })?;
+        }
+
+        *records = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        Ok(self.records.lock().unwrap().clone())
+    }
+}
+
+impl Storable<Goal> for DatabaseStorage {
+    fn store(&self, data: &[Goal]) -> Result<(), StorageError> {
+        let mut goals = self.goals.lock().unwrap();
+        self.write(self.database.set_user_goals(self.user_id, data.to_vec()))?;
+        *goals = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<Goal>, StorageError> {
+        Ok(self.goals.lock().unwrap().clone())
+    }
+}
+
+impl Storable<SessionState> for DatabaseStorage {
+    fn store(&self, data: &[SessionState]) -> Result<(), StorageError> {
+        let mut session = self.session.lock().unwrap();
+        self.write(
+            self.database
+                .set_user_session(self.user_id, data.first().cloned().unwrap_or_default()),
+        )?;
+        *session = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<SessionState>, StorageError> {
+        Ok(self.session.lock().unwrap().clone())
+    }
+}
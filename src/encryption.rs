@@ -0,0 +1,273 @@
+use crate::schema::{self, Envelope};
+use crate::storage::{FileStorage, Storable, StorageError, Stored, assign_ids, lock, write_file};
+use argon2::Argon2;
+use chacha20poly1305::{
+    Key, XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path, sync::Mutex};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+// Derived keys by passphrase and salt
+type KeyCache = HashMap<(usize, [u8; SALT_LEN]), [u8; 32]>;
+
+// What ends up on disk, only the ciphertext is secret
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// Keeps every collection of a `FileStorage` encrypted, the
+// files are opaque so formats and the history log are not
+// used, only the registered paths and upgrades
+pub struct EncryptedStorage<P>
+where
+    P: AsRef<Path>,
+{
+    inner: FileStorage<P>,
+    // Files are always sealed with the first one, the rest
+    // only open files from before a rotation
+    passphrases: Vec<String>,
+    salt: [u8; SALT_LEN],
+    // Deriving a key is slow on purpose, every passphrase
+    // and salt pair is only derived once
+    keys: Mutex<KeyCache>,
+}
+
+impl<P> EncryptedStorage<P>
+where
+    P: AsRef<Path>,
+{
+    pub fn new(inner: FileStorage<P>, passphrase: impl Into<String>) -> Self {
+        Self {
+            inner,
+            passphrases: vec![passphrase.into()],
+            salt: rand::random(),
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_previous_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrases.push(passphrase.into());
+        self
+    }
+
+    fn key(&self, passphrase: usize, salt: [u8; SALT_LEN]) -> Result<[u8; 32], StorageError> {
+        if let Some(key) = self.keys.lock().unwrap().get(&(passphrase, salt)) {
+            return Ok(*key);
+        }
+
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrases[passphrase].as_bytes(), &salt, &mut key)
+            .map_err(|_| StorageError::Crypto("Failed to derive key"))?;
+
+        self.keys.lock().unwrap().insert((passphrase, salt), key);
+
+        Ok(key)
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let key = self.key(0, self.salt)?;
+        let nonce: [u8; NONCE_LEN] = rand::random();
+
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(XNonce::from_slice(&nonce), plaintext)
+            .map_err(|_| StorageError::Crypto("Failed to encrypt"))?;
+
+        Ok(serde_json::to_vec_pretty(&Sealed {
+            salt: hex::encode(self.salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })?)
+    }
+
+    // Plaintext and whether it was sealed with the current
+    // passphrase
+    fn open(&self, sealed: &[u8]) -> Result<(Vec<u8>, bool), StorageError> {
+        let sealed: Sealed = serde_json::from_slice(sealed)?;
+
+        let salt: [u8; SALT_LEN] = decode_hex(&sealed.salt)?;
+        let nonce: [u8; NONCE_LEN] = decode_hex(&sealed.nonce)?;
+        let ciphertext =
+            hex::decode(&sealed.ciphertext).map_err(|_| StorageError::Crypto("Malformed file"))?;
+
+        for passphrase in 0..self.passphrases.len() {
+            let key = self.key(passphrase, salt)?;
+
+            if let Ok(plaintext) = XChaCha20Poly1305::new(Key::from_slice(&key))
+                .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            {
+                return Ok((plaintext, passphrase == 0));
+            }
+        }
+
+        Err(StorageError::Crypto("Wrong passphrase or tampered file"))
+    }
+
+    // Seals every file that still needs a previous passphrase
+    // with the current one, returns how many were changed.
+    // Plaintext files are left to be sealed by their next read
+    pub fn rotate(&self) -> Result<usize, StorageError> {
+        let mut rotated = 0;
+
+        for path in self.inner.paths() {
+            let _lock = lock(path, true)?;
+
+            let Some(sealed) = read_file(path)?.filter(|buf| is_sealed(buf)) else {
+                continue;
+            };
+
+            let (plaintext, current) = self.open(&sealed)?;
+
+            if !current {
+                write_file(path, &self.seal(&plaintext)?)?;
+                rotated += 1;
+            }
+        }
+
+        Ok(rotated)
+    }
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Result<[u8; N], StorageError> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(StorageError::Crypto("Malformed file"))
+}
+
+// None for missing and empty files
+fn read_file(path: &Path) -> Result<Option<Vec<u8>>, StorageError> {
+    match fs::read(path) {
+        Ok(buf) if buf.iter().all(|b| b.is_ascii_whitespace()) => Ok(None),
+        Ok(buf) => Ok(Some(buf)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+impl<P> EncryptedStorage<P>
+where
+    P: AsRef<Path>,
+{
+    fn seal_entries<T: Stored>(&self, data: &[T]) -> Result<Vec<u8>, StorageError> {
+        let plaintext = serde_json::to_vec(&Envelope {
+            version: schema::current_version(self.inner.upgrades::<T>()),
+            entries: data,
+        })?;
+
+        self.seal(&plaintext)
+    }
+
+    fn unseal<T: Stored>(&self, sealed: &[u8]) -> Result<Vec<T>, StorageError> {
+        let (plaintext, _) = self.open(sealed)?;
+        let (version, entries) = schema::open(serde_json::from_slice(&plaintext)?);
+
+        // Upgraded entries are only written back on the
+        // next store
+        let upgrades = self.inner.upgrades::<T>();
+        let entries = schema::upgrade(entries, version.unwrap_or(schema::FIRST_VERSION), upgrades)?;
+
+        Ok(serde_json::from_value(entries)?)
+    }
+
+    // Files written before encryption was turned on are read
+    // in their own format and sealed in place. No plaintext
+    // copy is kept, backups from upgrades included
+    fn seal_existing<T: Stored>(&self) -> Result<Vec<T>, StorageError> {
+        let path = self.inner.path::<T>()?;
+        let _lock = lock(path, true)?;
+
+        // Someone else may have sealed it in the meantime
+        match read_file(path)? {
+            None => return Ok(vec![]),
+            Some(buf) if is_sealed(&buf) => return self.unseal(&buf),
+            Some(_) => {}
+        }
+
+        let data: Vec<T> = self.inner.read_locked()?;
+        write_file(path, &self.seal_entries(&data)?)?;
+        remove_backups(path)?;
+
+        Ok(data)
+    }
+}
+
+// Upgrades leave the file as it was next to it, as
+// `<file>.v<version>.bak`
+fn remove_backups(path: &Path) -> Result<(), StorageError> {
+    let Some(name) = path.file_name() else {
+        return Ok(());
+    };
+    let prefix = format!("{}.v", name.to_string_lossy());
+
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+
+        let is_backup = name
+            .to_string_lossy()
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".bak"))
+            .is_some_and(|version| version.parse::<u32>().is_ok());
+
+        if is_backup {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_sealed(buf: &[u8]) -> bool {
+    serde_json::from_slice::<Sealed>(buf).is_ok()
+}
+
+impl<T, P> Storable<T> for EncryptedStorage<P>
+where
+    T: Stored,
+    P: AsRef<Path>,
+{
+    fn store(&self, data: &[T]) -> Result<(), StorageError> {
+        let sealed = self.seal_entries(data)?;
+
+        let path = self.inner.path::<T>()?;
+        let _lock = lock(path, true)?;
+        write_file(path, &sealed)
+    }
+
+    // Plaintext files are sealed on their first read
+    fn get(&self) -> Result<Vec<T>, StorageError> {
+        let path = self.inner.path::<T>()?;
+
+        let sealed = {
+            let _lock = lock(path, false)?;
+            read_file(path)?
+        };
+
+        let mut data = match sealed {
+            None => vec![],
+            Some(sealed) if is_sealed(&sealed) => self.unseal(&sealed)?,
+            Some(_) => self.seal_existing()?,
+        };
+
+        // Same as `FileStorage`, the ids have to stay the
+        // same between loads
+        if assign_ids(&mut data) {
+            self.store(&data)?;
+        }
+
+        Ok(data)
+    }
+}
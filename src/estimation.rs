@@ -0,0 +1,182 @@
+use crate::task::{Task, TaskRecord};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::time::Duration;
+
+// How far back the history is looked at to work
+// out how much time a task gets per day
+pub const ESTIMATION_WINDOW_DAYS: i64 = 14;
+
+// Planned vs actual time of every block in a group
+#[derive(Debug, Clone)]
+pub struct GroupEstimate {
+    pub group: String,
+    pub planned: Duration,
+    pub actual: Duration,
+    pub samples: usize,
+}
+
+impl GroupEstimate {
+    // Above 1.0 means blocks in this group tend to overrun
+    pub fn factor(&self) -> f32 {
+        if self.planned.is_zero() {
+            return 1.0;
+        }
+
+        self.actual.as_secs_f32() / self.planned.as_secs_f32()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskEstimate {
+    pub name: String,
+    pub group: String,
+    pub effort: Duration,
+    pub done: Duration,
+    pub remaining: Duration,
+    // Blocks still needed once the group overrun is accounted for
+    pub remaining_blocks: u32,
+    pub daily_rate: Duration,
+    pub projected_completion: Option<DateTime<Utc>>,
+}
+
+impl TaskEstimate {
+    // None when there is not enough history to tell
+    pub fn can_finish_by(&self, deadline: DateTime<Utc>) -> Option<bool> {
+        if self.remaining.is_zero() {
+            return Some(true);
+        }
+
+        self.projected_completion.map(|date| date <= deadline)
+    }
+}
+
+pub struct Estimator<'h> {
+    history: &'h [TaskRecord],
+}
+
+impl<'h> Estimator<'h> {
+    pub fn new(history: &'h [TaskRecord]) -> Self {
+        Self { history }
+    }
+
+    fn work(&self) -> impl Iterator<Item = &'h TaskRecord> {
+        self.history
+            .iter()
+            .filter(|r| !r.origin_group.starts_with("system/"))
+    }
+
+    pub fn group_estimates(&self) -> Vec<GroupEstimate> {
+        let mut estimates: Vec<GroupEstimate> = Vec::new();
+
+        for record in self.work() {
+            let Some(planned) = record.planned else {
+                continue;
+            };
+
+            if let Some(estimate) = estimates
+                .iter_mut()
+                .find(|e| e.group == record.origin_group)
+            {
+                estimate.planned += planned;
+                estimate.actual += record.time;
+                estimate.samples += 1;
+            } else {
+                estimates.push(GroupEstimate {
+                    group: record.origin_group.clone(),
+                    planned,
+                    actual: record.time,
+                    samples: 1,
+                });
+            }
+        }
+
+        estimates
+    }
+
+    // Overrun across every planned block in the history,
+    // the history is per user so this is the user's factor
+    pub fn overrun_factor(&self) -> f32 {
+        let (planned, actual) = self
+            .work()
+            .filter_map(|r| r.planned.map(|planned| (planned, r.time)))
+            .fold(
+                (Duration::from_secs(0), Duration::from_secs(0)),
+                |acc, (planned, actual)| (acc.0 + planned, acc.1 + actual),
+            );
+
+        if planned.is_zero() {
+            return 1.0;
+        }
+
+        actual.as_secs_f32() / planned.as_secs_f32()
+    }
+
+    pub fn task_estimate(&self, task: &Task, now: DateTime<Utc>) -> Option<TaskEstimate> {
+        let effort = task.config.effort?;
+
+        let records: Vec<&TaskRecord> = self
+            .work()
+            .filter(|r| r.origin_name == task.name && r.origin_group == task.group)
+            .collect();
+
+        let done = records
+            .iter()
+            .fold(Duration::from_secs(0), |acc, r| acc + r.time);
+        let remaining = effort.saturating_sub(done);
+
+        let factor = self
+            .group_estimates()
+            .iter()
+            .find(|e| e.group == task.group)
+            .map(|e| e.factor())
+            .unwrap_or_else(|| self.overrun_factor());
+
+        let block = task.config.time.mul_f32(factor);
+        let remaining_blocks = if block.is_zero() {
+            0
+        } else {
+            (remaining.as_secs_f32() / block.as_secs_f32()).ceil() as u32
+        };
+
+        let window = TimeDelta::days(ESTIMATION_WINDOW_DAYS);
+        let window_start = now - window;
+        let recent = records
+            .iter()
+            .filter(|r| r.finished.is_some_and(|f| f >= window_start && f <= now))
+            .fold(Duration::from_secs(0), |acc, r| acc + r.time);
+
+        // Tasks younger than the window are averaged over the
+        // days they have existed, with at least one day
+        let span = records
+            .iter()
+            .filter_map(|r| r.started.or(r.finished))
+            .min()
+            .map(|first| (now - first).clamp(TimeDelta::days(1), window))
+            .unwrap_or(window);
+        let daily_rate = recent.div_f64(span.as_seconds_f64() / 86400.0);
+
+        let projected_completion = if remaining.is_zero() {
+            Some(now)
+        } else if daily_rate.is_zero() {
+            None
+        } else {
+            let days = remaining.as_secs_f64() / daily_rate.as_secs_f64();
+            // Out of range when the rate is tiny, too far out to matter
+            Duration::try_from_secs_f64(days * 86400.0)
+                .ok()
+                .and_then(|duration| TimeDelta::from_std(duration).ok())
+                .and_then(|delta| now.checked_add_signed(delta))
+        };
+
+        Some(TaskEstimate {
+            name: task.name.clone(),
+            group: task.group.clone(),
+            effort,
+            done,
+            remaining,
+            remaining_blocks,
+            daily_rate,
+            projected_completion,
+        })
+    }
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Everything the scheduler looked at when it decided
+// what a given ScheduleTask should be
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleExplanation {
+    pub candidates: Vec<CandidateCost>,
+    pub current_ratio: f32,
+    pub target_ratio: f32,
+    pub break_trigger: Option<BreakTrigger>,
+    pub excluded: Vec<ExcludedTask>,
+}
+
+// Cost the schedule would end up with if this
+// task was the one picked, lower is better
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateCost {
+    pub name: String,
+    pub group: String,
+    pub cost: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BreakKind {
+    Break,
+    Minibreak,
+}
+
+// Which constraint forced a break, `elapsed` is the
+// work time since the last break of the same kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakTrigger {
+    pub kind: BreakKind,
+    pub elapsed: Duration,
+    pub frequency: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExclusionReason {
+    // A break was due, so no task was considered
+    BreakDue,
+    // Task was closed before the time being scheduled
+    Closed,
+    // Target ratio is zero, the task is never due
+    NoRatio,
+    // Blocks of the task would take no time
+    NoTime,
+    // Task does not repeat and is already in the history
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExcludedTask {
+    pub name: String,
+    pub group: String,
+    pub reason: ExclusionReason,
+}
@@ -0,0 +1,107 @@
+use crate::storage::Stored;
+use crate::task::TaskRecord;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GoalScope {
+    // Only time spent between `start` and `deadline` counts,
+    // e.g. "30 hours of Rust this month"
+    Period,
+    // Every record of the task counts, e.g. "finish
+    // chapter 5 by Nov 1"
+    Lifetime,
+}
+
+// A measurable target attached to a task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goal {
+    pub name: String,
+    pub task_name: String,
+    pub task_group: String,
+    pub target: Duration,
+    pub scope: GoalScope,
+    pub start: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pace {
+    Ahead,
+    OnTrack,
+    Behind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalProgress {
+    pub done: Duration,
+    pub target: Duration,
+    // Where progress should be by now to finish on time
+    pub expected: Duration,
+    pub pace: Pace,
+}
+
+impl GoalProgress {
+    pub fn percent(&self) -> f32 {
+        if self.target.is_zero() {
+            return 100.0;
+        }
+
+        (self.done.as_secs_f32() / self.target.as_secs_f32() * 100.0).min(100.0)
+    }
+
+    // Share of the target the goal is lagging behind,
+    // zero when ahead or on track
+    pub fn behind(&self) -> f32 {
+        if self.target.is_zero() {
+            return 0.0;
+        }
+
+        let missing = self.expected.saturating_sub(self.done);
+        (missing.as_secs_f32() / self.target.as_secs_f32()).min(1.0)
+    }
+}
+
+impl Stored for Goal {}
+
+impl Goal {
+    pub fn is_for(&self, name: &str, group: &str) -> bool {
+        self.task_name == name && self.task_group == group
+    }
+
+    pub fn progress(&self, history: &[TaskRecord], now: DateTime<Utc>) -> GoalProgress {
+        let done = history
+            .iter()
+            .filter(|r| self.is_for(&r.origin_name, &r.origin_group))
+            .filter(|r| match self.scope {
+                GoalScope::Lifetime => true,
+                GoalScope::Period => r
+                    .finished
+                    .is_some_and(|f| f >= self.start && f <= self.deadline),
+            })
+            .fold(Duration::from_secs(0), |acc, r| acc + r.time);
+
+        let total = (self.deadline - self.start).num_seconds().max(1) as f32;
+        let elapsed = (now - self.start).num_seconds().clamp(0, total as i64) as f32;
+        let expected = self.target.mul_f32(elapsed / total);
+
+        // A minute either way still counts as on track
+        let tolerance = Duration::from_secs(60);
+
+        let pace = if done >= self.target || done > expected + tolerance {
+            Pace::Ahead
+        } else if done + tolerance < expected {
+            Pace::Behind
+        } else {
+            Pace::OnTrack
+        };
+
+        GoalProgress {
+            done,
+            target: self.target,
+            expected,
+            pace,
+        }
+    }
+}
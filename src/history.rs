@@ -0,0 +1,443 @@
+use crate::schema::{self, FIRST_VERSION, Upgrade};
+use crate::storage::{Storable, StorageError, backup, end_line, line_version, lock, write_file};
+use crate::task::TaskRecord;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+// A line of the log, a record replaces the one with the
+// same id or is added to the end. A compacted log only
+// holds records and is a plain JSON Lines history
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum LogEntry {
+    Delete { deleted: Uuid },
+    Record(TaskRecord),
+}
+
+// Append-only history, finishing a block writes a single
+// line instead of the whole file
+pub struct HistoryLog<P>
+where
+    P: AsRef<Path>,
+{
+    path: P,
+    // History as this log last read or wrote it, compared
+    // against on every store to find what has to be appended
+    // and what other writers changed since
+    known: Mutex<Option<Vec<TaskRecord>>>,
+    upgrades: Vec<Upgrade>,
+}
+
+impl<P> HistoryLog<P>
+where
+    P: AsRef<Path>,
+{
+    pub fn new(path: P) -> Self {
+        Self {
+            path,
+            known: Mutex::new(None),
+            upgrades: vec![],
+        }
+    }
+
+    // Same as the upgrades of `FileStorage`, records are
+    // upgraded one version at a time
+    pub fn with_upgrades(mut self, upgrades: Vec<Upgrade>) -> Self {
+        self.upgrades = upgrades;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+
+    // A crash in the middle of an append leaves a truncated
+    // last line, it is skipped here and cut off by the next
+    // append
+    pub fn read(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        let loaded = {
+            let _lock = lock(self.path(), false)?;
+            self.load()?
+        };
+
+        let records = match loaded {
+            Some(records) => records,
+            // Read again under the exclusive lock, someone else
+            // may have upgraded the log in the meantime
+            None => {
+                let _lock = lock(self.path(), true)?;
+                self.load_current()?
+            }
+        };
+
+        *self.known.lock().unwrap() = Some(records.clone());
+
+        Ok(records)
+    }
+
+    // Upgrades logs of an older version, callers hold the
+    // exclusive lock
+    pub(crate) fn load_current(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        match self.load()? {
+            Some(records) => Ok(records),
+            None => self.upgrade(),
+        }
+    }
+
+    // None for logs of an older version, logs without a
+    // version line are of the first one. Callers hold the
+    // lock
+    fn load(&self) -> Result<Option<Vec<TaskRecord>>, StorageError> {
+        let buf = match fs::read_to_string(self.path()) {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = buf.split_inclusive('\n').peekable();
+        let version = lines.peek().and_then(|line| schema::header(line));
+        let current = schema::current_version(&self.upgrades);
+
+        match version.unwrap_or(FIRST_VERSION) {
+            version if version > current => {
+                return Err(StorageError::UnsupportedVersion(version, current));
+            }
+            version if version < current => return Ok(None),
+            _ => {}
+        }
+
+        let mut records: Vec<TaskRecord> = Vec::new();
+
+        for line in lines.skip(version.is_some() as usize) {
+            let entry = match serde_json::from_str::<LogEntry>(line) {
+                Ok(entry) => entry,
+                Err(_) if line.trim().is_empty() => continue,
+                Err(_) if !line.ends_with('\n') => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            match entry {
+                LogEntry::Delete { deleted } => records.retain(|r| r.id != deleted),
+                // Records without an id are never replaced
+                LogEntry::Record(record) => match records
+                    .iter_mut()
+                    .find(|r| !record.id.is_nil() && r.id == record.id)
+                {
+                    Some(existing) => *existing = record,
+                    None => records.push(record),
+                },
+            }
+        }
+
+        Ok(Some(records))
+    }
+
+    // Old entries may not fit the current types, so the log
+    // is folded and upgraded as plain JSON and written back
+    // compacted. Callers hold the exclusive lock
+    fn upgrade(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        let buf = fs::read_to_string(self.path())?;
+
+        let mut lines = buf.split_inclusive('\n').peekable();
+        let header = lines.peek().and_then(|line| schema::header(line));
+        let version = header.unwrap_or(FIRST_VERSION);
+
+        let mut records: Vec<Value> = Vec::new();
+
+        for line in lines.skip(header.is_some() as usize) {
+            let entry = match serde_json::from_str::<Value>(line) {
+                Ok(entry) => entry,
+                Err(_) if line.trim().is_empty() => continue,
+                Err(_) if !line.ends_with('\n') => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            match (entry.get("id").cloned(), entry.get("deleted")) {
+                (None, Some(deleted)) => records.retain(|r| r.get("id") != Some(deleted)),
+                (Some(id), _) => match records.iter_mut().find(|r| r.get("id") == Some(&id)) {
+                    Some(existing) => *existing = entry,
+                    None => records.push(entry),
+                },
+                (None, None) => records.push(entry),
+            }
+        }
+
+        let records: Vec<TaskRecord> = serde_json::from_value(schema::upgrade(
+            Value::Array(records),
+            version,
+            &self.upgrades,
+        )?)?;
+
+        backup(self.path(), version)?;
+        self.write(&records.iter().collect::<Vec<_>>())?;
+
+        Ok(records)
+    }
+
+    pub(crate) fn store_records(&self, records: &[&TaskRecord]) -> Result<(), StorageError> {
+        let known = self.known.lock().unwrap().take();
+        let known = match known {
+            Some(known) => Some(known),
+            // Whatever is unreadable gets replaced by the
+            // history we were given
+            None => self.read().ok(),
+        };
+
+        let entries = known
+            .as_ref()
+            .and_then(|known| append_entries(known, records));
+
+        let result = match entries {
+            Some(entries) if entries.is_empty() => Ok(()),
+            Some(entries) => self.append(&entries),
+            None => self.rewrite(known.as_deref(), records),
+        };
+
+        // On failure the file is in an unknown state and is
+        // read again before the next store
+        if result.is_ok() {
+            *self.known.lock().unwrap() = Some(records.iter().map(|r| (*r).clone()).collect());
+        }
+
+        result
+    }
+
+    // Adds records to the end without reading the log, one
+    // with an id that is already there replaces it
+    pub(crate) fn append_records(&self, records: &[&TaskRecord]) -> Result<(), StorageError> {
+        let entries: Vec<LogEntry> = records
+            .iter()
+            .map(|r| LogEntry::Record((*r).clone()))
+            .collect();
+
+        let mut known = self.known.lock().unwrap();
+        let result = self.append(&entries);
+
+        match (&result, known.as_mut()) {
+            (Ok(()), Some(known)) => {
+                for record in records {
+                    match known.iter_mut().find(|r| r.id == record.id) {
+                        Some(existing) => *existing = (*record).clone(),
+                        None => known.push((*record).clone()),
+                    }
+                }
+            }
+            _ => *known = None,
+        }
+
+        result
+    }
+
+    fn append(&self, entries: &[LogEntry]) -> Result<(), StorageError> {
+        let buf = lines(entries)?;
+        let current = schema::current_version(&self.upgrades);
+
+        let _lock = lock(self.path(), true)?;
+
+        match line_version(self.path())? {
+            // New logs start with their version
+            None => {
+                let mut log = schema::header_line(current)?;
+                log.extend(buf);
+                return write_file(self.path(), &log);
+            }
+            Some(version) if version > current => {
+                return Err(StorageError::UnsupportedVersion(version, current));
+            }
+            Some(version) if version < current => {
+                self.upgrade()?;
+            }
+            Some(_) => {}
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(self.path())?;
+        end_line(&mut file)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    // Read again under the same lock as the write, so what
+    // other writers changed since `known` is merged in instead
+    // of being overwritten
+    fn rewrite(
+        &self,
+        known: Option<&[TaskRecord]>,
+        records: &[&TaskRecord],
+    ) -> Result<(), StorageError> {
+        let _lock = lock(self.path(), true)?;
+
+        match self.load_current() {
+            Ok(current) => {
+                let merged = merge(known.unwrap_or_default(), current, records);
+                self.write(&merged.iter().collect::<Vec<_>>())
+            }
+            Err(err @ StorageError::UnsupportedVersion(..)) => Err(err),
+            // Whatever is unreadable gets replaced by the
+            // history we were given
+            Err(_) => self.write(records),
+        }
+    }
+
+    // Callers hold the exclusive lock
+    fn write(&self, records: &[&TaskRecord]) -> Result<(), StorageError> {
+        let mut buf = schema::header_line(schema::current_version(&self.upgrades))?;
+        buf.extend(lines(records)?);
+        write_file(self.path(), &buf)
+    }
+
+    // Leaves one line per record, updates and deletions are
+    // folded in. The log is locked from the read to the write
+    // so no append in between gets lost
+    pub fn compact(&self) -> Result<(), StorageError> {
+        let _lock = lock(self.path(), true)?;
+        let records = self.load_current()?;
+        self.write(&records.iter().collect::<Vec<_>>())
+    }
+
+    // Keeps the log as it was next to the original with a
+    // timestamp appended and starts a compacted one
+    pub fn rotate(&self) -> Result<PathBuf, StorageError> {
+        let _lock = lock(self.path(), true)?;
+        let records = self.load_current()?;
+
+        let mut archive: OsString = self.path().as_os_str().to_owned();
+        archive.push(format!(".{}", Utc::now().format("%Y%m%dT%H%M%S")));
+        let archive = PathBuf::from(archive);
+
+        fs::copy(self.path(), &archive)?;
+        self.write(&records.iter().collect::<Vec<_>>())?;
+
+        Ok(archive)
+    }
+}
+
+fn lines<T: Serialize>(items: &[T]) -> Result<Vec<u8>, StorageError> {
+    let mut buf = Vec::new();
+    for item in items {
+        serde_json::to_writer(&mut buf, item)?;
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+// `records` with the changes made to the log since `known`
+// was read, where both changed a record ours is kept. Records
+// without an id can't be told apart and are taken from ours
+fn merge(
+    known: &[TaskRecord],
+    current: Vec<TaskRecord>,
+    records: &[&TaskRecord],
+) -> Vec<TaskRecord> {
+    let find = |history: &[TaskRecord], id: Uuid| -> Option<usize> {
+        (!id.is_nil())
+            .then(|| history.iter().position(|r| r.id == id))
+            .flatten()
+    };
+
+    let mut merged: Vec<TaskRecord> = Vec::with_capacity(records.len());
+
+    for record in records {
+        let base = find(known, record.id).map(|i| &known[i]);
+
+        match (base, find(&current, record.id)) {
+            // Left as it was, so theirs
+            (Some(base), Some(theirs)) if base == *record => merged.push(current[theirs].clone()),
+            // Deleted by them and not changed by us
+            (Some(base), None) if base == *record => {}
+            _ => merged.push((*record).clone()),
+        }
+    }
+
+    let ours: HashSet<Uuid> = records.iter().map(|r| r.id).collect();
+    let base: HashSet<Uuid> = known.iter().map(|r| r.id).collect();
+
+    // Added by them, in finishing order like the scheduler
+    // inserts them
+    for record in current {
+        if record.id.is_nil() || ours.contains(&record.id) || base.contains(&record.id) {
+            continue;
+        }
+
+        let index = record
+            .finished
+            .and_then(|finished| {
+                merged
+                    .iter()
+                    .position(|r| r.finished.is_some_and(|f| f > finished))
+            })
+            .unwrap_or(merged.len());
+        merged.insert(index, record);
+    }
+
+    merged
+}
+
+// Entries turning `known` into `records`, none if records
+// were inserted or moved anywhere but the end
+fn append_entries(known: &[TaskRecord], records: &[&TaskRecord]) -> Option<Vec<LogEntry>> {
+    // Lines without an id can't be deleted or replaced by
+    // a later line
+    if known.iter().any(|r| r.id.is_nil()) {
+        return None;
+    }
+
+    let ids: HashSet<Uuid> = records.iter().map(|r| r.id).collect();
+    let known_ids: HashSet<Uuid> = known.iter().map(|r| r.id).collect();
+
+    let kept: Vec<&TaskRecord> = known.iter().filter(|r| ids.contains(&r.id)).collect();
+
+    if records.len() < kept.len() {
+        return None;
+    }
+
+    let (head, tail) = records.split_at(kept.len());
+
+    if head.iter().zip(kept.iter()).any(|(a, b)| a.id != b.id)
+        || tail.iter().any(|r| known_ids.contains(&r.id))
+    {
+        return None;
+    }
+
+    let deleted = known
+        .iter()
+        .filter(|r| !ids.contains(&r.id))
+        .map(|r| LogEntry::Delete { deleted: r.id });
+
+    let changed = head
+        .iter()
+        .zip(kept.iter())
+        .filter(|(a, b)| **a != **b)
+        .map(|(a, _)| LogEntry::Record((*a).clone()));
+
+    let added = tail.iter().map(|r| LogEntry::Record((*r).clone()));
+
+    Some(deleted.chain(changed).chain(added).collect())
+}
+
+impl<P> Storable<TaskRecord> for HistoryLog<P>
+where
+    P: AsRef<Path>,
+{
+    fn store(&self, data: &[TaskRecord]) -> Result<(), StorageError> {
+        self.store_records(&data.iter().collect::<Vec<_>>())
+    }
+
+    fn get(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        self.read()
+    }
+}
@@ -0,0 +1,94 @@
+use crate::task::TaskRecord;
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterruptionKind {
+    // Self inflicted, e.g. checking the phone
+    Internal,
+    // Caused by someone else, e.g. a call
+    External,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interruption {
+    pub kind: InterruptionKind,
+    pub duration: Duration,
+    pub note: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterruptionStats {
+    pub internal: usize,
+    pub external: usize,
+    pub lost: Duration,
+}
+
+impl InterruptionStats {
+    fn add(&mut self, interruption: &Interruption) {
+        match interruption.kind {
+            InterruptionKind::Internal => self.internal += 1,
+            InterruptionKind::External => self.external += 1,
+        }
+
+        self.lost += interruption.duration;
+    }
+
+    pub fn count(&self) -> usize {
+        self.internal + self.external
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskInterruptions {
+    pub name: String,
+    pub group: String,
+    pub stats: InterruptionStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterruptionReport {
+    pub total: InterruptionStats,
+    pub tasks: Vec<TaskInterruptions>,
+    // Indexed by the UTC hour the interruption happened in
+    pub hours: Vec<InterruptionStats>,
+}
+
+impl InterruptionReport {
+    pub fn from_history(history: &[TaskRecord]) -> Self {
+        let mut report = Self {
+            hours: vec![InterruptionStats::default(); 24],
+            ..Default::default()
+        };
+
+        for record in history {
+            for interruption in record.interruptions.iter() {
+                report.total.add(interruption);
+                report.hours[interruption.at.hour() as usize].add(interruption);
+
+                let task = report
+                    .tasks
+                    .iter_mut()
+                    .find(|t| t.name == record.origin_name && t.group == record.origin_group);
+
+                if let Some(task) = task {
+                    task.stats.add(interruption);
+                } else {
+                    let mut stats = InterruptionStats::default();
+                    stats.add(interruption);
+
+                    report.tasks.push(TaskInterruptions {
+                        name: record.origin_name.clone(),
+                        group: record.origin_group.clone(),
+                        stats,
+                    });
+                }
+            }
+        }
+
+        report
+    }
+}
@@ -1,26 +1,66 @@
 use cache::client::CacheStorage;
 use chrono::TimeDelta;
 use database::client::Database;
+use database::config::DatabaseConfiguration;
+use database::data::{AuditAction, AuditContext, AuditEntry};
+use database::storage::PendingAudit;
+use live::LiveSessions;
+use session::{Session, SessionState};
+use std::path::PathBuf;
+use std::sync::Arc;
+use storage::Storable;
+use task::TaskRecord;
 use uuid::Uuid;
 
 pub mod cache;
 pub mod database;
+pub mod encryption;
+pub mod estimation;
+pub mod explanation;
+pub mod goal;
+pub mod history;
+pub mod interruption;
+pub mod live;
 pub mod schedule;
+pub mod schema;
 pub mod server;
+pub mod session;
+pub mod simulation;
 pub mod storage;
 pub mod task;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod watch;
+pub mod webhook;
 
 pub struct AppState {
-    pub database: Box<dyn Database>,
+    pub database: Arc<dyn Database>,
     pub cache: Box<dyn CacheStorage>,
+    pub live: LiveSessions,
+    // Audit entries of edits made through live sessions
+    pub audit: PendingAudit,
+    // Task and ratio files edited by hand, one directory per
+    // user id. Changes are stored and reloaded into the
+    // user's live session
+    pub task_files: Option<PathBuf>,
 }
 
 impl AppState {
-    pub async fn connect<DB: Database + 'static, CS: CacheStorage + 'static>() -> Self {
-        Self {
-            database: Box::new(DB::connect().await),
+    pub async fn connect<DB: Database + 'static, CS: CacheStorage + 'static>(
+        config: &DatabaseConfiguration,
+    ) -> Result<Self, crate::database::error::Error> {
+        Ok(Self {
+            database: Arc::new(DB::connect(config).await?),
             cache: Box::new(CS::connect().await),
-        }
+            live: LiveSessions::default(),
+            audit: PendingAudit::default(),
+            task_files: None,
+        })
+    }
+
+    pub fn with_task_files(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.task_files = Some(dir.into());
+        self
     }
 }
 
@@ -30,6 +70,7 @@ impl AppState {
         username: &Option<String>,
         email: &Option<String>,
         password: &String,
+        context: &AuditContext,
     ) -> Result<String, crate::database::error::Error> {
         let user = if let Some(username) = username {
             self.database.get_user_by_email(username).await?
@@ -46,11 +87,25 @@ impl AppState {
         let creds = self.database.get_user_creds(&user.id.to_string()).await?;
 
         if !creds.check_password(password.as_bytes())? {
+            self.database
+                .log_audit(AuditEntry::new(user.id, AuditAction::LoginFailed, context))
+                .await?;
+
             return Err(crate::database::error::Error::Message(String::from(
                 "Password mismatch!",
             )));
         }
 
+        // Logged first, so a login that cannot be audited does
+        // not leave a session behind
+        self.database
+            .log_audit(AuditEntry::new(
+                user.id,
+                AuditAction::LoggedIn,
+                &context.clone().with_actor(user.id),
+            ))
+            .await?;
+
         let uuid = Uuid::new_v4().to_string();
         let key = format!("session:{}", uuid);
 
@@ -66,4 +121,40 @@ impl AppState {
 
         Ok(uuid)
     }
+
+    // User a session id returned by `authenticate` belongs to
+    pub async fn session_user(
+        &self,
+        session_id: &str,
+    ) -> Result<Uuid, crate::database::error::Error> {
+        let user_id = self
+            .cache
+            .get(&format!("session:{}", session_id))
+            .await
+            .ok_or(crate::database::error::Error::Message(String::from(
+                "Unknown session!",
+            )))?;
+
+        Uuid::parse_str(&user_id).map_err(crate::database::error::Error::Uuid)
+    }
+}
+
+impl AppState {
+    // Starts driving the user's session and forwards its
+    // events to the user's webhooks, unless the user already
+    // has one running. Returns whether it was attached
+    pub async fn attach_session<S>(self: &Arc<Self>, user_id: Uuid, session: Session<S>) -> bool
+    where
+        S: Storable<SessionState> + Storable<TaskRecord> + Send + 'static,
+    {
+        if !self.live.attach(user_id, session).await {
+            return false;
+        }
+
+        if let Some(receiver) = self.live.subscribe(user_id).await {
+            tokio::spawn(webhook::forward(self.clone(), user_id, receiver));
+        }
+
+        true
+    }
 }
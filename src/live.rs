@@ -0,0 +1,441 @@
+use crate::interruption::InterruptionKind;
+use crate::schedule::Scheduler;
+use crate::session::{Session, SessionError, SessionEvent, SessionState, SessionTransition};
+use crate::storage::{Storable, StorageError};
+use crate::task::{ScheduleTask, TaskRecord};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::{Mutex, broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+pub const ENDING_SOON: Duration = Duration::from_secs(60);
+pub const UPCOMING_PREVIEW: usize = 5;
+
+const EVENT_BUFFER: usize = 64;
+const COMMAND_BUFFER: usize = 16;
+
+// What gets streamed to the clients of a user
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    BlockStarted {
+        task: ScheduleTask,
+        at: DateTime<Utc>,
+    },
+    BreakStarted {
+        task: ScheduleTask,
+        at: DateTime<Utc>,
+    },
+    BlockEnded {
+        event: SessionEvent,
+        task: ScheduleTask,
+        at: DateTime<Utc>,
+        record: Option<TaskRecord>,
+    },
+    // Pause, resume, extend and interruptions
+    Changed {
+        event: SessionEvent,
+        task: ScheduleTask,
+        at: DateTime<Utc>,
+    },
+    Tick {
+        task: ScheduleTask,
+        remaining: Duration,
+    },
+    EndingSoon {
+        task: ScheduleTask,
+        remaining: Duration,
+    },
+    Replanned {
+        upcoming: Vec<ScheduleTask>,
+    },
+    Rejected {
+        message: String,
+    },
+}
+
+impl LiveEvent {
+    // Same name the event is tagged with when serialized
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LiveEvent::BlockStarted { .. } => "block_started",
+            LiveEvent::BreakStarted { .. } => "break_started",
+            LiveEvent::BlockEnded { .. } => "block_ended",
+            LiveEvent::Changed { .. } => "changed",
+            LiveEvent::Tick { .. } => "tick",
+            LiveEvent::EndingSoon { .. } => "ending_soon",
+            LiveEvent::Replanned { .. } => "replanned",
+            LiveEvent::Rejected { .. } => "rejected",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LiveError {
+    // No session is attached for the user
+    NotAttached,
+    // The edit was undone as it couldn't be written
+    Storage(StorageError),
+}
+
+impl fmt::Display for LiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiveError::NotAttached => write!(f, "No live session for this user"),
+            LiveError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum LiveCommand {
+    Start,
+    Pause,
+    Resume,
+    Skip,
+    Finish,
+    Extend {
+        minutes: u64,
+    },
+    Interrupt {
+        kind: InterruptionKind,
+        minutes: u64,
+        note: Option<String>,
+    },
+}
+
+// Change to the history of a running session, e.g. a
+// record logged by hand
+type HistoryEdit = Box<dyn FnOnce(&mut Scheduler) + Send>;
+
+enum LiveMessage {
+    Command(LiveCommand),
+    // Replied to once the history is written
+    History(HistoryEdit, oneshot::Sender<Result<(), StorageError>>),
+    // Read only access, nothing is persisted
+    Inspect(HistoryEdit),
+}
+
+struct LiveHandle {
+    commands: mpsc::Sender<LiveMessage>,
+    events: broadcast::Sender<LiveEvent>,
+}
+
+// Running sessions of every user, each one is driven by
+// its own task that ticks the session engine
+#[derive(Default)]
+pub struct LiveSessions {
+    handles: Mutex<HashMap<Uuid, LiveHandle>>,
+}
+
+impl LiveSessions {
+    // Leaves a session already attached for the user alone,
+    // returns whether this one was attached
+    pub async fn attach<S>(&self, user_id: Uuid, session: Session<S>) -> bool
+    where
+        S: Storable<SessionState> + Storable<TaskRecord> + Send + 'static,
+    {
+        let mut handles = self.handles.lock().await;
+        if handles.contains_key(&user_id) {
+            return false;
+        }
+
+        let (commands, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+
+        tokio::spawn(drive(session, receiver, events.clone()));
+        handles.insert(user_id, LiveHandle { commands, events });
+
+        true
+    }
+
+    pub async fn detach(&self, user_id: Uuid) {
+        let mut handles = self.handles.lock().await;
+        handles.remove(&user_id);
+    }
+
+    pub async fn subscribe(&self, user_id: Uuid) -> Option<broadcast::Receiver<LiveEvent>> {
+        let handles = self.handles.lock().await;
+        handles.get(&user_id).map(|h| h.events.subscribe())
+    }
+
+    async fn send(&self, user_id: Uuid, message: LiveMessage) -> Result<(), ()> {
+        let commands = {
+            let handles = self.handles.lock().await;
+            handles
+                .get(&user_id)
+                .map(|h| h.commands.clone())
+                .ok_or(())?
+        };
+
+        commands.send(message).await.map_err(|_| ())
+    }
+
+    // Errors when the user has no session attached
+    pub async fn command(&self, user_id: Uuid, command: LiveCommand) -> Result<(), ()> {
+        self.send(user_id, LiveMessage::Command(command)).await
+    }
+
+    // Runs `edit` on the scheduler of the user's session, the
+    // history is persisted and the schedule re-planned after
+    pub async fn edit_history<F, R>(&self, user_id: Uuid, edit: F) -> Result<R, LiveError>
+    where
+        F: FnOnce(&mut Scheduler) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (persisted, written) = oneshot::channel();
+
+        let result = self
+            .run(user_id, edit, move |edit| {
+                LiveMessage::History(edit, persisted)
+            })
+            .await
+            .map_err(|_| LiveError::NotAttached)?;
+
+        written
+            .await
+            .map_err(|_| LiveError::NotAttached)?
+            .map_err(LiveError::Storage)?;
+
+        Ok(result)
+    }
+
+    pub async fn inspect_history<F, R>(&self, user_id: Uuid, inspect: F) -> Result<R, ()>
+    where
+        F: FnOnce(&Scheduler) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(
+            user_id,
+            |scheduler| inspect(scheduler),
+            LiveMessage::Inspect,
+        )
+        .await
+    }
+
+    async fn run<F, R>(
+        &self,
+        user_id: Uuid,
+        f: F,
+        message: impl FnOnce(HistoryEdit) -> LiveMessage,
+    ) -> Result<R, ()>
+    where
+        F: FnOnce(&mut Scheduler) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        let edit: HistoryEdit = Box::new(move |scheduler| {
+            let _ = sender.send(f(scheduler));
+        });
+
+        self.send(user_id, message(edit)).await?;
+        receiver.await.map_err(|_| ())
+    }
+}
+
+// Client supplied, so it can be anything
+fn minutes(minutes: u64) -> Result<Duration, SessionError> {
+    minutes
+        .checked_mul(60)
+        .map(Duration::from_secs)
+        .ok_or(SessionError::InvalidDuration)
+}
+
+fn apply<S>(
+    session: &mut Session<S>,
+    command: LiveCommand,
+    now: DateTime<Utc>,
+) -> Result<SessionTransition, SessionError>
+where
+    S: Storable<SessionState> + Storable<TaskRecord>,
+{
+    match command {
+        LiveCommand::Start => session.start(now),
+        LiveCommand::Pause => session.pause(now),
+        LiveCommand::Resume => session.resume(now),
+        LiveCommand::Skip => session.skip(now),
+        LiveCommand::Finish => session.finish(now),
+        LiveCommand::Extend { minutes: by } => session.extend(minutes(by)?, now),
+        LiveCommand::Interrupt {
+            kind,
+            minutes: lost,
+            note,
+        } => session.interrupt(kind, minutes(lost)?, note, now),
+    }
+}
+
+fn publish<S>(
+    session: &Session<S>,
+    events: &broadcast::Sender<LiveEvent>,
+    transitions: Vec<SessionTransition>,
+    now: DateTime<Utc>,
+) where
+    S: Storable<SessionState> + Storable<TaskRecord>,
+{
+    let mut replanned = false;
+
+    for transition in transitions {
+        let event = match transition.event {
+            SessionEvent::Started if is_break(&transition.task) => LiveEvent::BreakStarted {
+                task: transition.task,
+                at: transition.at,
+            },
+            SessionEvent::Started => LiveEvent::BlockStarted {
+                task: transition.task,
+                at: transition.at,
+            },
+            SessionEvent::Completed | SessionEvent::Skipped | SessionEvent::FinishedEarly => {
+                replanned = true;
+                LiveEvent::BlockEnded {
+                    event: transition.event,
+                    task: transition.task,
+                    at: transition.at,
+                    record: transition.record,
+                }
+            }
+            event => LiveEvent::Changed {
+                event,
+                task: transition.task,
+                at: transition.at,
+            },
+        };
+
+        // Nobody listening is not an error
+        let _ = events.send(event);
+    }
+
+    if replanned {
+        let upcoming = session
+            .scheduler
+            .compute_tasks(&vec![], UPCOMING_PREVIEW, now);
+        let _ = events.send(LiveEvent::Replanned { upcoming });
+    }
+}
+
+fn is_break(task: &ScheduleTask) -> bool {
+    task.origin_group == "system/break" || task.origin_group == "system/minibreak"
+}
+
+// Storage can block, e.g. on file locks, so everything that
+// may write runs on a blocking thread. None if it panicked
+// and took the session with it
+async fn blocking<S, F, R>(session: Session<S>, f: F) -> Option<(Session<S>, R)>
+where
+    S: Storable<SessionState> + Storable<TaskRecord> + Send + 'static,
+    F: FnOnce(&mut Session<S>) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut session = session;
+        let result = f(&mut session);
+        (session, result)
+    })
+    .await
+    .ok()
+}
+
+async fn drive<S>(
+    mut session: Session<S>,
+    mut commands: mpsc::Receiver<LiveMessage>,
+    events: broadcast::Sender<LiveEvent>,
+) where
+    S: Storable<SessionState> + Storable<TaskRecord> + Send + 'static,
+{
+    let mut interval = tokio::time::interval(TICK_INTERVAL);
+    // Block (start, planned) the ending soon warning was sent for,
+    // extending a block makes it warn again
+    let mut warned: Option<(DateTime<Utc>, Duration)> = None;
+
+    loop {
+        tokio::select! {
+            message = commands.recv() => {
+                // Session was detached
+                let command = match message {
+                    Some(LiveMessage::Command(command)) => command,
+                    Some(LiveMessage::History(edit, persisted)) => {
+                        let Some((edited, result)) =
+                            blocking(session, |session| session.edit_history(edit)).await
+                        else {
+                            break;
+                        };
+                        session = edited;
+                        let _ = persisted.send(result);
+
+                        let upcoming = session.scheduler.compute_tasks(
+                            &vec![],
+                            UPCOMING_PREVIEW,
+                            Utc::now(),
+                        );
+                        let _ = events.send(LiveEvent::Replanned { upcoming });
+                        continue;
+                    }
+                    Some(LiveMessage::Inspect(inspect)) => {
+                        inspect(&mut session.scheduler);
+                        continue;
+                    }
+                    None => break,
+                };
+
+                let now = Utc::now();
+
+                let Some((applied, result)) =
+                    blocking(session, move |session| apply(session, command, now)).await
+                else {
+                    break;
+                };
+                session = applied;
+
+                match result {
+                    Ok(transition) => publish(&session, &events, vec![transition], now),
+                    Err(err) => {
+                        let _ = events.send(LiveEvent::Rejected {
+                            message: err.to_string(),
+                        });
+                    }
+                }
+            }
+            _ = interval.tick() => {
+                let now = Utc::now();
+
+                let Some((ticked, result)) =
+                    blocking(session, move |session| session.tick(now)).await
+                else {
+                    break;
+                };
+                session = ticked;
+
+                match result {
+                    Ok(transitions) => publish(&session, &events, transitions, now),
+                    Err(err) => {
+                        let _ = events.send(LiveEvent::Rejected {
+                            message: err.to_string(),
+                        });
+                    }
+                }
+
+                let SessionState::Running(block) = &session.state else {
+                    continue;
+                };
+
+                let remaining = session.state.remaining(now).unwrap_or_default();
+                let task = block.task.clone();
+
+                if remaining <= ENDING_SOON && warned != Some((block.started, block.planned)) {
+                    warned = Some((block.started, block.planned));
+                    let _ = events.send(LiveEvent::EndingSoon {
+                        task: task.clone(),
+                        remaining,
+                    });
+                }
+
+                let _ = events.send(LiveEvent::Tick { task, remaining });
+            }
+        }
+    }
+}
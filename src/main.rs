@@ -1,18 +1,91 @@
 use std::sync::Arc;
 
 use poem::{EndpointExt, Route, Server, listener::TcpListener, middleware::AddData};
-use scheduler::{AppState, cache::local::LocalStorage, database::sqlite::Sqlite};
+use scheduler::{
+    AppState,
+    cache::local::LocalStorage,
+    database::{
+        config::DatabaseConfiguration, migration, postgres::Postgres, seed, sqlite::Sqlite,
+    },
+};
 
 extern crate scheduler;
 
+const USAGE: &str =
+    "Usage: scheduler [serve | migrate status | migrate up | migrate down [version] | seed]";
+
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
-    let state = Arc::new(AppState::connect::<Sqlite, LocalStorage>().await);
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let config = DatabaseConfiguration::from_env();
+    if config.is_in_memory() {
+        // Whatever the other commands change is gone once
+        // they exit
+        if !matches!(args.as_slice(), [] | ["serve"]) {
+            return Err(std::io::Error::other(
+                "The database is in memory, set SCHEDULER_DATABASE to a file or URL to migrate or seed it",
+            ));
+        }
+
+        eprintln!("Warning: using an in-memory database, nothing is kept once the server stops");
+    }
+    let state = if config.is_postgres() {
+        AppState::connect::<Postgres, LocalStorage>(&config)
+            .await
+            .expect("Failed to connect postgres database.")
+    } else {
+        AppState::connect::<Sqlite, LocalStorage>(&config)
+            .await
+            .expect("Failed to connect sqlite database.")
+    };
+    // Directory of hand edited task files, see `AppState`
+    let state = match std::env::var("SCHEDULER_TASK_FILES") {
+        Ok(dir) => state.with_task_files(dir),
+        Err(_) => state,
+    };
+    let state = Arc::new(state);
+
+    match args.as_slice() {
+        [] | ["serve"] => serve(state).await,
+        ["migrate", "status"] => status(&state).await,
+        ["migrate", "up"] => up(&state).await,
+        ["migrate", "down"] => down(&state, None).await,
+        ["migrate", "down", version] => match version.parse() {
+            Ok(version) => down(&state, Some(version)).await,
+            Err(_) => usage(),
+        },
+        ["seed"] => seed(&state).await,
+        _ => usage(),
+    }
+}
+
+fn usage() -> Result<(), std::io::Error> {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
 
-    state.database.migrate().await.unwrap();
+fn fail(action: &str, err: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::other(format!("Failed to {}: {}", action, err))
+}
+
+async fn serve(state: Arc<AppState>) -> Result<(), std::io::Error> {
+    state
+        .database
+        .migrate()
+        .await
+        .map_err(|e| fail("migrate database", e))?;
 
     let app = Route::new()
+        .nest("/audit", scheduler::server::audit::route())
         .nest("/auth", scheduler::server::auth::route())
+        .nest("/live", scheduler::server::live::route())
+        .nest("/records", scheduler::server::records::route())
+        .nest("/tasks", scheduler::server::tasks::route())
+        .nest("/webhooks", scheduler::server::webhooks::route())
         .with(AddData::new(state));
 
     Server::new(TcpListener::bind("0.0.0.0:8080"))
@@ -20,6 +93,98 @@ async fn main() -> Result<(), std::io::Error> {
         .await
 }
 
+async fn status(state: &AppState) -> Result<(), std::io::Error> {
+    let migrations = state
+        .database
+        .migration_status()
+        .await
+        .map_err(|e| fail("read migrations", e))?;
+
+    for migration in migrations {
+        println!(
+            "{} {:<12} {}{}",
+            migration.version,
+            migration.description,
+            if migration.applied {
+                "applied"
+            } else {
+                "pending"
+            },
+            if migration.reversible {
+                ""
+            } else {
+                " (irreversible)"
+            },
+        );
+    }
+
+    Ok(())
+}
+
+async fn up(state: &AppState) -> Result<(), std::io::Error> {
+    state
+        .database
+        .migrate()
+        .await
+        .map_err(|e| fail("migrate database", e))?;
+
+    status(state).await
+}
+
+// Without a version only the latest applied migration is
+// reverted
+async fn down(state: &AppState, version: Option<i64>) -> Result<(), std::io::Error> {
+    let version = match version {
+        Some(version) => version,
+        None => {
+            let migrations = state
+                .database
+                .migration_status()
+                .await
+                .map_err(|e| fail("read migrations", e))?;
+
+            match migration::previous_version(&migrations) {
+                Some(version) => version,
+                None => {
+                    println!("Nothing to revert");
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    state
+        .database
+        .revert(version)
+        .await
+        .map_err(|e| fail("revert migrations", e))?;
+
+    status(state).await
+}
+
+async fn seed(state: &AppState) -> Result<(), std::io::Error> {
+    state
+        .database
+        .migrate()
+        .await
+        .map_err(|e| fail("migrate database", e))?;
+
+    match seed::seed(&*state.database)
+        .await
+        .map_err(|e| fail("seed database", e))?
+    {
+        Some(user) => println!(
+            "Added user {} ({} / {})",
+            user.id,
+            seed::DEMO_USERNAME,
+            seed::DEMO_PASSWORD
+        ),
+        None => println!("Already seeded"),
+    }
+
+    Ok(())
+}
+
 //
 // use scheduler::schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler};
 // use scheduler::storage::{FileStorage, Storable};
@@ -41,11 +206,11 @@ async fn main() -> Result<(), std::io::Error> {
 // }
 
 // fn main() {
-//     let storage = FileStorage::new("../tasks.json", "../history.json", "../task-ratio.json");
+//     let storage = FileStorage::new("../tasks.json", "../history.json", "../task-ratio.json", "../session.json");
 //
-//     let tasks: Vec<Task> = storage.get();
-//     let history: Vec<TaskRecord> = storage.get();
-//     let ratioed_tasks = ExpectedRatioTasks::read(&storage, tasks);
+//     let tasks: Vec<Task> = storage.get().unwrap();
+//     let history: Vec<TaskRecord> = storage.get().unwrap();
+//     let ratioed_tasks = ExpectedRatioTasks::read(&storage, tasks).unwrap();
 //
 //     let mut scheduler = Scheduler::new(ratioed_tasks, history, ScheduleConfiguration::default());
 //
@@ -63,7 +228,7 @@ async fn main() -> Result<(), std::io::Error> {
 //                 since_last_start = Instant::now();
 //                 scheduler.feed_record(TaskRecord::from(current_task));
 //
-//                 storage.store(&scheduler.task_history);
+//                 storage.store(scheduler.history()).unwrap();
 //
 //                 current_task = ScheduleTask {
 //                     origin_name: String::from("Transition"),
@@ -1,7 +1,21 @@
-use crate::storage::Storable;
-use crate::task::{ScheduleTask, Task, TaskRecord};
+use crate::estimation::{Estimator, TaskEstimate};
+use crate::explanation::{
+    BreakKind, BreakTrigger, CandidateCost, ExcludedTask, ExclusionReason, ScheduleExplanation,
+};
+use crate::goal::{Goal, GoalProgress};
+use crate::interruption::InterruptionReport;
+use crate::storage::{Storable, StorageError};
+use crate::task::{RecordError, ScheduleTask, Task, TaskRecord};
+use chrono::{DateTime, TimeDelta, Utc};
 use derivative::Derivative;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
 use std::time::Duration;
+use uuid::Uuid;
+
+// Costs closer than this are considered a tie
+const COST_TIE_EPSILON: f32 = 1e-6;
 
 #[derive(Derivative)]
 #[derivative(Debug, Clone, Default)]
@@ -18,6 +32,44 @@ pub struct ScheduleConfiguration {
 
     #[derivative(Default(value = "3"))]
     pub transitiontime: u64,
+
+    // Attach a ScheduleExplanation to every computed task
+    pub explain: bool,
+
+    // How much being behind on a goal lowers a task's cost
+    #[derivative(Default(value = "0.25"))]
+    pub goal_weight: f32,
+
+    // When set, candidates are sampled instead of always
+    // taking the cheapest one
+    pub sampling: Option<SamplingConfiguration>,
+}
+
+// Softmax sampling over candidate costs. The same seed and
+// history always produce the same schedule, so changing the
+// seed (e.g. using the date) is what makes schedules vary
+#[derive(Debug, Clone)]
+pub struct SamplingConfiguration {
+    pub seed: u64,
+    // Higher values flatten the distribution, values at or
+    // below zero behave like the deterministic mode
+    pub temperature: f32,
+}
+
+// SplitMix64 finalizer, spreads nearby inputs far apart
+fn split_mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+// Seed of the stream for one position of the schedule. Plain
+// addition would make seed N at position p + 1 the same as
+// seed N + 1 at position p, so consecutive (e.g. date based)
+// seeds would give shifted copies of each other
+fn stream_seed(seed: u64, position: u64) -> u64 {
+    split_mix(split_mix(seed) ^ position)
 }
 
 #[derive(Debug, Clone, Default)]
@@ -35,7 +87,39 @@ impl ExpectedRatioTasks {
         Ok(Self(tasks))
     }
 
-    pub fn write<P>(&self, storage: &P)
+    // Changes the ratio of a single task, scaling every
+    // other task so the total stays at 1.0
+    pub fn with_ratio(&self, name: &str, group: &str, ratio: f32) -> Result<Self, ()> {
+        let current = self
+            .0
+            .iter()
+            .find(|(t, _)| t.name == name && t.group == group)
+            .map(|(_, r)| *r)
+            .ok_or(())?;
+
+        let rest = 1.0 - current;
+        let scale = if rest > 0.0 {
+            (1.0 - ratio) / rest
+        } else {
+            0.0
+        };
+
+        let tasks = self
+            .0
+            .iter()
+            .map(|(t, r)| {
+                if t.name == name && t.group == group {
+                    (t.clone(), ratio)
+                } else {
+                    (t.clone(), r * scale)
+                }
+            })
+            .collect();
+
+        Self::new(tasks)
+    }
+
+    pub fn write<P>(&self, storage: &P) -> Result<(), StorageError>
     where
         P: Storable<((String, String), f32)>,
     {
@@ -45,14 +129,26 @@ impl ExpectedRatioTasks {
             .map(|v| ((v.0.name.clone(), v.0.group.clone()), v.1))
             .collect();
 
-        storage.store(&data);
+        storage.store(&data)
     }
 
-    pub fn read<P>(storage: &P, tasks: Vec<Task>) -> Self
+    pub fn read<P>(storage: &P, tasks: Vec<Task>) -> Result<Self, StorageError>
     where
         P: Storable<((String, String), f32)>,
     {
-        let data: Vec<((String, String), f32)> = storage.get();
+        let data: Vec<((String, String), f32)> = storage.get()?;
+        Self::from_ratios(tasks, &data)
+    }
+
+    // Tasks without a ratio get a share taken from the last
+    // task that has one
+    pub fn from_ratios(
+        tasks: Vec<Task>,
+        data: &[((String, String), f32)],
+    ) -> Result<Self, StorageError> {
+        if tasks.is_empty() {
+            return Ok(Self::default());
+        }
 
         let mut output: Vec<(Task, f32)> = Vec::with_capacity(tasks.len());
         let mut used_tasks: Vec<Task> = Vec::with_capacity(tasks.len());
@@ -68,12 +164,30 @@ impl ExpectedRatioTasks {
             }
         }
 
-        for task in tasks.iter().filter(|v| {
-            used_tasks
-                .iter()
-                .find(|v2| (v.name.clone(), v.group.clone()) == (v2.name.clone(), v2.group.clone()))
-                .is_none()
-        }) {
+        let unused_tasks: Vec<&Task> = tasks
+            .iter()
+            .filter(|v| {
+                used_tasks
+                    .iter()
+                    .find(|v2| {
+                        (v.name.clone(), v.group.clone()) == (v2.name.clone(), v2.group.clone())
+                    })
+                    .is_none()
+            })
+            .collect();
+
+        // Without any stored ratios every task gets the same share
+        if output.is_empty() {
+            let share = 1.0 / unused_tasks.len() as f32;
+            let output = unused_tasks
+                .into_iter()
+                .map(|t| (t.clone(), share))
+                .collect();
+
+            return Self::new(output).map_err(|_| StorageError::InvalidRatios);
+        }
+
+        for task in unused_tasks {
             let index = output.len() - 1;
             let last_f32 = output.get_mut(index).unwrap().1;
             output.push((task.clone(), last_f32 / 2.0));
@@ -81,15 +195,16 @@ impl ExpectedRatioTasks {
             last.1 /= 2.0;
         }
 
-        Self::new(output).unwrap()
+        Self::new(output).map_err(|_| StorageError::InvalidRatios)
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Scheduler {
-    pub task_history: Vec<TaskRecord>,
+    task_history: Vec<TaskRecord>,
     pub config: ScheduleConfiguration,
     pub tasks: ExpectedRatioTasks,
+    pub goals: Vec<Goal>,
 }
 
 impl Scheduler {
@@ -106,10 +221,200 @@ impl Scheduler {
         }
     }
 
+    pub fn with_goals(mut self, goals: Vec<Goal>) -> Self {
+        self.goals = goals;
+        self
+    }
+
+    // Picks up tasks and ratios edited outside of the
+    // scheduler, history is left alone
+    pub fn reload_tasks<P>(&mut self, storage: &P) -> Result<(), StorageError>
+    where
+        P: Storable<Task> + Storable<((String, String), f32)>,
+    {
+        let tasks: Vec<Task> = storage.get()?;
+        self.tasks = ExpectedRatioTasks::read(storage, tasks)?;
+        Ok(())
+    }
+
+    pub fn goal_progress(&self, now: DateTime<Utc>) -> Vec<(Goal, GoalProgress)> {
+        self.goals
+            .iter()
+            .map(|goal| (goal.clone(), goal.progress(&self.task_history, now)))
+            .collect()
+    }
+
+    // Goals that are behind pace make their task cheaper to pick
+    fn goal_boost(&self, task: &Task, now: DateTime<Utc>) -> f32 {
+        let behind = self
+            .goals
+            .iter()
+            .filter(|goal| goal.is_for(&task.name, &task.group) && goal.deadline > now)
+            .map(|goal| goal.progress(&self.task_history, now).behind())
+            .fold(0.0, f32::max);
+
+        behind * self.config.goal_weight
+    }
+
     pub fn feed_record(&mut self, record: TaskRecord) {
         self.task_history.push(record);
     }
 
+    pub fn history(&self) -> &[TaskRecord] {
+        &self.task_history
+    }
+
+    pub(crate) fn replace_history(&mut self, history: Vec<TaskRecord>) -> Vec<TaskRecord> {
+        std::mem::replace(&mut self.task_history, history)
+    }
+
+    fn record_index(&self, id: Uuid) -> Result<usize, RecordError> {
+        self.task_history
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or(RecordError::NotFound(id))
+    }
+
+    // Breaks are worked out from the order of the history, so
+    // records with a finish time go before the first record
+    // that finished after them
+    fn insert_record(&mut self, record: TaskRecord) {
+        let index = record
+            .finished
+            .and_then(|finished| {
+                self.task_history
+                    .iter()
+                    .position(|r| r.finished.is_some_and(|f| f > finished))
+            })
+            .unwrap_or(self.task_history.len());
+
+        self.task_history.insert(index, record);
+    }
+
+    // Adds time that was not spent inside a generated block
+    pub fn log_record(&mut self, record: TaskRecord) -> Uuid {
+        let id = record.id;
+        self.insert_record(record);
+        id
+    }
+
+    // Replaces a past record, the id is kept
+    pub fn update_record(&mut self, id: Uuid, mut record: TaskRecord) -> Result<(), RecordError> {
+        let index = self.record_index(id)?;
+        self.task_history.remove(index);
+
+        record.id = id;
+        self.insert_record(record);
+        Ok(())
+    }
+
+    pub fn delete_record(&mut self, id: Uuid) -> Result<TaskRecord, RecordError> {
+        let index = self.record_index(id)?;
+        Ok(self.task_history.remove(index))
+    }
+
+    // Splits a record `at` into it, returning the ids of
+    // both halves
+    pub fn split_record(&mut self, id: Uuid, at: Duration) -> Result<(Uuid, Uuid), RecordError> {
+        let index = self.record_index(id)?;
+        let record = &mut self.task_history[index];
+
+        if at.is_zero() || at >= record.time {
+            return Err(RecordError::InvalidSplit);
+        }
+
+        let split_point = record
+            .started
+            .zip(TimeDelta::from_std(at).ok())
+            .map(|(started, delta)| started + delta);
+
+        // Without a start there is no telling where they
+        // happened, they stay with the first half
+        let (before, after) = record
+            .interruptions
+            .drain(..)
+            .partition(|i| split_point.is_none_or(|point| i.at < point));
+
+        let mut second = record.clone();
+        second.id = Uuid::new_v4();
+        second.time = record.time - at;
+        second.planned = record.planned.map(|p| p.saturating_sub(at));
+        second.started = split_point;
+        second.interruptions = after;
+
+        record.interruptions = before;
+
+        record.time = at;
+        record.planned = record.planned.map(|p| p.min(at));
+        record.finished = split_point.or(record.finished);
+
+        let second_id = second.id;
+        self.task_history.insert(index + 1, second);
+
+        Ok((id, second_id))
+    }
+
+    // Merges `second` into `first`, both have to be records
+    // of the same task
+    pub fn merge_records(&mut self, first: Uuid, second: Uuid) -> Result<Uuid, RecordError> {
+        if first == second {
+            return Err(RecordError::SameRecord);
+        }
+
+        let first_index = self.record_index(first)?;
+        let second_index = self.record_index(second)?;
+
+        let other = self.task_history[second_index].clone();
+        let record = &self.task_history[first_index];
+
+        if record.origin_name != other.origin_name || record.origin_group != other.origin_group {
+            return Err(RecordError::DifferentTasks);
+        }
+
+        let mut merged = record.clone();
+        merged.time += other.time;
+        merged.planned = match (merged.planned, other.planned) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        merged.started = match (merged.started, other.started) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        merged.finished = match (merged.finished, other.finished) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        merged.interruptions.extend(other.interruptions);
+        merged.interruptions.sort_by_key(|i| i.at);
+
+        self.task_history
+            .retain(|r| r.id != first && r.id != second);
+        self.insert_record(merged);
+
+        Ok(first)
+    }
+
+    pub fn interruption_report(&self) -> InterruptionReport {
+        InterruptionReport::from_history(&self.task_history)
+    }
+
+    pub fn estimator(&self) -> Estimator<'_> {
+        Estimator::new(&self.task_history)
+    }
+
+    // Estimates for every open task that has an effort target
+    pub fn estimates(&self, now: DateTime<Utc>) -> Vec<TaskEstimate> {
+        let estimator = self.estimator();
+
+        self.tasks
+            .0
+            .iter()
+            .filter(|(task, _)| task.closed.is_none())
+            .filter_map(|(task, _)| estimator.task_estimate(task, now))
+            .collect()
+    }
+
     fn compute_history_ratio_tasks(
         &self,
         future_factor: Vec<TaskRecord>,
@@ -183,7 +488,10 @@ impl Scheduler {
         return cost;
     }
 
-    fn compute_break(&self, virtual_history: &Vec<TaskRecord>) -> Option<ScheduleTask> {
+    fn compute_break(
+        &self,
+        virtual_history: &Vec<TaskRecord>,
+    ) -> Option<(ScheduleTask, BreakTrigger)> {
         let mut total_history = self.task_history.clone();
         total_history.append(&mut virtual_history.clone());
 
@@ -206,57 +514,277 @@ impl Scheduler {
 
         println!("{:?}", since_last_break);
 
-        if since_last_break >= Duration::from_secs(self.config.break_frequency * 60) {
-            Some(ScheduleTask {
-                origin_name: String::from("Break"),
-                origin_group: String::from("system/break"),
-                time: Duration::from_secs(self.config.breaktime * 60),
-            })
-        } else if since_last_minibreak >= Duration::from_secs(self.config.minibreak_frequency * 60)
-        {
-            Some(ScheduleTask {
-                origin_name: String::from("Minibreak"),
-                origin_group: String::from("system/minibreak"),
-                time: Duration::from_secs(self.config.minibreaktime * 60),
-            })
+        let break_frequency = Duration::from_secs(self.config.break_frequency * 60);
+        let minibreak_frequency = Duration::from_secs(self.config.minibreak_frequency * 60);
+
+        if since_last_break >= break_frequency {
+            Some((
+                ScheduleTask {
+                    origin_name: String::from("Break"),
+                    origin_group: String::from("system/break"),
+                    time: Duration::from_secs(self.config.breaktime * 60),
+                    explanation: None,
+                },
+                BreakTrigger {
+                    kind: BreakKind::Break,
+                    elapsed: since_last_break,
+                    frequency: break_frequency,
+                },
+            ))
+        } else if since_last_minibreak >= minibreak_frequency {
+            Some((
+                ScheduleTask {
+                    origin_name: String::from("Minibreak"),
+                    origin_group: String::from("system/minibreak"),
+                    time: Duration::from_secs(self.config.minibreaktime * 60),
+                    explanation: None,
+                },
+                BreakTrigger {
+                    kind: BreakKind::Minibreak,
+                    elapsed: since_last_minibreak,
+                    frequency: minibreak_frequency,
+                },
+            ))
         } else {
             None
         }
     }
 
-    pub fn compute_task(&self, virtual_history: &Vec<TaskRecord>) -> ScheduleTask {
+    // Why `task` can't be scheduled at `now`, None when it is
+    // a candidate
+    fn exclusion_reason(
+        &self,
+        task: &Task,
+        ratio: f32,
+        virtual_history: &[TaskRecord],
+        now: DateTime<Utc>,
+    ) -> Option<ExclusionReason> {
+        if task.closed.is_some_and(|closed| closed <= now) {
+            return Some(ExclusionReason::Closed);
+        }
+
+        if ratio <= 0.0 {
+            return Some(ExclusionReason::NoRatio);
+        }
+
+        if task.config.time.is_zero() {
+            return Some(ExclusionReason::NoTime);
+        }
+
+        let done = self
+            .task_history
+            .iter()
+            .chain(virtual_history.iter())
+            .any(|r| r.origin_name == task.name && r.origin_group == task.group);
+
+        if !task.config.repeat && done {
+            return Some(ExclusionReason::Completed);
+        }
+
+        None
+    }
+
+    // None when there are no tasks to pick from. Goals are
+    // measured at `now`, the wall clock is never read so the
+    // same inputs always give the same task
+    pub fn compute_task(
+        &self,
+        virtual_history: &Vec<TaskRecord>,
+        now: DateTime<Utc>,
+    ) -> Option<ScheduleTask> {
         let break_task = self.compute_break(&virtual_history);
 
-        if let Some(break_schedule) = break_task {
-            return break_schedule;
+        if let Some((mut break_schedule, trigger)) = break_task {
+            if self.config.explain {
+                // A due break always wins, no task is considered
+                let excluded = self
+                    .tasks
+                    .0
+                    .iter()
+                    .map(|(task, _)| ExcludedTask {
+                        name: task.name.clone(),
+                        group: task.group.clone(),
+                        reason: ExclusionReason::BreakDue,
+                    })
+                    .collect();
+
+                break_schedule.explanation = Some(ScheduleExplanation {
+                    break_trigger: Some(trigger),
+                    excluded,
+                    ..Default::default()
+                });
+            }
+
+            return Some(break_schedule);
         }
 
-        let mut lowest_task = TaskRecord::default();
-        let mut previous_cost = f32::MAX;
+        let mut future_tasks = Vec::with_capacity(self.tasks.0.len());
+        let mut candidates = Vec::with_capacity(self.tasks.0.len());
+        let mut excluded = Vec::new();
+
+        for (task, ratio) in self.tasks.0.iter() {
+            if let Some(reason) = self.exclusion_reason(task, *ratio, virtual_history, now) {
+                excluded.push(ExcludedTask {
+                    name: task.name.clone(),
+                    group: task.group.clone(),
+                    reason,
+                });
+                continue;
+            }
 
-        for (task, _) in self.tasks.0.iter() {
             let future_task = TaskRecord {
                 origin_name: task.name.clone(),
                 origin_group: task.group.clone(),
                 time: task.config.time.clone(),
+                ..Default::default()
             };
 
             let mut future_factor = vec![future_task.clone()];
             future_factor.append(&mut virtual_history.clone());
 
             let history = self.compute_history_ratio_tasks(future_factor);
-            let cost = self.compute_cost(history);
-
-            if previous_cost > cost {
-                lowest_task = future_task;
-                previous_cost = cost;
-            }
+            let cost = self.compute_cost(history) - self.goal_boost(task, now);
+
+            candidates.push(CandidateCost {
+                name: task.name.clone(),
+                group: task.group.clone(),
+                cost,
+            });
+            future_tasks.push(future_task);
         }
 
-        ScheduleTask {
+        let index = self.select_candidate(&candidates, virtual_history)?;
+        let lowest_task = future_tasks.swap_remove(index);
+
+        let explanation = if self.config.explain {
+            Some(self.explain(&lowest_task, virtual_history, candidates, excluded))
+        } else {
+            None
+        };
+
+        Some(ScheduleTask {
             origin_name: lowest_task.origin_name.clone(),
             origin_group: lowest_task.origin_group.clone(),
             time: lowest_task.time,
+            explanation,
+        })
+    }
+
+    fn target_ratio(&self, name: &str, group: &str) -> f32 {
+        self.tasks
+            .0
+            .iter()
+            .find(|(t, _)| t.name == name && t.group == group)
+            .map(|(_, ratio)| *ratio)
+            .unwrap_or(0.0)
+    }
+
+    fn select_candidate(
+        &self,
+        candidates: &[CandidateCost],
+        virtual_history: &[TaskRecord],
+    ) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        if let Some(sampling) = &self.config.sampling
+            && sampling.temperature > 0.0
+        {
+            return Some(self.sample_candidate(sampling, candidates, virtual_history));
+        }
+
+        let lowest_cost = candidates
+            .iter()
+            .fold(f32::MAX, |acc, c| if c.cost < acc { c.cost } else { acc });
+
+        // Ties go to the task furthest below its target ratio and
+        // then to the identity, so the order of the tasks list
+        // never decides anything
+        let ratios = self.compute_history_ratio_tasks(virtual_history.to_vec());
+        let deficit = |candidate: &CandidateCost| {
+            let identity = (candidate.name.clone(), candidate.group.clone());
+
+            let current = ratios
+                .iter()
+                .find(|r| r.0 == identity)
+                .map(|r| r.1)
+                .unwrap_or(0.0);
+
+            self.target_ratio(&candidate.name, &candidate.group) - current
+        };
+
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.cost - lowest_cost <= COST_TIE_EPSILON)
+            .min_by(|(_, a), (_, b)| {
+                deficit(b)
+                    .partial_cmp(&deficit(a))
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| a.group.cmp(&b.group))
+                    .then_with(|| a.name.cmp(&b.name))
+            })
+            .map(|(index, _)| index)
+    }
+
+    fn sample_candidate(
+        &self,
+        sampling: &SamplingConfiguration,
+        candidates: &[CandidateCost],
+        virtual_history: &[TaskRecord],
+    ) -> usize {
+        // Every position in the schedule gets its own stream so
+        // computing one task never shifts the ones after it
+        let position = (self.task_history.len() + virtual_history.len()) as u64;
+        let mut rng = StdRng::seed_from_u64(stream_seed(sampling.seed, position));
+
+        let lowest_cost = candidates
+            .iter()
+            .fold(f32::MAX, |acc, c| if c.cost < acc { c.cost } else { acc });
+
+        let weights: Vec<f32> = candidates
+            .iter()
+            .map(|c| (-(c.cost - lowest_cost) / sampling.temperature).exp())
+            .collect();
+
+        let total: f32 = weights.iter().sum();
+        let mut roll = rng.random_range(0.0..total);
+
+        for (index, weight) in weights.iter().enumerate() {
+            if roll < *weight {
+                return index;
+            }
+            roll -= weight;
+        }
+
+        candidates.len() - 1
+    }
+
+    fn explain(
+        &self,
+        chosen: &TaskRecord,
+        virtual_history: &[TaskRecord],
+        candidates: Vec<CandidateCost>,
+        excluded: Vec<ExcludedTask>,
+    ) -> ScheduleExplanation {
+        let identity = (chosen.origin_name.clone(), chosen.origin_group.clone());
+
+        let current_ratio = self
+            .compute_history_ratio_tasks(virtual_history.to_vec())
+            .iter()
+            .find(|r| r.0 == identity)
+            .map(|r| r.1)
+            .unwrap_or(0.0);
+
+        let target_ratio = self.target_ratio(&chosen.origin_name, &chosen.origin_group);
+
+        ScheduleExplanation {
+            candidates,
+            current_ratio,
+            target_ratio,
+            break_trigger: None,
+            excluded,
         }
     }
 
@@ -264,6 +792,7 @@ impl Scheduler {
         &self,
         virtual_history: &Vec<TaskRecord>,
         limit: usize,
+        now: DateTime<Utc>,
     ) -> Vec<ScheduleTask> {
         let mut future_schedule = Vec::with_capacity(limit);
 
@@ -279,7 +808,10 @@ impl Scheduler {
 
             temp_virtual_history.append(&mut future_history);
 
-            future_schedule.push(self.compute_task(&temp_virtual_history));
+            match self.compute_task(&temp_virtual_history, now) {
+                Some(task) => future_schedule.push(task),
+                None => break,
+            }
         }
 
         future_schedule
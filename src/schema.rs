@@ -0,0 +1,89 @@
+use crate::storage::StorageError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Files written before versioning are treated as this
+// version, every upgrade moves a collection one up
+pub const FIRST_VERSION: u32 = 1;
+
+// Turns the entries of one version into the next, working
+// on plain JSON so old shapes don't need their own types
+pub type Upgrade = fn(Value) -> Result<Value, StorageError>;
+
+#[derive(Serialize)]
+pub(crate) struct Envelope<'a, T> {
+    pub version: u32,
+    pub entries: &'a [T],
+}
+
+// First line of JSON Lines files and the history log,
+// nothing but the version so it can't be mistaken for an
+// entry
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Header {
+    pub version: u32,
+}
+
+// Version if the line is a header
+pub(crate) fn header(line: &str) -> Option<u32> {
+    serde_json::from_str::<Header>(line)
+        .ok()
+        .map(|header| header.version)
+}
+
+pub(crate) fn header_line(version: u32) -> Result<Vec<u8>, StorageError> {
+    let mut line = serde_json::to_vec(&Header { version })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+// Leading comment of CSV files
+pub(crate) fn csv_header(version: u32) -> String {
+    format!("# version {}\n", version)
+}
+
+pub(crate) fn csv_version(line: &str) -> Option<u32> {
+    line.trim().strip_prefix("# version ")?.parse().ok()
+}
+
+pub fn current_version(upgrades: &[Upgrade]) -> u32 {
+    FIRST_VERSION + upgrades.len() as u32
+}
+
+// Version and entries of a file, none for files that are
+// still a bare list or lack a version
+pub(crate) fn open(value: Value) -> (Option<u32>, Value) {
+    match value {
+        Value::Object(mut document) if document.contains_key("entries") => {
+            let version = document
+                .get("version")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as u32);
+
+            (version, document.remove("entries").unwrap_or_default())
+        }
+        value => (None, value),
+    }
+}
+
+// Runs every upgrade after `version`
+pub(crate) fn upgrade(
+    mut entries: Value,
+    version: u32,
+    upgrades: &[Upgrade],
+) -> Result<Value, StorageError> {
+    let current = current_version(upgrades);
+
+    if version > current {
+        return Err(StorageError::UnsupportedVersion(version, current));
+    }
+
+    let first = version.saturating_sub(FIRST_VERSION) as usize;
+
+    for upgrade in &upgrades[first..] {
+        entries = upgrade(entries)?;
+    }
+
+    Ok(entries)
+}
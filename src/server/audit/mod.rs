@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use poem::{
+    IntoResponse, Route, get, handler,
+    http::StatusCode,
+    web::{Data, Json, Query},
+};
+use serde::Deserialize;
+
+use crate::{AppState, server::auth::AuthenticatedUser};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<u32>,
+}
+
+// The signed in user's own trail, newest first
+#[handler]
+async fn list_audit(
+    user: AuthenticatedUser,
+    query: Query<AuditQuery>,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let entries = state
+        .database
+        .get_user_audit(user.0, limit)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to get audit log: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    Ok(Json(entries))
+}
+
+pub fn route() -> Route {
+    Route::new().at("/", get(list_audit))
+}
@@ -1,17 +1,18 @@
 use std::sync::Arc;
 
 use poem::{
-    IntoResponse, Route, handler,
-    http::StatusCode,
+    FromRequest, IntoResponse, Request, RequestBody, Route, handler,
+    http::{StatusCode, header},
     post,
     web::{Data, Json},
 };
 use serde::Deserialize;
 use serde_json::json;
+use uuid::Uuid;
 
 use crate::{
     AppState,
-    database::data::{Credentials, User},
+    database::data::{AuditContext, Credentials, User},
 };
 
 #[derive(Deserialize)]
@@ -29,12 +30,68 @@ pub struct LoginRequestData {
     pub password: String,
 }
 
+// Extracts the user of the session id sent either as a
+// bearer token or as a `session_id` query parameter, the
+// latter is for clients like EventSource that can't set headers
+pub struct AuthenticatedUser(pub Uuid);
+
+impl<'a> FromRequest<'a> for AuthenticatedUser {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let state = req.data::<Arc<AppState>>().ok_or_else(|| {
+            poem::Error::from_string("Missing app state", StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+        let bearer = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(String::from);
+
+        let query = req.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("session_id="))
+                .map(String::from)
+        });
+
+        let session_id = bearer.or(query).ok_or_else(|| {
+            poem::Error::from_string("Missing session id", StatusCode::UNAUTHORIZED)
+        })?;
+
+        let user_id = state.session_user(&session_id).await.map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to authenticate: {}", e),
+                StatusCode::UNAUTHORIZED,
+            )
+        })?;
+
+        Ok(AuthenticatedUser(user_id))
+    }
+}
+
+// The address a request came from, the actor is up to the
+// handler since not every request is authenticated
+impl<'a> FromRequest<'a> for AuditContext {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        Ok(AuditContext {
+            actor: None,
+            ip: req
+                .remote_addr()
+                .as_socket_addr()
+                .map(|addr| addr.ip().to_string()),
+        })
+    }
+}
+
 #[handler]
 async fn signup(
     data: Json<SignupRequestData>,
+    context: AuditContext,
     state: Data<&Arc<AppState>>,
 ) -> poem::Result<impl IntoResponse> {
     let user = User::new(&data.name);
+    let context = context.with_actor(user.id);
     let creds = Credentials::new(user.id, &data.email, &data.username)
         .add_password_and_salt(data.password.as_bytes())
         .map_err(|e| {
@@ -44,12 +101,16 @@ async fn signup(
             )
         })?;
 
-    state.database.create_user(user, creds).await.map_err(|e| {
-        poem::Error::from_string(
-            format!("Failed to create user: {}", e),
-            StatusCode::INTERNAL_SERVER_ERROR,
-        )
-    })?;
+    state
+        .database
+        .create_user(user, creds, &context)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to create user: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
 
     Ok((StatusCode::CREATED, Json(json!({}))))
 }
@@ -57,10 +118,11 @@ async fn signup(
 #[handler]
 async fn login(
     data: Json<LoginRequestData>,
+    context: AuditContext,
     state: Data<&Arc<AppState>>,
 ) -> poem::Result<impl IntoResponse> {
     let session_id = state
-        .authenticate(&data.username, &data.email, &data.password)
+        .authenticate(&data.username, &data.email, &data.password, &context)
         .await
         .map_err(|e| {
             poem::Error::from_string(
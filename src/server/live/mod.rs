@@ -0,0 +1,231 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::{SinkExt, StreamExt, stream};
+use poem::{
+    IntoResponse, Route, get, handler,
+    http::StatusCode,
+    post,
+    web::{
+        Data, Json,
+        sse::{Event, SSE},
+        websocket::{Message, WebSocket},
+    },
+};
+use serde::Deserialize;
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+
+use crate::{
+    AppState,
+    database::storage::DatabaseStorage,
+    goal::Goal,
+    interruption::InterruptionKind,
+    live::{LiveCommand, LiveEvent},
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    server::auth::AuthenticatedUser,
+    session::Session,
+    storage::{FileStorage, Storable},
+    task::{Task, TaskRecord},
+    watch::reload_live,
+};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+async fn subscribe(
+    state: &AppState,
+    user: &AuthenticatedUser,
+) -> poem::Result<Receiver<LiveEvent>> {
+    state.live.subscribe(user.0).await.ok_or_else(|| {
+        poem::Error::from_string("No live session for this user", StatusCode::NOT_FOUND)
+    })
+}
+
+// Next event for the client, slow clients skip what
+// they missed instead of being disconnected
+async fn next_event(receiver: &mut Receiver<LiveEvent>) -> Option<LiveEvent> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[handler]
+async fn events(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let receiver = subscribe(&state, &user).await?;
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        let event = next_event(&mut receiver).await?;
+        let data = serde_json::to_string(&event).ok()?;
+
+        Some((Event::message(data), receiver))
+    });
+
+    Ok(SSE::new(stream).keep_alive(KEEP_ALIVE))
+}
+
+// Streams the same events as `/events` and accepts
+// LiveCommand messages to control the session
+#[handler]
+async fn socket(
+    ws: WebSocket,
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let mut receiver = subscribe(&state, &user).await?;
+    let state = state.0.clone();
+    let user_id = user.0;
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        let (mut sink, mut incoming) = socket.split();
+
+        tokio::spawn(async move {
+            while let Some(event) = next_event(&mut receiver).await {
+                let Ok(data) = serde_json::to_string(&event) else {
+                    continue;
+                };
+
+                if sink.send(Message::Text(data)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(Ok(message)) = incoming.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+
+            // Bad commands are ignored, the event stream tells
+            // the client whether a command did anything
+            if let Ok(command) = serde_json::from_str::<LiveCommand>(&text) {
+                let _ = state.live.command(user_id, command).await;
+            }
+        }
+    }))
+}
+
+// Builds the user's scheduler from the database and starts
+// driving it, a session that is already live is kept
+#[handler]
+async fn start_session(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    if state.live.subscribe(user.0).await.is_some() {
+        return Ok(StatusCode::OK);
+    }
+
+    let storage = DatabaseStorage::load(state.database.clone(), user.0)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to load schedule: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?
+        .with_audit(state.audit.clone());
+
+    let session = (|| {
+        let tasks: Vec<Task> = storage.get()?;
+        let history: Vec<TaskRecord> = storage.get()?;
+        let goals: Vec<Goal> = storage.get()?;
+        let tasks = ExpectedRatioTasks::read(&storage, tasks)?;
+
+        let scheduler =
+            Scheduler::new(tasks, history, ScheduleConfiguration::default()).with_goals(goals);
+        Session::new(scheduler, storage)
+    })()
+    .map_err(|e| {
+        poem::Error::from_string(
+            format!("Failed to start session: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+    })?;
+
+    // Another request may have started it in the meantime
+    if !state.attach_session(user.0, session).await {
+        return Ok(StatusCode::OK);
+    }
+
+    if let Some(dir) = &state.task_files {
+        let dir = dir.join(user.0.to_string());
+        let storage = FileStorage::new(
+            dir.join("tasks.json"),
+            dir.join("history.json"),
+            dir.join("task-ratio.json"),
+            dir.join("session.json"),
+        );
+        reload_live(state.clone(), user.0, storage);
+    }
+
+    Ok(StatusCode::CREATED)
+}
+
+#[handler]
+async fn stop_session(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    state.live.detach(user.0).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct InterruptionRequestData {
+    pub kind: InterruptionKind,
+    pub minutes: u64,
+    pub note: Option<String>,
+}
+
+#[handler]
+async fn log_interruption(
+    user: AuthenticatedUser,
+    data: Json<InterruptionRequestData>,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let command = LiveCommand::Interrupt {
+        kind: data.kind,
+        minutes: data.minutes,
+        note: data.0.note,
+    };
+
+    state.live.command(user.0, command).await.map_err(|_| {
+        poem::Error::from_string("No live session for this user", StatusCode::NOT_FOUND)
+    })?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[handler]
+async fn interruption_report(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let report = state
+        .live
+        .inspect_history(user.0, |scheduler| scheduler.interruption_report())
+        .await
+        .map_err(|_| {
+            poem::Error::from_string("No live session for this user", StatusCode::NOT_FOUND)
+        })?;
+
+    Ok(Json(report))
+}
+
+pub fn route() -> Route {
+    Route::new()
+        .at("/start", post(start_session))
+        .at("/stop", post(stop_session))
+        .at("/events", get(events))
+        .at("/ws", get(socket))
+        .at(
+            "/interruptions",
+            get(interruption_report).post(log_interruption),
+        )
+}
@@ -1 +1,6 @@
+pub mod audit;
 pub mod auth;
+pub mod live;
+pub mod records;
+pub mod tasks;
+pub mod webhooks;
@@ -0,0 +1,312 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use poem::{
+    IntoResponse, Route, get, handler,
+    http::StatusCode,
+    post, put,
+    web::{Data, Json, Path},
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    database::{
+        data::{AuditAction, AuditContext, AuditEntry},
+        storage::history_changes,
+    },
+    live::LiveError,
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    server::auth::AuthenticatedUser,
+    task::{RecordError, TaskRecord},
+};
+
+#[derive(Deserialize)]
+pub struct RecordRequestData {
+    pub name: String,
+    pub group: String,
+    pub minutes: u64,
+    // Defaults to now
+    pub finished: Option<DateTime<Utc>>,
+}
+
+impl RecordRequestData {
+    fn into_record(self) -> poem::Result<TaskRecord> {
+        Ok(TaskRecord::manual(
+            self.name,
+            self.group,
+            minutes(self.minutes)?,
+            self.finished.unwrap_or_else(Utc::now),
+        ))
+    }
+}
+
+fn minutes(minutes: u64) -> poem::Result<Duration> {
+    minutes
+        .checked_mul(60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| {
+            poem::Error::from_string("Duration is out of range", StatusCode::BAD_REQUEST)
+        })
+}
+
+#[derive(Deserialize)]
+pub struct SplitRequestData {
+    pub minutes: u64,
+}
+
+#[derive(Deserialize)]
+pub struct MergeRequestData {
+    pub first: Uuid,
+    pub second: Uuid,
+}
+
+fn edit_error(e: RecordError) -> poem::Error {
+    let status = match e {
+        RecordError::NotFound(_) => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    poem::Error::from_string(format!("Failed to edit history: {}", e), status)
+}
+
+fn storage_error(e: impl std::fmt::Display) -> poem::Error {
+    poem::Error::from_string(
+        format!("Failed to store history: {}", e),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+// Records with `ids` as they are in the history, for the
+// audit trail
+fn summarize_records(scheduler: &Scheduler, ids: &[Uuid]) -> Option<String> {
+    let summaries: Vec<String> = ids
+        .iter()
+        .filter_map(|id| scheduler.history().iter().find(|r| r.id == *id))
+        .map(|r| {
+            format!(
+                "{} {}/{} {}m",
+                r.id,
+                r.origin_group,
+                r.origin_name,
+                r.time.as_secs() / 60
+            )
+        })
+        .collect();
+
+    (!summaries.is_empty()).then(|| summaries.join(", "))
+}
+
+// Runs `edit` on the live session's scheduler, or on one
+// loaded from the database when the user has none. The
+// entry it returns is written with the history
+async fn edit<F, R>(state: &AppState, user: &AuthenticatedUser, edit: F) -> poem::Result<R>
+where
+    F: FnOnce(&mut Scheduler) -> Result<(R, AuditEntry), RecordError> + Clone + Send + 'static,
+    R: Send + 'static,
+{
+    let audit = state.audit.clone();
+    let live = edit.clone();
+    let edited = state
+        .live
+        .edit_history(user.0, move |scheduler| {
+            let (result, entry) = live(scheduler)?;
+            // The session stores its history right after
+            audit.push(entry);
+            Ok(result)
+        })
+        .await;
+
+    match edited {
+        Ok(result) => result.map_err(edit_error),
+        Err(LiveError::NotAttached) => edit_stored(state, user.0, edit).await,
+        Err(LiveError::Storage(e)) => Err(storage_error(e)),
+    }
+}
+
+async fn edit_stored<F, R>(state: &AppState, user_id: Uuid, edit: F) -> poem::Result<R>
+where
+    F: FnOnce(&mut Scheduler) -> Result<(R, AuditEntry), RecordError>,
+{
+    let transaction = state.database.begin().await.map_err(storage_error)?;
+
+    let history = transaction
+        .get_user_records(user_id)
+        .await
+        .map_err(storage_error)?;
+    let mut scheduler = Scheduler::new(
+        ExpectedRatioTasks::default(),
+        history.clone(),
+        ScheduleConfiguration::default(),
+    );
+
+    // Dropping the transaction rolls it back
+    let (result, entry) = edit(&mut scheduler).map_err(edit_error)?;
+    let (changed, deleted) = history_changes(&history, scheduler.history());
+    if changed.is_empty() && deleted.is_empty() {
+        return Ok(result);
+    }
+
+    transaction
+        .update_user_records(user_id, changed, deleted)
+        .await
+        .map_err(storage_error)?;
+
+    transaction.log_audit(entry).await.map_err(|e| {
+        poem::Error::from_string(
+            format!("Failed to log change: {}", e),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    transaction.commit().await.map_err(storage_error)?;
+
+    Ok(result)
+}
+
+#[handler]
+async fn list_records(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let records = match state
+        .live
+        .inspect_history(user.0, |scheduler| scheduler.history().to_vec())
+        .await
+    {
+        Ok(records) => records,
+        Err(()) => state.database.get_user_records(user.0).await.map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to load history: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?,
+    };
+
+    Ok(Json(records))
+}
+
+#[handler]
+async fn log_record(
+    user: AuthenticatedUser,
+    data: Json<RecordRequestData>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let record = data.0.into_record()?;
+    let (user_id, context) = (user.0, context.with_actor(user.0));
+
+    let id = edit(&state, &user, move |scheduler| {
+        let id = scheduler.log_record(record);
+        let entry = AuditEntry::new(user_id, AuditAction::RecordLogged, &context)
+            .change(None, summarize_records(scheduler, &[id]));
+
+        Ok((id, entry))
+    })
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "id": id }))))
+}
+
+#[handler]
+async fn update_record(
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    data: Json<RecordRequestData>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let record = data.0.into_record()?;
+    let (user_id, context) = (user.0, context.with_actor(user.0));
+
+    edit(&state, &user, move |scheduler| {
+        let before = summarize_records(scheduler, &[id]);
+        scheduler.update_record(id, record)?;
+        let entry = AuditEntry::new(user_id, AuditAction::RecordEdited, &context)
+            .change(before, summarize_records(scheduler, &[id]));
+
+        Ok(((), entry))
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn delete_record(
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let (user_id, context) = (user.0, context.with_actor(user.0));
+
+    edit(&state, &user, move |scheduler| {
+        let before = summarize_records(scheduler, &[id]);
+        scheduler.delete_record(id)?;
+        let entry =
+            AuditEntry::new(user_id, AuditAction::RecordDeleted, &context).change(before, None);
+
+        Ok(((), entry))
+    })
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn split_record(
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    data: Json<SplitRequestData>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let at = minutes(data.minutes)?;
+    let (user_id, context) = (user.0, context.with_actor(user.0));
+
+    let (first, second) = edit(&state, &user, move |scheduler| {
+        let before = summarize_records(scheduler, &[id]);
+        let (first, second) = scheduler.split_record(id, at)?;
+        let entry = AuditEntry::new(user_id, AuditAction::RecordSplit, &context)
+            .change(before, summarize_records(scheduler, &[first, second]));
+
+        Ok(((first, second), entry))
+    })
+    .await?;
+
+    Ok(Json(json!({ "first": first, "second": second })))
+}
+
+#[handler]
+async fn merge_records(
+    user: AuthenticatedUser,
+    data: Json<MergeRequestData>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let (first, second) = (data.first, data.second);
+    let (user_id, context) = (user.0, context.with_actor(user.0));
+
+    let id = edit(&state, &user, move |scheduler| {
+        let before = summarize_records(scheduler, &[first, second]);
+        let id = scheduler.merge_records(first, second)?;
+        let entry = AuditEntry::new(user_id, AuditAction::RecordsMerged, &context)
+            .change(before, summarize_records(scheduler, &[id]));
+
+        Ok((id, entry))
+    })
+    .await?;
+
+    Ok(Json(json!({ "id": id })))
+}
+
+pub fn route() -> Route {
+    Route::new()
+        .at("/", get(list_records).post(log_record))
+        .at("/merge", post(merge_records))
+        .at("/:id", put(update_record).delete(delete_record))
+        .at("/:id/split", post(split_record))
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use poem::{
+    IntoResponse, Route, get, handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    database::data::{AuditAction, AuditContext, AuditEntry},
+    goal::Goal,
+    schedule::ExpectedRatioTasks,
+    server::auth::AuthenticatedUser,
+    task::Task,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct RatioData {
+    pub name: String,
+    pub group: String,
+    pub ratio: f32,
+}
+
+fn internal_error(action: &str, e: impl std::fmt::Display) -> poem::Error {
+    poem::Error::from_string(
+        format!("Failed to {}: {}", action, e),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+pub(crate) fn summarize_tasks(tasks: &[Task]) -> String {
+    tasks
+        .iter()
+        .map(|t| format!("{}/{}", t.group, t.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn summarize_ratios(ratios: &[((String, String), f32)]) -> String {
+    ratios
+        .iter()
+        .map(|((name, group), ratio)| format!("{}/{} {}", group, name, ratio))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn summarize_goals(goals: &[Goal]) -> String {
+    goals
+        .iter()
+        .map(|g| format!("{} {}/{}", g.name, g.task_group, g.task_name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Checks that tasks and ratios still add up, before
+// anything is stored
+fn expected_tasks(
+    tasks: Vec<Task>,
+    ratios: &[((String, String), f32)],
+) -> poem::Result<ExpectedRatioTasks> {
+    ExpectedRatioTasks::from_ratios(tasks, ratios).map_err(|e| {
+        poem::Error::from_string(format!("Invalid ratios: {}", e), StatusCode::BAD_REQUEST)
+    })
+}
+
+// Swaps stored tasks and ratios into a running session
+async fn replan(state: &AppState, user_id: Uuid, expected: ExpectedRatioTasks) {
+    // Without a live session the next start picks them up
+    let _ = state
+        .live
+        .edit_history(user_id, move |scheduler| scheduler.tasks = expected)
+        .await;
+}
+
+#[handler]
+async fn list_tasks(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let tasks = state
+        .database
+        .get_user_tasks(user.0)
+        .await
+        .map_err(|e| internal_error("get tasks", e))?;
+
+    Ok(Json(tasks))
+}
+
+#[handler]
+async fn set_tasks(
+    user: AuthenticatedUser,
+    data: Json<Vec<Task>>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let transaction = state
+        .database
+        .begin()
+        .await
+        .map_err(|e| internal_error("store tasks", e))?;
+
+    let before = transaction
+        .get_user_tasks(user.0)
+        .await
+        .map_err(|e| internal_error("get tasks", e))?;
+
+    let ratios = transaction
+        .get_user_ratios(user.0)
+        .await
+        .map_err(|e| internal_error("get ratios", e))?;
+
+    // Dropping the transaction rolls it back
+    let expected = expected_tasks(data.0.clone(), &ratios)?;
+
+    transaction
+        .set_user_tasks(user.0, data.0.clone())
+        .await
+        .map_err(|e| internal_error("store tasks", e))?;
+
+    transaction
+        .log_audit(
+            AuditEntry::new(
+                user.0,
+                AuditAction::TasksChanged,
+                &context.with_actor(user.0),
+            )
+            .change(
+                Some(summarize_tasks(&before)),
+                Some(summarize_tasks(&data.0)),
+            ),
+        )
+        .await
+        .map_err(|e| internal_error("log change", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| internal_error("store tasks", e))?;
+
+    replan(&state, user.0, expected).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn list_ratios(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let ratios = state
+        .database
+        .get_user_ratios(user.0)
+        .await
+        .map_err(|e| internal_error("get ratios", e))?;
+
+    let ratios: Vec<RatioData> = ratios
+        .into_iter()
+        .map(|((name, group), ratio)| RatioData { name, group, ratio })
+        .collect();
+
+    Ok(Json(ratios))
+}
+
+#[handler]
+async fn set_ratios(
+    user: AuthenticatedUser,
+    data: Json<Vec<RatioData>>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let ratios: Vec<((String, String), f32)> = data
+        .0
+        .into_iter()
+        .map(|r| ((r.name, r.group), r.ratio))
+        .collect();
+
+    let total: f32 = ratios.iter().map(|r| r.1).sum();
+
+    if (total - 1.0).abs() > 0.01 {
+        return Err(poem::Error::from_string(
+            "Ratios have to add up to 1.0",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let transaction = state
+        .database
+        .begin()
+        .await
+        .map_err(|e| internal_error("store ratios", e))?;
+
+    let before = transaction
+        .get_user_ratios(user.0)
+        .await
+        .map_err(|e| internal_error("get ratios", e))?;
+
+    let tasks = transaction
+        .get_user_tasks(user.0)
+        .await
+        .map_err(|e| internal_error("get tasks", e))?;
+
+    let expected = expected_tasks(tasks, &ratios)?;
+
+    transaction
+        .set_user_ratios(user.0, ratios.clone())
+        .await
+        .map_err(|e| internal_error("store ratios", e))?;
+
+    transaction
+        .log_audit(
+            AuditEntry::new(
+                user.0,
+                AuditAction::RatiosChanged,
+                &context.with_actor(user.0),
+            )
+            .change(
+                Some(summarize_ratios(&before)),
+                Some(summarize_ratios(&ratios)),
+            ),
+        )
+        .await
+        .map_err(|e| internal_error("log change", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| internal_error("store ratios", e))?;
+
+    replan(&state, user.0, expected).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn list_goals(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let goals = state
+        .database
+        .get_user_goals(user.0)
+        .await
+        .map_err(|e| internal_error("get goals", e))?;
+
+    Ok(Json(goals))
+}
+
+#[handler]
+async fn set_goals(
+    user: AuthenticatedUser,
+    data: Json<Vec<Goal>>,
+    context: AuditContext,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    if data.iter().any(|goal| goal.deadline <= goal.start) {
+        return Err(poem::Error::from_string(
+            "Goals have to end after they start",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let transaction = state
+        .database
+        .begin()
+        .await
+        .map_err(|e| internal_error("store goals", e))?;
+
+    let before = transaction
+        .get_user_goals(user.0)
+        .await
+        .map_err(|e| internal_error("get goals", e))?;
+
+    transaction
+        .set_user_goals(user.0, data.0.clone())
+        .await
+        .map_err(|e| internal_error("store goals", e))?;
+
+    transaction
+        .log_audit(
+            AuditEntry::new(
+                user.0,
+                AuditAction::GoalsChanged,
+                &context.with_actor(user.0),
+            )
+            .change(
+                Some(summarize_goals(&before)),
+                Some(summarize_goals(&data.0)),
+            ),
+        )
+        .await
+        .map_err(|e| internal_error("log change", e))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| internal_error("store goals", e))?;
+
+    // Without a live session the next start picks them up
+    let _ = state
+        .live
+        .edit_history(user.0, move |scheduler| scheduler.goals = data.0)
+        .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn route() -> Route {
+    Route::new()
+        .at("/", get(list_tasks).put(set_tasks))
+        .at("/ratios", get(list_ratios).put(set_ratios))
+        .at("/goals", get(list_goals).put(set_goals))
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use poem::{
+    IntoResponse, Route, delete, get, handler,
+    http::StatusCode,
+    web::{Data, Json, Path},
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    database::data::{WEBHOOK_EVENTS, Webhook},
+    server::auth::AuthenticatedUser,
+};
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequestData {
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[handler]
+async fn create_webhook(
+    user: AuthenticatedUser,
+    data: Json<CreateWebhookRequestData>,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    if let Some(event) = data
+        .events
+        .iter()
+        .find(|e| !WEBHOOK_EVENTS.contains(&e.as_str()))
+    {
+        return Err(poem::Error::from_string(
+            format!("Unknown event: {}", event),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    // Deliveries only ever go out over HTTP(S) to a named host
+    let valid_url = reqwest::Url::parse(&data.url)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+
+    if !valid_url {
+        return Err(poem::Error::from_string(
+            format!("Invalid webhook url: {}", data.url),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let webhook = Webhook::new(user.0, &data.url, data.events.clone());
+    let (id, secret) = (webhook.id, webhook.secret.clone());
+
+    state.database.create_webhook(webhook).await.map_err(|e| {
+        poem::Error::from_string(
+            format!("Failed to create webhook: {}", e),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    })?;
+
+    // The secret is only ever shown here
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "id": id,
+            "secret": secret,
+        })),
+    ))
+}
+
+#[handler]
+async fn list_webhooks(
+    user: AuthenticatedUser,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let webhooks = state
+        .database
+        .get_user_webhooks(user.0)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to get webhooks: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let webhooks: Vec<_> = webhooks
+        .iter()
+        .map(|w| {
+            json!({
+                "id": w.id,
+                "url": w.url,
+                "events": w.events,
+                "created_at": w.created_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!(webhooks)))
+}
+
+#[handler]
+async fn delete_webhook(
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    state
+        .database
+        .delete_webhook(user.0, id)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to delete webhook: {}", e),
+                StatusCode::NOT_FOUND,
+            )
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn list_deliveries(
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    state: Data<&Arc<AppState>>,
+) -> poem::Result<impl IntoResponse> {
+    let owned = state
+        .database
+        .get_user_webhooks(user.0)
+        .await
+        .map(|webhooks| webhooks.iter().any(|w| w.id == id))
+        .unwrap_or(false);
+
+    if !owned {
+        return Err(poem::Error::from_string(
+            "Webhook not found",
+            StatusCode::NOT_FOUND,
+        ));
+    }
+
+    let deliveries = state
+        .database
+        .get_webhook_deliveries(id)
+        .await
+        .map_err(|e| {
+            poem::Error::from_string(
+                format!("Failed to get deliveries: {}", e),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+
+    let deliveries: Vec<_> = deliveries
+        .iter()
+        .map(|d| {
+            json!({
+                "id": d.id,
+                "event": d.event,
+                "attempt": d.attempt,
+                "status_code": d.status_code,
+                "error": d.error,
+                "created_at": d.created_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!(deliveries)))
+}
+
+pub fn route() -> Route {
+    Route::new()
+        .at("/", get(list_webhooks).post(create_webhook))
+        .at("/:id", delete(delete_webhook))
+        .at("/:id/deliveries", get(list_deliveries))
+}
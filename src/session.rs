@@ -0,0 +1,444 @@
+use crate::interruption::{Interruption, InterruptionKind};
+use crate::schedule::Scheduler;
+use crate::storage::{Storable, StorageError, Stored};
+use crate::task::{ScheduleTask, TaskRecord};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const TRANSITION_GROUP: &str = "system/transition";
+
+#[derive(Debug)]
+pub enum SessionError {
+    AlreadyActive,
+    NotRunning,
+    NotPaused,
+    NoActiveBlock,
+    NothingToSchedule,
+    // Too long to be added to a block
+    InvalidDuration,
+    Storage(StorageError),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::AlreadyActive => write!(f, "A block is already active"),
+            SessionError::NotRunning => write!(f, "No block is running"),
+            SessionError::NotPaused => write!(f, "The block is not paused"),
+            SessionError::NoActiveBlock => write!(f, "No active block"),
+            SessionError::NothingToSchedule => write!(f, "Nothing to schedule"),
+            SessionError::InvalidDuration => write!(f, "Duration is out of range"),
+            SessionError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// The block the user is currently working on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveBlock {
+    pub task: ScheduleTask,
+    pub started: DateTime<Utc>,
+    // Grows when the block is extended
+    pub planned: Duration,
+    // Time worked before the current segment, pauses
+    // are not counted
+    pub worked: Duration,
+    // When the block was last started or resumed
+    pub segment_start: DateTime<Utc>,
+    #[serde(default)]
+    pub interruptions: Vec<Interruption>,
+}
+
+impl ActiveBlock {
+    fn new(task: ScheduleTask, now: DateTime<Utc>) -> Self {
+        Self {
+            planned: task.time,
+            task,
+            started: now,
+            worked: Duration::from_secs(0),
+            segment_start: now,
+            interruptions: vec![],
+        }
+    }
+
+    fn segment(&self, now: DateTime<Utc>) -> Duration {
+        (now - self.segment_start).to_std().unwrap_or_default()
+    }
+
+    fn is_transition(&self) -> bool {
+        self.task.origin_group == TRANSITION_GROUP
+    }
+
+    // Interruptions don't count as focus time
+    fn record(&self, worked: Duration, now: DateTime<Utc>) -> TaskRecord {
+        let interrupted = self
+            .interruptions
+            .iter()
+            .fold(Duration::from_secs(0), |acc, i| acc + i.duration);
+
+        TaskRecord {
+            id: Uuid::new_v4(),
+            origin_name: self.task.origin_name.clone(),
+            origin_group: self.task.origin_group.clone(),
+            time: worked.saturating_sub(interrupted),
+            planned: Some(self.planned),
+            started: Some(self.started),
+            finished: Some(now),
+            interruptions: self.interruptions.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum SessionState {
+    #[default]
+    Idle,
+    Running(ActiveBlock),
+    Paused(ActiveBlock),
+}
+
+impl Stored for SessionState {}
+
+impl SessionState {
+    pub fn block(&self) -> Option<&ActiveBlock> {
+        match self {
+            SessionState::Idle => None,
+            SessionState::Running(block) | SessionState::Paused(block) => Some(block),
+        }
+    }
+
+    // Time left on the active block at `now`
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            SessionState::Idle => None,
+            SessionState::Running(block) => Some(
+                block
+                    .planned
+                    .saturating_sub(block.worked + block.segment(now)),
+            ),
+            SessionState::Paused(block) => Some(block.planned.saturating_sub(block.worked)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionEvent {
+    Started,
+    Paused,
+    Resumed,
+    Skipped,
+    Extended,
+    Interrupted,
+    FinishedEarly,
+    Completed,
+}
+
+// Result of every state change, `record` is set when
+// the change ended a block that has to go in the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTransition {
+    pub event: SessionEvent,
+    pub task: ScheduleTask,
+    pub at: DateTime<Utc>,
+    pub record: Option<TaskRecord>,
+}
+
+// Drives the scheduler in real time. Every transition is
+// written to the storage so a restart picks up the
+// running block where it was left
+pub struct Session<S>
+where
+    S: Storable<SessionState> + Storable<TaskRecord>,
+{
+    pub scheduler: Scheduler,
+    pub state: SessionState,
+    storage: S,
+}
+
+impl<S> Session<S>
+where
+    S: Storable<SessionState> + Storable<TaskRecord>,
+{
+    pub fn new(scheduler: Scheduler, storage: S) -> Result<Self, StorageError> {
+        let stored: Vec<SessionState> = storage.get()?;
+        let state = stored.into_iter().next().unwrap_or_default();
+
+        Ok(Self {
+            scheduler,
+            state,
+            storage,
+        })
+    }
+
+    // Writes the state and history, call it after changing
+    // the scheduler history directly
+    pub fn persist(&self) -> Result<(), StorageError> {
+        self.storage.store(std::slice::from_ref(&self.state))?;
+        self.storage.store(self.scheduler.history())
+    }
+
+    // Runs `edit` on the scheduler, the history is put back
+    // as it was if it can't be written
+    pub fn edit_history<F>(&mut self, edit: F) -> Result<(), StorageError>
+    where
+        F: FnOnce(&mut Scheduler),
+    {
+        let history = self.scheduler.history().to_vec();
+        edit(&mut self.scheduler);
+
+        if let Err(err) = self.persist() {
+            self.scheduler.replace_history(history);
+            let _ = self.persist();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    // Moves to `state`, nothing changes in memory unless it
+    // was written
+    fn transition(
+        &mut self,
+        state: SessionState,
+        event: SessionEvent,
+        task: ScheduleTask,
+        at: DateTime<Utc>,
+        record: Option<TaskRecord>,
+    ) -> Result<SessionTransition, SessionError> {
+        let previous = std::mem::replace(&mut self.state, state);
+
+        if let Some(record) = &record {
+            self.scheduler.feed_record(record.clone());
+        }
+
+        if let Err(err) = self.persist() {
+            self.state = previous;
+            if let Some(record) = &record {
+                let _ = self.scheduler.delete_record(record.id);
+            }
+
+            // Whatever part of it made it to the storage is
+            // put back as well, if that fails too the next
+            // transition writes it again
+            let _ = self.persist();
+
+            return Err(SessionError::Storage(err));
+        }
+
+        Ok(SessionTransition {
+            event,
+            task,
+            at,
+            record,
+        })
+    }
+
+    pub fn start(&mut self, now: DateTime<Utc>) -> Result<SessionTransition, SessionError> {
+        if !matches!(self.state, SessionState::Idle) {
+            return Err(SessionError::AlreadyActive);
+        }
+
+        let task = self
+            .scheduler
+            .compute_task(&vec![], now)
+            .filter(|task| !task.time.is_zero())
+            .ok_or(SessionError::NothingToSchedule)?;
+
+        let state = SessionState::Running(ActiveBlock::new(task.clone(), now));
+        self.transition(state, SessionEvent::Started, task, now, None)
+    }
+
+    pub fn pause(&mut self, now: DateTime<Utc>) -> Result<SessionTransition, SessionError> {
+        let SessionState::Running(mut block) = self.state.clone() else {
+            return Err(SessionError::NotRunning);
+        };
+
+        block.worked += block.segment(now);
+        block.segment_start = now;
+
+        let task = block.task.clone();
+        self.transition(
+            SessionState::Paused(block),
+            SessionEvent::Paused,
+            task,
+            now,
+            None,
+        )
+    }
+
+    pub fn resume(&mut self, now: DateTime<Utc>) -> Result<SessionTransition, SessionError> {
+        let SessionState::Paused(mut block) = self.state.clone() else {
+            return Err(SessionError::NotPaused);
+        };
+
+        block.segment_start = now;
+
+        let task = block.task.clone();
+        self.transition(
+            SessionState::Running(block),
+            SessionEvent::Resumed,
+            task,
+            now,
+            None,
+        )
+    }
+
+    pub fn extend(
+        &mut self,
+        by: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<SessionTransition, SessionError> {
+        let mut state = self.state.clone();
+        let task = match &mut state {
+            SessionState::Idle => return Err(SessionError::NoActiveBlock),
+            SessionState::Running(block) | SessionState::Paused(block) => {
+                // Has to fit a TimeDelta for the end to be worked out
+                block.planned = block
+                    .planned
+                    .checked_add(by)
+                    .filter(|planned| chrono::TimeDelta::from_std(*planned).is_ok())
+                    .ok_or(SessionError::InvalidDuration)?;
+                block.task.clone()
+            }
+        };
+
+        self.transition(state, SessionEvent::Extended, task, now, None)
+    }
+
+    // Logs an interruption against the active block, it is
+    // taken out of the focus time once the block ends
+    pub fn interrupt(
+        &mut self,
+        kind: InterruptionKind,
+        duration: Duration,
+        note: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<SessionTransition, SessionError> {
+        let mut state = self.state.clone();
+        let task = match &mut state {
+            SessionState::Idle => return Err(SessionError::NoActiveBlock),
+            SessionState::Running(block) | SessionState::Paused(block) => {
+                block.interruptions.push(Interruption {
+                    kind,
+                    duration,
+                    note,
+                    at: now,
+                });
+                block.task.clone()
+            }
+        };
+
+        self.transition(state, SessionEvent::Interrupted, task, now, None)
+    }
+
+    // Ends the block without counting it as work, any time
+    // already spent on it is still recorded
+    pub fn skip(&mut self, now: DateTime<Utc>) -> Result<SessionTransition, SessionError> {
+        let (block, worked) = self.end_block(now)?;
+
+        let record = if worked.is_zero() || block.is_transition() {
+            None
+        } else {
+            Some(block.record(worked, now))
+        };
+
+        self.transition(
+            SessionState::Idle,
+            SessionEvent::Skipped,
+            block.task,
+            now,
+            record,
+        )
+    }
+
+    pub fn finish(&mut self, now: DateTime<Utc>) -> Result<SessionTransition, SessionError> {
+        let (block, worked) = self.end_block(now)?;
+        let record = (!block.is_transition()).then(|| block.record(worked, now));
+
+        self.transition(
+            SessionState::Idle,
+            SessionEvent::FinishedEarly,
+            block.task,
+            now,
+            record,
+        )
+    }
+
+    // The state is left alone, the caller's transition
+    // moves on to idle
+    fn end_block(&self, now: DateTime<Utc>) -> Result<(ActiveBlock, Duration), SessionError> {
+        let (block, worked) = match self.state.clone() {
+            SessionState::Idle => return Err(SessionError::NoActiveBlock),
+            SessionState::Running(block) => {
+                let worked = block.worked + block.segment(now);
+                (block, worked)
+            }
+            SessionState::Paused(block) => {
+                let worked = block.worked;
+                (block, worked)
+            }
+        };
+
+        Ok((block, worked))
+    }
+
+    // Completes the running block once its time is up and
+    // moves on, work blocks are followed by a transition.
+    // If nothing ticked for a while (e.g. a restart) the next
+    // block starts now instead of replaying the missed ones
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Result<Vec<SessionTransition>, SessionError> {
+        let SessionState::Running(block) = &self.state else {
+            return Ok(vec![]);
+        };
+
+        if block.worked + block.segment(now) < block.planned {
+            return Ok(vec![]);
+        }
+
+        // The block ended at its planned time, not at the tick
+        let ended = block.segment_start
+            + chrono::TimeDelta::from_std(block.planned.saturating_sub(block.worked))
+                .unwrap_or_default();
+        let ended = ended.min(now);
+
+        let (block, worked) = self.end_block(ended)?;
+
+        // Transitions between blocks are not part of the history,
+        // however they end
+        let record = (!block.is_transition()).then(|| block.record(worked, ended));
+
+        let mut transitions = vec![self.transition(
+            SessionState::Idle,
+            SessionEvent::Completed,
+            block.task.clone(),
+            ended,
+            record,
+        )?];
+
+        let transition_time = Duration::from_secs(self.scheduler.config.transitiontime * 60);
+
+        if !block.task.origin_group.starts_with("system/") && !transition_time.is_zero() {
+            let task = ScheduleTask {
+                origin_name: String::from("Transition"),
+                origin_group: String::from(TRANSITION_GROUP),
+                time: transition_time,
+                explanation: None,
+            };
+
+            let state = SessionState::Running(ActiveBlock::new(task.clone(), now));
+            transitions.push(self.transition(state, SessionEvent::Started, task, now, None)?);
+        } else {
+            match self.start(now) {
+                Ok(started) => transitions.push(started),
+                // With nothing left to schedule the session goes idle
+                Err(SessionError::NothingToSchedule) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(transitions)
+    }
+}
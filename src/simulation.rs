@@ -0,0 +1,234 @@
+use crate::schedule::Scheduler;
+use crate::task::TaskRecord;
+use chrono::{DateTime, TimeDelta, Utc};
+use derivative::Derivative;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::time::Duration;
+
+// How closely the simulated person follows the schedule
+#[derive(Derivative)]
+#[derivative(Debug, Clone, Default)]
+pub struct SimulationConfiguration {
+    #[derivative(Default(value = "14"))]
+    pub days: u32,
+    // Work time per day, breaks are not counted
+    #[derivative(Default(value = "Duration::from_secs(60*60*6)"))]
+    pub daily_work: Duration,
+    // Chance of a work block being skipped entirely
+    pub skip_probability: f32,
+    // Average extra time spent on a block, 0.25 means
+    // blocks run 25% longer than planned on average
+    pub average_overrun: f32,
+    pub seed: u64,
+    // When the first simulated day starts, goals are paced
+    // against this clock instead of the real one
+    #[derivative(Default(value = "DateTime::UNIX_EPOCH"))]
+    pub start: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskTrajectory {
+    pub name: String,
+    pub group: String,
+    pub target: f32,
+    // Ratio at the end of every simulated day
+    pub ratios: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulationReport {
+    pub trajectories: Vec<TaskTrajectory>,
+    pub blocks: usize,
+    pub skipped: usize,
+    pub worked: Duration,
+}
+
+#[derive(Debug)]
+pub enum SimulationError {
+    // Breaks would be due all the time and no work would
+    // ever get scheduled
+    ZeroBreakFrequency,
+    // A break that takes no time ends the simulated day
+    ZeroBreakTime,
+    // Has to be a chance, between 0 and 1
+    InvalidSkipProbability,
+    // Has to be a finite share that is not negative
+    InvalidOverrun,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::ZeroBreakFrequency => {
+                write!(f, "Break frequencies must be above zero")
+            }
+            SimulationError::ZeroBreakTime => write!(f, "Break times must be above zero"),
+            SimulationError::InvalidSkipProbability => {
+                write!(f, "Skip probability must be between 0 and 1")
+            }
+            SimulationError::InvalidOverrun => {
+                write!(f, "Average overrun must be finite and not negative")
+            }
+        }
+    }
+}
+
+pub struct Simulation {
+    scheduler: Scheduler,
+    config: SimulationConfiguration,
+}
+
+impl Simulation {
+    // The scheduler is cloned so simulating never touches
+    // the real history
+    pub fn new(
+        scheduler: &Scheduler,
+        config: SimulationConfiguration,
+    ) -> Result<Self, SimulationError> {
+        let schedule = &scheduler.config;
+
+        if schedule.break_frequency == 0 || schedule.minibreak_frequency == 0 {
+            return Err(SimulationError::ZeroBreakFrequency);
+        }
+
+        if schedule.breaktime == 0 || schedule.minibreaktime == 0 {
+            return Err(SimulationError::ZeroBreakTime);
+        }
+
+        if !(0.0..=1.0).contains(&config.skip_probability) {
+            return Err(SimulationError::InvalidSkipProbability);
+        }
+
+        if !config.average_overrun.is_finite() || config.average_overrun < 0.0 {
+            return Err(SimulationError::InvalidOverrun);
+        }
+
+        Ok(Self {
+            scheduler: scheduler.clone(),
+            config,
+        })
+    }
+
+    pub fn run(mut self) -> SimulationReport {
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+
+        let mut report = SimulationReport {
+            trajectories: self
+                .scheduler
+                .tasks
+                .0
+                .iter()
+                .map(|(task, ratio)| TaskTrajectory {
+                    name: task.name.clone(),
+                    group: task.group.clone(),
+                    target: *ratio,
+                    ratios: Vec::with_capacity(self.config.days as usize),
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        for day in 0..self.config.days {
+            let mut worked_today = Duration::from_secs(0);
+            let mut now = self.config.start + TimeDelta::days(day as i64);
+
+            while worked_today < self.config.daily_work {
+                // Nothing left to schedule
+                let Some(task) = self.scheduler.compute_task(&vec![], now) else {
+                    break;
+                };
+
+                if task.time.is_zero() {
+                    break;
+                }
+
+                if task.origin_group.starts_with("system/") {
+                    let record = timed(TaskRecord::from(task), &mut now);
+                    self.scheduler.feed_record(record);
+                    continue;
+                }
+
+                report.blocks += 1;
+
+                // A skipped block still burns its slot in the day
+                if rng.random::<f32>() < self.config.skip_probability {
+                    report.skipped += 1;
+                    worked_today += task.time;
+                    now += TimeDelta::from_std(task.time).unwrap_or_default();
+                    continue;
+                }
+
+                let mut record = TaskRecord::from(task);
+                record.time = record.time.mul_f32(1.0 + self.overrun(&mut rng));
+
+                worked_today += record.time;
+                report.worked += record.time;
+                self.scheduler.feed_record(timed(record, &mut now));
+            }
+
+            let ratios = work_ratios(self.scheduler.history());
+
+            for trajectory in report.trajectories.iter_mut() {
+                let ratio = ratios
+                    .iter()
+                    .find(|r| r.0 == (trajectory.name.clone(), trajectory.group.clone()))
+                    .map(|r| r.1)
+                    .unwrap_or(0.0);
+
+                trajectory.ratios.push(ratio);
+            }
+        }
+
+        report
+    }
+
+    // Uniform between zero and twice the average so the
+    // mean overrun matches the configuration, which `new`
+    // made sure is finite and not negative
+    fn overrun(&self, rng: &mut StdRng) -> f32 {
+        if self.config.average_overrun == 0.0 {
+            return 0.0;
+        }
+
+        rng.random_range(0.0..self.config.average_overrun * 2.0)
+    }
+}
+
+// Places the record at `now` on the simulated clock and
+// moves the clock to its end
+fn timed(mut record: TaskRecord, now: &mut DateTime<Utc>) -> TaskRecord {
+    record.started = Some(*now);
+    *now += TimeDelta::from_std(record.time).unwrap_or_default();
+    record.finished = Some(*now);
+    record
+}
+
+// Share of the work time spent on every task, system
+// tasks like breaks are left out
+fn work_ratios(history: &[TaskRecord]) -> Vec<((String, String), f32)> {
+    let work: Vec<&TaskRecord> = history
+        .iter()
+        .filter(|r| !r.origin_group.starts_with("system/"))
+        .collect();
+
+    let total = work
+        .iter()
+        .fold(Duration::from_secs(0), |acc, r| acc + r.time);
+
+    let mut ratios: Vec<((String, String), f32)> = Vec::new();
+
+    for record in work {
+        let identity = (record.origin_name.clone(), record.origin_group.clone());
+        let ratio = record.time.as_secs_f32() / total.as_secs_f32();
+
+        if let Some(entry) = ratios.iter_mut().find(|r| r.0 == identity) {
+            entry.1 += ratio;
+        } else {
+            ratios.push((identity, ratio));
+        }
+    }
+
+    ratios
+}
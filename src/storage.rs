@@ -1,115 +1,764 @@
+use crate::history::HistoryLog;
+use crate::schema::{self, Envelope, FIRST_VERSION, Upgrade};
+use crate::session::SessionState;
 use crate::task::{Task, TaskRecord};
+use crate::watch::watch;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
 use std::{
-    fs::{File, OpenOptions},
-    io::{Read, Write},
-    path::Path,
+    any::{Any, TypeId, type_name},
+    collections::HashMap,
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
 };
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Toml(String),
+    Yaml(serde_yaml::Error),
+    Csv(csv::Error),
+    // Stored ratios don't add up to 1.0
+    InvalidRatios,
+    // Seconds in a CSV row that aren't a valid duration
+    InvalidDuration(f64),
+    // No file was registered for the collection
+    Unregistered(&'static str),
+    // The format can't hold the collection, e.g. CSV for
+    // anything but task history
+    Unsupported(Format, &'static str),
+    // Written by a newer version, found and supported
+    UnsupportedVersion(u32, u32),
+    Crypto(&'static str),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(error) => write!(f, "{}", error),
+            StorageError::Json(error) => write!(f, "{}", error),
+            StorageError::Toml(error) => write!(f, "{}", error),
+            StorageError::Yaml(error) => write!(f, "{}", error),
+            StorageError::Csv(error) => write!(f, "{}", error),
+            StorageError::InvalidRatios => write!(f, "Task ratios don't add up to 1.0"),
+            StorageError::InvalidDuration(seconds) => {
+                write!(f, "{} seconds is not a valid duration", seconds)
+            }
+            StorageError::Unregistered(name) => write!(f, "No file registered for {}", name),
+            StorageError::Crypto(message) => write!(f, "{}", message),
+            StorageError::UnsupportedVersion(found, supported) => write!(
+                f,
+                "File has version {} but only up to {} is supported",
+                found, supported
+            ),
+            StorageError::Unsupported(format, name) => {
+                write!(f, "{:?} files can't store {}", format, name)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(value: std::io::Error) -> Self {
+        StorageError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(value: serde_json::Error) -> Self {
+        StorageError::Json(value)
+    }
+}
+
+impl From<toml::ser::Error> for StorageError {
+    fn from(value: toml::ser::Error) -> Self {
+        StorageError::Toml(value.to_string())
+    }
+}
+
+impl From<toml::de::Error> for StorageError {
+    fn from(value: toml::de::Error) -> Self {
+        StorageError::Toml(value.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for StorageError {
+    fn from(value: serde_yaml::Error) -> Self {
+        StorageError::Yaml(value)
+    }
+}
+
+impl From<csv::Error> for StorageError {
+    fn from(value: csv::Error) -> Self {
+        StorageError::Csv(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    // One entry per line, records can be appended without
+    // rewriting the file
+    JsonLines,
+    Toml,
+    Yaml,
+    // Flat rows, only for task history
+    Csv,
+}
+
+impl Format {
+    // Unknown extensions fall back to JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") | Some("ndjson") => Format::JsonLines,
+            Some("toml") => Format::Toml,
+            Some("yaml") | Some("yml") => Format::Yaml,
+            Some("csv") => Format::Csv,
+            _ => Format::Json,
+        }
+    }
+
+    // JSON, TOML and YAML files wrap their entries in a
+    // versioned envelope, JSON Lines files start with a
+    // version line and CSV files with a version comment
+    fn encode<T>(self, data: &[T], version: u32) -> Result<Vec<u8>, StorageError>
+    where
+        T: Serialize + 'static,
+    {
+        let envelope = Envelope {
+            version,
+            entries: data,
+        };
+
+        match self {
+            Format::Json => Ok(serde_json::to_vec_pretty(&envelope)?),
+            Format::JsonLines => {
+                let mut buf = schema::header_line(version)?;
+                buf.extend(entry_lines(data)?);
+                Ok(buf)
+            }
+            Format::Toml => Ok(toml::to_string(&envelope)?.into_bytes()),
+            Format::Yaml => Ok(serde_yaml::to_string(&envelope)?.into_bytes()),
+            Format::Csv => {
+                let records = records_of(self, data)?;
+                let mut writer = csv::Writer::from_writer(schema::csv_header(version).into_bytes());
+                for record in records {
+                    writer.serialize(CsvRecord::from(record))?;
+                }
+                Ok(writer.into_inner().map_err(|e| e.into_error())?)
+            }
+        }
+    }
+
+    fn decode_document(self, buf: &str) -> Result<Value, StorageError> {
+        match self {
+            Format::Toml => Ok(toml::from_str(buf)?),
+            Format::Yaml => Ok(serde_yaml::from_str(buf)?),
+            _ => Ok(serde_json::from_str(buf)?),
+        }
+    }
+
+    // Version and entries as a JSON array, the version is
+    // none for files written before versioning
+    fn decode_entries(self, buf: &str) -> Result<(Option<u32>, Value), StorageError> {
+        match self {
+            Format::JsonLines => {
+                let mut lines = buf
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .peekable();
+                let version = lines.peek().and_then(|line| schema::header(line));
+                let entries = lines
+                    .skip(version.is_some() as usize)
+                    .map(serde_json::from_str)
+                    .collect::<Result<Vec<Value>, _>>()?;
+
+                Ok((version, Value::Array(entries)))
+            }
+            Format::Csv => {
+                let version = buf.lines().next().and_then(schema::csv_version);
+                let mut entries = Vec::new();
+                let mut reader = csv::ReaderBuilder::new()
+                    .comment(Some(b'#'))
+                    .from_reader(buf.as_bytes());
+                for row in reader.deserialize() {
+                    let row: CsvRecord = row?;
+                    entries.push(serde_json::to_value(TaskRecord::try_from(row)?)?);
+                }
+
+                Ok((version, Value::Array(entries)))
+            }
+            _ => Ok(schema::open(self.decode_document(buf)?)),
+        }
+    }
+}
+
+fn entry_lines<T: Serialize>(data: &[T]) -> Result<Vec<u8>, StorageError> {
+    let mut buf = Vec::new();
+    for item in data {
+        serde_json::to_writer(&mut buf, item)?;
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+// CSV has no nesting, durations are written as seconds and
+// interruptions as JSON in a single column
+#[derive(Serialize, Deserialize)]
+struct CsvRecord {
+    id: Uuid,
+    name: String,
+    group: String,
+    seconds: f64,
+    planned_seconds: Option<f64>,
+    started: Option<DateTime<Utc>>,
+    finished: Option<DateTime<Utc>>,
+    interruptions: String,
+}
+
+impl From<&TaskRecord> for CsvRecord {
+    fn from(record: &TaskRecord) -> Self {
+        Self {
+            id: record.id,
+            name: record.origin_name.clone(),
+            group: record.origin_group.clone(),
+            seconds: record.time.as_secs_f64(),
+            planned_seconds: record.planned.map(|p| p.as_secs_f64()),
+            started: record.started,
+            finished: record.finished,
+            interruptions: if record.interruptions.is_empty() {
+                String::new()
+            } else {
+                serde_json::to_string(&record.interruptions).unwrap_or_default()
+            },
+        }
+    }
+}
+
+impl TryFrom<CsvRecord> for TaskRecord {
+    type Error = StorageError;
+
+    fn try_from(row: CsvRecord) -> Result<Self, Self::Error> {
+        let interruptions = if row.interruptions.trim().is_empty() {
+            vec![]
+        } else {
+            serde_json::from_str(&row.interruptions)?
+        };
+
+        Ok(Self {
+            id: row.id,
+            origin_name: row.name,
+            origin_group: row.group,
+            time: seconds(row.seconds)?,
+            planned: row.planned_seconds.map(seconds).transpose()?,
+            started: row.started,
+            finished: row.finished,
+            interruptions,
+        })
+    }
+}
+
+fn seconds(seconds: f64) -> Result<Duration, StorageError> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| StorageError::InvalidDuration(seconds))
+}
+
+fn records_of<T>(format: Format, data: &[T]) -> Result<Vec<&TaskRecord>, StorageError>
+where
+    T: 'static,
+{
+    data.iter()
+        .map(|item| {
+            (item as &dyn Any)
+                .downcast_ref::<TaskRecord>()
+                .ok_or(StorageError::Unsupported(format, type_name::<T>()))
+        })
+        .collect()
+}
+
+fn collection_of<T>(format: Format, records: Vec<TaskRecord>) -> Result<Vec<T>, StorageError>
+where
+    T: 'static,
+{
+    let records: Box<dyn Any> = Box::new(records);
+    records
+        .downcast::<Vec<T>>()
+        .map(|records| *records)
+        .map_err(|_| StorageError::Unsupported(format, type_name::<T>()))
+}
+
+// Types that can be kept in a collection, implemented
+// next to each type so a collection that was never meant
+// to be stored doesn't compile
+pub trait Stored: Serialize + DeserializeOwned + 'static {}
+
+// Ratios of every (name, group) task
+impl Stored for ((String, String), f32) {}
+
+struct Collection<P> {
+    path: P,
+    format: Format,
+}
+
+// Every collection lives in its own file, the four the
+// scheduler needs are registered by `new` and any other
+// serde type can be added with `register`
 pub struct FileStorage<P>
 where
     P: AsRef<Path>,
 {
-    pub tasks: P,
-    pub records: P,
-    pub tasks_ratios: P,
+    collections: HashMap<TypeId, Collection<P>>,
+    // Task history in JSON Lines is kept as an append-only
+    // log instead of being rewritten on every store
+    history: Option<HistoryLog<PathBuf>>,
+    upgrades: HashMap<TypeId, Vec<Upgrade>>,
 }
 
 impl<P> FileStorage<P>
 where
     P: AsRef<Path>,
 {
-    pub fn new(tasks: P, records: P, tasks_ratios: P) -> Self {
+    // Formats are picked from the file extensions
+    pub fn new(tasks: P, records: P, tasks_ratios: P, session: P) -> Self {
         Self {
-            tasks,
-            records,
-            tasks_ratios,
+            collections: HashMap::new(),
+            history: None,
+            upgrades: HashMap::new(),
+        }
+        .register::<Task>(tasks)
+        .register::<TaskRecord>(records)
+        .register::<((String, String), f32)>(tasks_ratios)
+        .register::<SessionState>(session)
+    }
+
+    pub fn register<T: Stored>(self, path: P) -> Self {
+        let format = Format::from_path(path.as_ref());
+        self.register_as::<T>(path, format)
+    }
+
+    pub fn register_as<T: Stored>(mut self, path: P, format: Format) -> Self {
+        if TypeId::of::<T>() == TypeId::of::<TaskRecord>() {
+            let upgrades = self.upgrades::<TaskRecord>().to_vec();
+            self.history = (format == Format::JsonLines)
+                .then(|| HistoryLog::new(path.as_ref().to_path_buf()).with_upgrades(upgrades));
+        }
+
+        self.collections
+            .insert(TypeId::of::<T>(), Collection { path, format });
+        self
+    }
+
+    // Upgrades for every schema version of the collection,
+    // the first one moves files from version 1 to 2
+    pub fn with_upgrades<T: Stored>(mut self, upgrades: Vec<Upgrade>) -> Self {
+        if TypeId::of::<T>() == TypeId::of::<TaskRecord>() {
+            self.history = self.history.map(|log| log.with_upgrades(upgrades.clone()));
+        }
+
+        self.upgrades.insert(TypeId::of::<T>(), upgrades);
+        self
+    }
+
+    pub(crate) fn upgrades<T: 'static>(&self) -> &[Upgrade] {
+        self.upgrades
+            .get(&TypeId::of::<T>())
+            .map(|upgrades| upgrades.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn collection<T: 'static>(&self) -> Result<&Collection<P>, StorageError> {
+        self.collections
+            .get(&TypeId::of::<T>())
+            .ok_or(StorageError::Unregistered(type_name::<T>()))
+    }
+
+    pub fn path<T: 'static>(&self) -> Result<&Path, StorageError> {
+        Ok(self.collection::<T>()?.path.as_ref())
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.collections.values().map(|c| c.path.as_ref())
+    }
+
+    pub fn format<T: 'static>(&self) -> Result<Format, StorageError> {
+        Ok(self.collection::<T>()?.format)
+    }
+
+    // Reports changes to the task and ratio files, e.g.
+    // someone editing them by hand
+    pub fn watch_tasks(&self, interval: Duration) -> Result<mpsc::Receiver<PathBuf>, StorageError> {
+        let paths = vec![
+            self.path::<Task>()?.to_path_buf(),
+            self.path::<((String, String), f32)>()?.to_path_buf(),
+        ];
+
+        Ok(watch(paths, interval))
+    }
+
+    // Only there when task history is stored as JSON Lines
+    pub fn history(&self) -> Option<&HistoryLog<PathBuf>> {
+        self.history.as_ref()
+    }
+
+    fn history_log<T: 'static>(&self) -> Option<&HistoryLog<PathBuf>> {
+        self.history
+            .as_ref()
+            .filter(|_| TypeId::of::<T>() == TypeId::of::<TaskRecord>())
+    }
+
+    // JSON Lines files only get the new entries written to
+    // their end, any other format is rewritten as a whole
+    // and task history goes through its log
+    pub fn append<T>(&self, data: &[T]) -> Result<(), StorageError>
+    where
+        T: Stored + Clone,
+    {
+        let collection = self.collection::<T>()?;
+        let path = collection.path.as_ref();
+
+        if let Some(log) = self.history_log::<T>() {
+            return log.append_records(&records_of(Format::JsonLines, data)?);
+        }
+
+        // Locked from the read to the write so nothing stored
+        // in between gets lost
+        let _lock = lock(path, true)?;
+
+        let upgrades = self.upgrades::<T>();
+        let current = schema::current_version(upgrades);
+
+        if collection.format == Format::JsonLines && line_version(path)? == Some(current) {
+            let mut file = OpenOptions::new().read(true).append(true).open(path)?;
+            end_line(&mut file)?;
+            file.write_all(&entry_lines(data)?)?;
+            file.sync_all()?;
+            return Ok(());
         }
+
+        let (mut all, upgraded) = load_collection::<T>(path, collection.format, upgrades)?;
+        if let Some(version) = upgraded {
+            backup(path, version)?;
+        }
+
+        assign_ids(&mut all);
+        all.extend_from_slice(data);
+        write_file(path, &collection.format.encode(&all, current)?)
     }
 }
 
-impl Storable<Task> for FileStorage<&'static str> {
-    // FIX: unwraps
-    fn store(&self, data: &Vec<Task>) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(self.tasks)
-            .unwrap();
+// Missing and empty files are empty collections, files of
+// an older version are upgraded in place
+fn read_collection<T>(
+    path: &Path,
+    format: Format,
+    upgrades: &[Upgrade],
+) -> Result<Vec<T>, StorageError>
+where
+    T: Serialize + DeserializeOwned + 'static,
+{
+    let (data, upgraded) = {
+        let _lock = lock(path, false)?;
+        load_collection(path, format, upgrades)?
+    };
 
-        let data = serde_json::to_string_pretty(&data).unwrap();
-        write!(file, "{}", data).unwrap();
+    if upgraded.is_none() {
+        return Ok(data);
     }
 
-    // FIX: unwraps
-    fn get(&self) -> Vec<Task> {
-        let path = Path::new(self.tasks);
-        if !path.exists() {
-            File::create(&path).unwrap();
+    // Read again under the exclusive lock, someone else may
+    // have upgraded the file in the meantime
+    let _lock = lock(path, true)?;
+    let (data, upgraded) = load_collection(path, format, upgrades)?;
+
+    if let Some(version) = upgraded {
+        backup(path, version)?;
+        write_file(
+            path,
+            &format.encode(&data, schema::current_version(upgrades))?,
+        )?;
+    }
+
+    Ok(data)
+}
+
+// The original of an upgraded file stays next to it
+pub(crate) fn backup(path: &Path, version: u32) -> Result<(), StorageError> {
+    let mut backup: OsString = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    fs::copy(path, PathBuf::from(backup))?;
+    Ok(())
+}
+
+// Version in the first line of a JSON Lines file, none for
+// missing and empty files
+pub(crate) fn line_version(path: &Path) -> Result<Option<u32>, StorageError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            return Ok(Some(schema::header(&line).unwrap_or(FIRST_VERSION)));
         }
+    }
+
+    Ok(None)
+}
+
+// Entries of the file brought up to the current version,
+// along with the version they were upgraded from. Callers
+// hold the lock
+fn load_collection<T>(
+    path: &Path,
+    format: Format,
+    upgrades: &[Upgrade],
+) -> Result<(Vec<T>, Option<u32>), StorageError>
+where
+    T: Serialize + DeserializeOwned + 'static,
+{
+    let buf = match fs::read_to_string(path) {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok((vec![], None)),
+        Err(err) => return Err(err.into()),
+    };
+
+    if buf.trim().is_empty() {
+        return Ok((vec![], None));
+    }
+
+    if format == Format::Csv && TypeId::of::<T>() != TypeId::of::<TaskRecord>() {
+        return Err(StorageError::Unsupported(format, type_name::<T>()));
+    }
 
-        let mut file = File::open(&path).unwrap();
+    let (version, entries) = format.decode_entries(&buf)?;
+    let current = schema::current_version(upgrades);
 
-        let mut buf = String::new();
-        file.read_to_string(&mut buf).unwrap();
-        serde_json::from_str(&buf).unwrap()
+    if version == Some(current) {
+        return Ok((serde_json::from_value(entries)?, None));
     }
+
+    let version = version.unwrap_or(FIRST_VERSION);
+
+    // CSV rows are read through `CsvRecord`, which always
+    // gives records in their current shape
+    let entries = match format {
+        Format::Csv if version > current => {
+            return Err(StorageError::UnsupportedVersion(version, current));
+        }
+        Format::Csv => entries,
+        _ => schema::upgrade(entries, version, upgrades)?,
+    };
+    let data = serde_json::from_value(entries)?;
+
+    Ok((data, Some(version)))
 }
 
-impl Storable<TaskRecord> for FileStorage<&'static str> {
-    // FIX: unwraps
-    fn store(&self, data: &Vec<TaskRecord>) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(self.records)
-            .unwrap();
+fn write_collection<T>(
+    path: &Path,
+    format: Format,
+    version: u32,
+    data: &[T],
+) -> Result<(), StorageError>
+where
+    T: Serialize + 'static,
+{
+    let data = format.encode(data, version)?;
+
+    let _lock = lock(path, true)?;
+    write_file(path, &data)
+}
+
+// Advisory lock held until the returned file is dropped,
+// shared for reading and exclusive for writing. It is
+// taken on a `.lock` file next to the data since writes
+// replace the data file itself
+pub(crate) fn lock(path: &Path, exclusive: bool) -> Result<File, StorageError> {
+    let mut lock_path: OsString = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+
+    if exclusive {
+        file.lock()?;
+    } else {
+        file.lock_shared()?;
+    }
+
+    Ok(file)
+}
+
+// A crash in the middle of an append leaves a truncated
+// last line and a file edited by hand may be missing the
+// final newline. The first is cut off and the second
+// finished, either way appends start on a fresh line
+pub(crate) fn end_line(file: &mut File) -> Result<(), StorageError> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(());
+    }
 
-        let data = serde_json::to_string_pretty(&data).unwrap();
-        write!(file, "{}", data).unwrap();
+    let mut last = [0; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] == b'\n' {
+        return Ok(());
     }
 
-    // FIX: unwraps
-    fn get(&self) -> Vec<TaskRecord> {
-        let path = Path::new(self.records);
-        if !path.exists() {
-            File::create(&path).unwrap();
+    // Read back a chunk at a time until the start of the
+    // last line
+    let mut start = len;
+    let mut tail = Vec::new();
+    while start > 0 {
+        let size = start.min(4096);
+        start -= size;
+
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+
+        if let Some(newline) = tail.iter().rposition(|b| *b == b'\n') {
+            start += newline as u64 + 1;
+            tail.drain(..=newline);
+            break;
+        }
+    }
+
+    if serde_json::from_slice::<Value>(&tail).is_ok() {
+        file.write_all(b"\n")?;
+    } else {
+        file.set_len(start)?;
+    }
+
+    Ok(())
+}
+
+// Written to a temporary file that replaces the original
+// once it is on disk, a crash never leaves half a file
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<(), StorageError> {
+    let mut temp_path: OsString = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path)?;
+
+    // The rename itself only survives a crash once the
+    // directory is synced
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+impl<T, P> Storable<T> for FileStorage<P>
+where
+    T: Stored,
+    P: AsRef<Path>,
+{
+    fn store(&self, data: &[T]) -> Result<(), StorageError> {
+        if let Some(log) = self.history_log::<T>() {
+            return log.store_records(&records_of(Format::JsonLines, data)?);
         }
 
-        let mut file = File::open(&path).unwrap();
+        let collection = self.collection::<T>()?;
+        let version = schema::current_version(self.upgrades::<T>());
+        write_collection(collection.path.as_ref(), collection.format, version, data)
+    }
+
+    fn get(&self) -> Result<Vec<T>, StorageError> {
+        let mut data = self.read::<T>()?;
+
+        // Stored right away so the ids stay the same
+        // between loads
+        if assign_ids(&mut data) {
+            self.store(&data)?;
+        }
 
-        let mut buf = String::new();
-        file.read_to_string(&mut buf).unwrap();
-        serde_json::from_str(&buf).unwrap()
+        Ok(data)
     }
 }
 
-impl Storable<((String, String), f32)> for FileStorage<&'static str> {
-    fn store(&self, data: &Vec<((String, String), f32)>) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(self.tasks_ratios)
-            .unwrap();
+impl<P> FileStorage<P>
+where
+    P: AsRef<Path>,
+{
+    fn read<T>(&self) -> Result<Vec<T>, StorageError>
+    where
+        T: Stored,
+    {
+        if let Some(log) = self.history_log::<T>() {
+            return collection_of(Format::JsonLines, log.read()?);
+        }
 
-        let data = serde_json::to_string_pretty(&data).unwrap();
-        write!(file, "{}", data).unwrap();
+        let collection = self.collection::<T>()?;
+        read_collection(
+            collection.path.as_ref(),
+            collection.format,
+            self.upgrades::<T>(),
+        )
     }
 
-    fn get(&self) -> Vec<((String, String), f32)> {
-        let path = Path::new(self.tasks_ratios);
-        if !path.exists() {
-            File::create(&path).unwrap();
+    // Entries brought up to the current version without
+    // writing them back, callers hold the exclusive lock
+    pub(crate) fn read_locked<T>(&self) -> Result<Vec<T>, StorageError>
+    where
+        T: Stored,
+    {
+        if let Some(log) = self.history_log::<T>() {
+            return collection_of(Format::JsonLines, log.load_current()?);
         }
 
-        let mut file = File::open(&path).unwrap();
+        let collection = self.collection::<T>()?;
+        let (data, _) = load_collection(
+            collection.path.as_ref(),
+            collection.format,
+            self.upgrades::<T>(),
+        )?;
 
-        let mut buf = String::new();
-        file.read_to_string(&mut buf).unwrap();
-        serde_json::from_str(&buf).unwrap()
+        Ok(data)
     }
 }
 
+// Task records written before records had ids, returns
+// whether any were found
+pub(crate) fn assign_ids<T: 'static>(data: &mut [T]) -> bool {
+    let mut assigned = false;
+
+    for item in data {
+        if let Some(record) = (item as &mut dyn Any).downcast_mut::<TaskRecord>()
+            && record.id.is_nil()
+        {
+            record.id = Uuid::new_v4();
+            assigned = true;
+        }
+    }
+
+    assigned
+}
+
 pub trait Storable<T> {
-    fn store(&self, data: &Vec<T>);
-    fn get(&self) -> Vec<T>;
+    fn store(&self, data: &[T]) -> Result<(), StorageError>;
+    fn get(&self) -> Result<Vec<T>, StorageError>;
 }
@@ -1,13 +1,71 @@
-use chrono::{DateTime, Utc};
+use crate::explanation::ScheduleExplanation;
+use crate::interruption::Interruption;
+use crate::storage::Stored;
+use chrono::{DateTime, TimeDelta, Utc};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::time::Duration;
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct TaskRecord {
+    // Nil in records written before they had ids, storage
+    // assigns them one on load
+    #[serde(default)]
+    pub id: Uuid,
     pub origin_name: String,
     pub origin_group: String,
     pub time: Duration,
+    // Time the block was scheduled for, `time` is what
+    // was actually spent on it
+    #[serde(default)]
+    pub planned: Option<Duration>,
+    #[serde(default)]
+    pub started: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub finished: Option<DateTime<Utc>>,
+    // Already taken out of `time`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interruptions: Vec<Interruption>,
+}
+
+impl Stored for TaskRecord {}
+
+impl TaskRecord {
+    // Time logged by hand instead of through a block,
+    // e.g. "40 min of reading this morning"
+    pub fn manual(
+        name: impl Into<String>,
+        group: impl Into<String>,
+        time: Duration,
+        finished: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            origin_name: name.into(),
+            origin_group: group.into(),
+            time,
+            planned: None,
+            started: TimeDelta::from_std(time)
+                .ok()
+                .and_then(|delta| finished.checked_sub_signed(delta)),
+            finished: Some(finished),
+            interruptions: vec![],
+        }
+    }
+
+    // Record of a block that ran from `started` until now
+    pub fn completed(task: ScheduleTask, started: DateTime<Utc>) -> Self {
+        let finished = Utc::now();
+
+        Self {
+            time: (finished - started).to_std().unwrap_or_default(),
+            started: Some(started),
+            finished: Some(finished),
+            ..Self::from(task)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -15,14 +73,21 @@ pub struct ScheduleTask {
     pub origin_name: String,
     pub origin_group: String,
     pub time: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<ScheduleExplanation>,
 }
 
 impl From<ScheduleTask> for TaskRecord {
     fn from(value: ScheduleTask) -> Self {
         Self {
+            id: Uuid::new_v4(),
             origin_name: value.origin_name.clone(),
             origin_group: value.origin_group.clone(),
             time: value.time.clone(),
+            planned: Some(value.time),
+            started: None,
+            finished: None,
+            interruptions: vec![],
         }
     }
 }
@@ -33,6 +98,29 @@ impl From<TaskRecord> for ScheduleTask {
             origin_name: value.origin_name.clone(),
             origin_group: value.origin_group.clone(),
             time: value.time.clone(),
+            explanation: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    NotFound(Uuid),
+    // Only records of the same task can be merged
+    DifferentTasks,
+    // Split point has to fall inside the record
+    InvalidSplit,
+    // A record can't be merged into itself
+    SameRecord,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::NotFound(id) => write!(f, "Record {} not found", id),
+            RecordError::DifferentTasks => write!(f, "Records belong to different tasks"),
+            RecordError::InvalidSplit => write!(f, "Split point is outside the record"),
+            RecordError::SameRecord => write!(f, "Can't merge a record with itself"),
         }
     }
 }
@@ -48,6 +136,8 @@ pub struct Task {
     pub closed: Option<DateTime<Utc>>,
 }
 
+impl Stored for Task {}
+
 impl Task {
     pub fn new(name: &'static str, group: &'static str, config: TaskConfiguration) -> Self {
         Self {
@@ -69,4 +159,7 @@ pub struct TaskConfiguration {
     pub time: Duration,
     #[derivative(Default(value = "true"))]
     pub repeat: bool,
+    // Total time the task needs before it is done
+    #[serde(default)]
+    pub effort: Option<Duration>,
 }
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::Faults;
+use crate::cache::client::CacheStorage;
+
+// Time as the cache sees it, it only moves when told to
+#[derive(Clone)]
+pub struct Clock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl Clock {
+    pub fn starting_at(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+
+    pub fn advance(&self, by: TimeDelta) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::starting_at(DateTime::UNIX_EPOCH)
+    }
+}
+
+struct Entry {
+    value: String,
+    expires: Option<DateTime<Utc>>,
+}
+
+// A `CacheStorage` whose expirations follow a `Clock`, so
+// sessions running out can be tested without waiting. Clones
+// share their entries, clock and faults
+#[derive(Clone, Default)]
+pub struct MemoryCache {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    clock: Clock,
+    faults: Faults<()>,
+}
+
+impl MemoryCache {
+    pub fn with_clock(clock: Clock) -> Self {
+        Self {
+            clock,
+            ..Self::default()
+        }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    // A failing `get` finds nothing and a failing `delete`
+    // leaves the key in place
+    pub fn faults(&self) -> &Faults<()> {
+        &self.faults
+    }
+}
+
+#[async_trait]
+impl CacheStorage for MemoryCache {
+    async fn connect() -> Self {
+        Self::default()
+    }
+
+    async fn set(&self, key: String, value: String) -> Result<(), ()> {
+        self.faults.check("set").await?;
+
+        self.entries.lock().unwrap().insert(
+            key,
+            Entry {
+                value,
+                expires: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn expire(&self, key: String, time: TimeDelta) -> Result<(), ()> {
+        self.faults.check("expire").await?;
+
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&key) {
+            entry.expires = Some(self.clock.now() + time);
+        }
+
+        Ok(())
+    }
+
+    async fn get<'s>(&self, key: &'s str) -> Option<String> {
+        self.faults.check("get").await.ok()?;
+
+        let mut entries = self.entries.lock().unwrap();
+        let expired = entries
+            .get(key)?
+            .expires
+            .is_some_and(|expires| expires <= self.clock.now());
+
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+
+        entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    async fn delete<'s>(&self, key: &'s str) {
+        if self.faults.check("delete").await.is_ok() {
+            self.entries.lock().unwrap().remove(key);
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use sqlx::migrate::MigrateError;
+use uuid::Uuid;
+
+use super::Faults;
+use crate::database::{
+    client::{
+        Database, DatabaseAudit, DatabaseSchedule, DatabaseUser, DatabaseWebhook, Transaction,
+    },
+    config::DatabaseConfiguration,
+    data::{AuditAction, AuditContext, AuditEntry, Credentials, User, Webhook, WebhookDelivery},
+    error::Error,
+    migration::MigrationStatus,
+};
+use crate::goal::Goal;
+use crate::session::SessionState;
+use crate::task::{Task, TaskRecord};
+
+type Ratios = Vec<((String, String), f32)>;
+
+#[derive(Clone, Default)]
+struct Tables {
+    users: HashMap<Uuid, String>,
+    // By user id
+    credentials: HashMap<Uuid, Credentials>,
+    webhooks: Vec<Webhook>,
+    deliveries: Vec<WebhookDelivery>,
+    tasks: HashMap<Uuid, Vec<Task>>,
+    ratios: HashMap<Uuid, Ratios>,
+    // With their positions, like the real tables
+    records: HashMap<Uuid, Vec<(usize, TaskRecord)>>,
+    goals: HashMap<Uuid, Vec<Goal>>,
+    sessions: HashMap<Uuid, SessionState>,
+    audit: Vec<AuditEntry>,
+}
+
+impl Tables {
+    fn user(&self, user_id: Uuid) -> Result<(), Error> {
+        if self.users.contains_key(&user_id) {
+            Ok(())
+        } else {
+            Err(Error::Message(format!("Unknown user {}", user_id)))
+        }
+    }
+
+    // Emails and usernames are unique across users
+    fn taken(&self, user_id: Uuid, email: Option<&str>, username: Option<&str>) -> bool {
+        self.credentials.values().any(|creds| {
+            creds.user_id != user_id
+                && (email == Some(creds.email.as_str())
+                    || username == Some(creds.username.as_str()))
+        })
+    }
+
+    fn user_by(&self, find: impl Fn(&Credentials) -> bool) -> Result<User, Error> {
+        let creds = self
+            .credentials
+            .values()
+            .find(|creds| find(creds))
+            .ok_or(Error::DB(sqlx::Error::RowNotFound))?;
+
+        Ok(User {
+            id: creds.user_id,
+            name: self.users[&creds.user_id].clone(),
+        })
+    }
+
+    fn update(&mut self, user_id: Uuid, edit: impl FnOnce(&mut Credentials)) -> Result<(), Error> {
+        let creds = self
+            .credentials
+            .get_mut(&user_id)
+            .ok_or(Error::DbNoEffect)?;
+
+        edit(creds);
+        creds.updated_at = chrono::Utc::now();
+
+        Ok(())
+    }
+}
+
+// A write as it can be applied again, to replay the writes
+// of a transaction when it commits
+type Write = Arc<dyn Fn(&mut Tables) -> Result<(), Error> + Send + Sync>;
+
+// What a transaction commits to
+#[derive(Clone)]
+struct Pending {
+    committed: Arc<Mutex<Tables>>,
+    writes: Arc<Mutex<Vec<Write>>>,
+}
+
+// A `Database` kept in memory that behaves like the real
+// backends, for testing code built on `AppState` without a
+// pool. Clones share their tables and faults
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    tables: Arc<Mutex<Tables>>,
+    faults: Faults<Error>,
+    // Only set for a transaction
+    pending: Option<Pending>,
+}
+
+impl MemoryDatabase {
+    pub fn faults(&self) -> &Faults<Error> {
+        &self.faults
+    }
+
+    async fn read<T>(
+        &self,
+        call: &'static str,
+        read: impl FnOnce(&Tables) -> T,
+    ) -> Result<T, Error> {
+        self.faults.check(call).await?;
+
+        Ok(read(&self.tables.lock().unwrap()))
+    }
+
+    // Every write either changes the tables as a whole or
+    // not at all
+    async fn write(
+        &self,
+        call: &'static str,
+        write: impl Fn(&mut Tables) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.faults.check(call).await?;
+
+        let mut tables = self.tables.lock().unwrap();
+        let mut changed = tables.clone();
+        write(&mut changed)?;
+        *tables = changed;
+
+        if let Some(pending) = &self.pending {
+            pending.writes.lock().unwrap().push(Arc::new(write));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Database for MemoryDatabase {
+    async fn connect(_config: &DatabaseConfiguration) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+
+    // There is no schema to manage
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        Ok(())
+    }
+
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        Ok(vec![])
+    }
+
+    async fn revert(&self, _version: i64) -> Result<(), MigrateError> {
+        Ok(())
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>, Error> {
+        self.faults.check("begin").await?;
+
+        let tables = self.tables.lock().unwrap().clone();
+
+        Ok(Box::new(Self {
+            tables: Arc::new(Mutex::new(tables)),
+            faults: self.faults.clone(),
+            pending: Some(Pending {
+                committed: self.tables.clone(),
+                writes: Arc::new(Mutex::new(vec![])),
+            }),
+        }))
+    }
+}
+
+// The transaction works on a copy of the tables, its writes
+// are replayed on commit so changes made next to it are kept.
+// If one of them no longer applies nothing is committed
+#[async_trait]
+impl Transaction for MemoryDatabase {
+    async fn commit(self: Box<Self>) -> Result<(), Error> {
+        self.faults.check("commit").await?;
+
+        let Some(pending) = &self.pending else {
+            return Ok(());
+        };
+
+        let mut committed = pending.committed.lock().unwrap();
+        let mut changed = committed.clone();
+        for write in pending.writes.lock().unwrap().iter() {
+            write(&mut changed)?;
+        }
+        *committed = changed;
+
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<(), Error> {
+        self.faults.check("rollback").await
+    }
+}
+
+#[async_trait]
+impl DatabaseUser for MemoryDatabase {
+    async fn create_user(
+        &self,
+        user: User,
+        creds: Credentials,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let entry = AuditEntry::new(user.id, AuditAction::UserCreated, context)
+            .change(None, Some(creds.summary()));
+
+        self.write("create_user", move |tables| {
+            if tables.users.contains_key(&user.id)
+                || tables.taken(user.id, Some(&creds.email), Some(&creds.username))
+            {
+                return Err(Error::Message(String::from("User already exists")));
+            }
+
+            tables.users.insert(user.id, user.name.clone());
+            tables.credentials.insert(user.id, creds.clone());
+            tables.audit.push(entry.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_by_email<'s>(&self, email: &'s str) -> Result<User, Error> {
+        self.read("get_user_by_email", |tables| {
+            tables.user_by(|creds| creds.email == email)
+        })
+        .await?
+    }
+
+    async fn get_user_by_username<'s>(&self, username: &'s str) -> Result<User, Error> {
+        self.read("get_user_by_username", |tables| {
+            tables.user_by(|creds| creds.username == username)
+        })
+        .await?
+    }
+
+    async fn get_user_creds<'s>(&self, user_id: &'s str) -> Result<Credentials, Error> {
+        let user_id = Uuid::parse_str(user_id).map_err(Error::Uuid)?;
+
+        self.read("get_user_creds", |tables| {
+            tables
+                .credentials
+                .get(&user_id)
+                .cloned()
+                .ok_or(Error::DB(sqlx::Error::RowNotFound))
+        })
+        .await?
+    }
+
+    async fn update_user_email(
+        &self,
+        user_id: Uuid,
+        email: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let entry = AuditEntry::new(user_id, AuditAction::EmailChanged, context);
+
+        self.write("update_user_email", move |tables| {
+            if tables.taken(user_id, Some(&email), None) {
+                return Err(Error::Message(String::from("Email is taken")));
+            }
+
+            let mut before = None;
+            tables.update(user_id, |creds| {
+                before = Some(std::mem::replace(&mut creds.email, email.clone()));
+            })?;
+            tables
+                .audit
+                .push(entry.clone().change(before, Some(email.clone())));
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_user_username(
+        &self,
+        user_id: Uuid,
+        username: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let entry = AuditEntry::new(user_id, AuditAction::UsernameChanged, context);
+
+        self.write("update_user_username", move |tables| {
+            if tables.taken(user_id, None, Some(&username)) {
+                return Err(Error::Message(String::from("Username is taken")));
+            }
+
+            let mut before = None;
+            tables.update(user_id, |creds| {
+                before = Some(std::mem::replace(&mut creds.username, username.clone()));
+            })?;
+            tables
+                .audit
+                .push(entry.clone().change(before, Some(username.clone())));
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_user_password_hash(
+        &self,
+        user_id: Uuid,
+        password_hash: String,
+        context: &AuditContext,
+    ) -> Result<(), Error> {
+        let entry = AuditEntry::new(user_id, AuditAction::PasswordChanged, context);
+
+        self.write("update_user_password_hash", move |tables| {
+            tables.update(user_id, |creds| creds.password_hash = password_hash.clone())?;
+            tables.audit.push(entry.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    // Everything of the user goes with it, except for the
+    // audit trail
+    async fn delete_user(&self, user_id: Uuid, context: &AuditContext) -> Result<(), Error> {
+        let entry = AuditEntry::new(user_id, AuditAction::UserDeleted, context);
+
+        self.write("delete_user", move |tables| {
+            tables.users.remove(&user_id).ok_or(Error::DbNoEffect)?;
+            let before = tables.credentials.remove(&user_id).map(|c| c.summary());
+            tables.audit.push(entry.clone().change(before, None));
+
+            let (removed, kept) = tables
+                .webhooks
+                .drain(..)
+                .partition(|webhook| webhook.user_id == user_id);
+            tables.webhooks = kept;
+            tables.deliveries.retain(|delivery| {
+                !removed
+                    .iter()
+                    .any(|webhook: &Webhook| webhook.id == delivery.webhook_id)
+            });
+
+            tables.tasks.remove(&user_id);
+            tables.ratios.remove(&user_id);
+            tables.records.remove(&user_id);
+            tables.goals.remove(&user_id);
+            tables.sessions.remove(&user_id);
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DatabaseWebhook for MemoryDatabase {
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error> {
+        self.write("create_webhook", move |tables| {
+            tables.user(webhook.user_id)?;
+            tables.webhooks.push(webhook.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_webhooks(&self, user_id: Uuid) -> Result<Vec<Webhook>, Error> {
+        self.read("get_user_webhooks", |tables| {
+            tables
+                .webhooks
+                .iter()
+                .filter(|webhook| webhook.user_id == user_id)
+                .cloned()
+                .collect()
+        })
+        .await
+    }
+
+    async fn delete_webhook(&self, user_id: Uuid, webhook_id: Uuid) -> Result<(), Error> {
+        self.write("delete_webhook", move |tables| {
+            let before = tables.webhooks.len();
+            tables
+                .webhooks
+                .retain(|webhook| webhook.id != webhook_id || webhook.user_id != user_id);
+
+            if tables.webhooks.len() == before {
+                return Err(Error::DbNoEffect);
+            }
+
+            tables
+                .deliveries
+                .retain(|delivery| delivery.webhook_id != webhook_id);
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn log_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        self.write("log_webhook_delivery", move |tables| {
+            if !tables
+                .webhooks
+                .iter()
+                .any(|webhook| webhook.id == delivery.webhook_id)
+            {
+                return Err(Error::Message(format!(
+                    "Unknown webhook {}",
+                    delivery.webhook_id
+                )));
+            }
+
+            tables.deliveries.push(delivery.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        self.read("get_webhook_deliveries", |tables| {
+            let mut deliveries: Vec<WebhookDelivery> = tables
+                .deliveries
+                .iter()
+                .filter(|delivery| delivery.webhook_id == webhook_id)
+                .cloned()
+                .collect();
+            deliveries.sort_by_key(|delivery| delivery.created_at);
+
+            deliveries
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DatabaseSchedule for MemoryDatabase {
+    async fn get_user_tasks(&self, user_id: Uuid) -> Result<Vec<Task>, Error> {
+        self.read("get_user_tasks", |tables| {
+            let mut tasks = tables.tasks.get(&user_id).cloned().unwrap_or_default();
+            tasks.sort_by_key(|task| task.created);
+
+            tasks
+        })
+        .await
+    }
+
+    async fn set_user_tasks(&self, user_id: Uuid, tasks: Vec<Task>) -> Result<(), Error> {
+        self.write("set_user_tasks", move |tables| {
+            tables.user(user_id)?;
+            tables.tasks.insert(user_id, tasks.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_ratios(&self, user_id: Uuid) -> Result<Vec<((String, String), f32)>, Error> {
+        self.read("get_user_ratios", |tables| {
+            tables.ratios.get(&user_id).cloned().unwrap_or_default()
+        })
+        .await
+    }
+
+    async fn set_user_ratios(
+        &self,
+        user_id: Uuid,
+        ratios: Vec<((String, String), f32)>,
+    ) -> Result<(), Error> {
+        self.write("set_user_ratios", move |tables| {
+            tables.user(user_id)?;
+            tables.ratios.insert(user_id, ratios.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_records(&self, user_id: Uuid) -> Result<Vec<TaskRecord>, Error> {
+        self.read("get_user_records", |tables| {
+            let mut records = tables.records.get(&user_id).cloned().unwrap_or_default();
+            records.sort_by_key(|(position, _)| *position);
+
+            records.into_iter().map(|(_, record)| record).collect()
+        })
+        .await
+    }
+
+    async fn set_user_records(&self, user_id: Uuid, records: Vec<TaskRecord>) -> Result<(), Error> {
+        self.write("set_user_records", move |tables| {
+            tables.user(user_id)?;
+            tables
+                .records
+                .insert(user_id, records.iter().cloned().enumerate().collect());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_user_records(
+        &self,
+        user_id: Uuid,
+        changed: Vec<(usize, TaskRecord)>,
+        deleted: Vec<Uuid>,
+    ) -> Result<(), Error> {
+        self.write("update_user_records", move |tables| {
+            tables.user(user_id)?;
+            let records = tables.records.entry(user_id).or_default();
+            records.retain(|(_, record)| !deleted.contains(&record.id));
+
+            for (position, record) in &changed {
+                let record = (*position, record.clone());
+                match records.iter_mut().find(|(_, r)| r.id == record.1.id) {
+                    Some(stored) => *stored = record,
+                    None => records.push(record),
+                }
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_goals(&self, user_id: Uuid) -> Result<Vec<Goal>, Error> {
+        self.read("get_user_goals", |tables| {
+            tables.goals.get(&user_id).cloned().unwrap_or_default()
+        })
+        .await
+    }
+
+    async fn set_user_goals(&self, user_id: Uuid, goals: Vec<Goal>) -> Result<(), Error> {
+        self.write("set_user_goals", move |tables| {
+            tables.user(user_id)?;
+            tables.goals.insert(user_id, goals.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_session(&self, user_id: Uuid) -> Result<Option<SessionState>, Error> {
+        self.read("get_user_session", |tables| {
+            tables.sessions.get(&user_id).cloned()
+        })
+        .await
+    }
+
+    async fn set_user_session(&self, user_id: Uuid, state: SessionState) -> Result<(), Error> {
+        self.write("set_user_session", move |tables| {
+            tables.user(user_id)?;
+            tables.sessions.insert(user_id, state.clone());
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DatabaseAudit for MemoryDatabase {
+    async fn log_audit(&self, entry: AuditEntry) -> Result<(), Error> {
+        self.write("log_audit", move |tables| {
+            tables.audit.push(entry.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_audit(&self, user_id: Uuid, limit: u32) -> Result<Vec<AuditEntry>, Error> {
+        self.read("get_user_audit", |tables| {
+            let mut entries: Vec<AuditEntry> = tables
+                .audit
+                .iter()
+                .filter(|entry| entry.user_id == user_id)
+                .cloned()
+                .collect();
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.created_at));
+            entries.truncate(limit as usize);
+
+            entries
+        })
+        .await
+    }
+}
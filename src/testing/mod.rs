@@ -0,0 +1,87 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub mod cache;
+pub mod database;
+
+struct FaultState<E> {
+    // Errors in the order they are handed out, limited to one
+    // call or for whichever call comes next
+    failures: VecDeque<(Option<&'static str>, E)>,
+    latency: Duration,
+}
+
+// Errors and delays to inject into the calls of a test double,
+// shared between every clone so a test can keep one around
+pub struct Faults<E> {
+    state: Arc<Mutex<FaultState<E>>>,
+}
+
+impl<E> Faults<E> {
+    // The next call fails with `error`
+    pub fn fail_next(&self, error: E) {
+        self.state.lock().unwrap().failures.push_back((None, error));
+    }
+
+    // The next call of the method named `call` fails with
+    // `error`, other calls go through
+    pub fn fail_next_call(&self, call: &'static str, error: E) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .push_back((Some(call), error));
+    }
+
+    // Every call waits this long before it runs
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures.clear();
+        state.latency = Duration::ZERO;
+    }
+
+    pub(crate) async fn check(&self, call: &'static str) -> Result<(), E> {
+        let latency = self.state.lock().unwrap().latency;
+
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let position = state
+            .failures
+            .iter()
+            .position(|(target, _)| target.is_none_or(|target| target == call));
+
+        match position.and_then(|i| state.failures.remove(i)) {
+            Some((_, error)) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<E> Default for Faults<E> {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FaultState {
+                failures: VecDeque::new(),
+                latency: Duration::ZERO,
+            })),
+        }
+    }
+}
+
+impl<E> Clone for Faults<E> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::AppState;
+use crate::database::data::{AuditAction, AuditContext, AuditEntry};
+use crate::database::error::Error;
+use crate::live::LiveError;
+use crate::schedule::ExpectedRatioTasks;
+use crate::server::tasks::{summarize_ratios, summarize_tasks};
+use crate::storage::{FileStorage, Storable, StorageError};
+use crate::task::Task;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use uuid::Uuid;
+
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Polls the modification times and sends the path of every
+// changed file, our own writes are reported as well. Stops
+// once the receiver is dropped
+pub fn watch(paths: Vec<PathBuf>, interval: Duration) -> mpsc::Receiver<PathBuf> {
+    let (sender, receiver) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let mut modified: Vec<Option<SystemTime>> = paths.iter().map(|p| modified_at(p)).collect();
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            for (path, last) in paths.iter().zip(modified.iter_mut()) {
+                let current = modified_at(path);
+
+                if current == *last {
+                    continue;
+                }
+
+                *last = current;
+
+                if sender.send(path.clone()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+// Stores tasks and ratios whenever their files change and
+// reloads them into the user's live session, until the
+// session is gone
+pub fn reload_live<P>(state: Arc<AppState>, user_id: Uuid, storage: FileStorage<P>)
+where
+    P: AsRef<Path> + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let Some(mut events) = state.live.subscribe(user_id).await else {
+            return;
+        };
+        let Ok(mut changes) = storage.watch_tasks(WATCH_INTERVAL) else {
+            return;
+        };
+        let storage = Arc::new(storage);
+
+        loop {
+            let path = tokio::select! {
+                path = changes.recv() => match path {
+                    Some(path) => path,
+                    None => return,
+                },
+                event = events.recv() => match event {
+                    Err(RecvError::Closed) => return,
+                    _ => continue,
+                },
+            };
+
+            if let Err(e) = reload(&state, user_id, storage.clone()).await {
+                log::warn!("Failed to reload {}: {}", path.display(), e);
+            }
+        }
+    });
+}
+
+async fn reload<P>(
+    state: &AppState,
+    user_id: Uuid,
+    storage: Arc<FileStorage<P>>,
+) -> Result<(), Error>
+where
+    P: AsRef<Path> + Send + Sync + 'static,
+{
+    // Reading takes the file locks
+    let (tasks, ratios) = tokio::task::spawn_blocking(move || {
+        let tasks: Vec<Task> = storage.get()?;
+        let ratios: Vec<((String, String), f32)> = storage.get()?;
+        Ok::<_, StorageError>((tasks, ratios))
+    })
+    .await
+    .map_err(|e| Error::Message(e.to_string()))?
+    .map_err(|e| Error::Message(e.to_string()))?;
+
+    let expected = ExpectedRatioTasks::from_ratios(tasks.clone(), &ratios)
+        .map_err(|e| Error::Message(e.to_string()))?;
+
+    let context = AuditContext::default().with_actor(user_id);
+    let transaction = state.database.begin().await?;
+    let tasks_before = transaction.get_user_tasks(user_id).await?;
+    let ratios_before = transaction.get_user_ratios(user_id).await?;
+
+    transaction.set_user_tasks(user_id, tasks.clone()).await?;
+    transaction.set_user_ratios(user_id, ratios.clone()).await?;
+    transaction
+        .log_audit(
+            AuditEntry::new(user_id, AuditAction::TasksChanged, &context).change(
+                Some(summarize_tasks(&tasks_before)),
+                Some(summarize_tasks(&tasks)),
+            ),
+        )
+        .await?;
+    transaction
+        .log_audit(
+            AuditEntry::new(user_id, AuditAction::RatiosChanged, &context).change(
+                Some(summarize_ratios(&ratios_before)),
+                Some(summarize_ratios(&ratios)),
+            ),
+        )
+        .await?;
+    transaction.commit().await?;
+
+    // Without a live session the next start picks them up
+    match state
+        .live
+        .edit_history(user_id, move |scheduler| scheduler.tasks = expected)
+        .await
+    {
+        Ok(()) | Err(LiveError::NotAttached) => Ok(()),
+        Err(e) => Err(Error::Message(e.to_string())),
+    }
+}
@@ -0,0 +1,181 @@
+use crate::AppState;
+use crate::database::data::{WEBHOOK_EVENTS, Webhook, WebhookDelivery};
+use crate::live::LiveEvent;
+use chrono::Utc;
+use derivative::Derivative;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use uuid::Uuid;
+
+pub const SIGNATURE_HEADER: &str = "X-Scheduler-Signature";
+pub const EVENT_HEADER: &str = "X-Scheduler-Event";
+pub const DELIVERY_HEADER: &str = "X-Scheduler-Delivery";
+// Unix seconds the signature was made at
+pub const TIMESTAMP_HEADER: &str = "X-Scheduler-Timestamp";
+
+// Webhooks added or removed while a session is live are
+// picked up after at most this long
+pub const WEBHOOK_REFRESH: Duration = Duration::from_secs(30);
+
+#[derive(Derivative)]
+#[derivative(Debug, Clone, Default)]
+pub struct RetryPolicy {
+    #[derivative(Default(value = "5"))]
+    pub attempts: u32,
+    // Doubled after every failed attempt
+    #[derivative(Default(value = "Duration::from_secs(1)"))]
+    pub backoff: Duration,
+    #[derivative(Default(value = "Duration::from_secs(60)"))]
+    pub max_backoff: Duration,
+    #[derivative(Default(value = "Duration::from_secs(10)"))]
+    pub timeout: Duration,
+}
+
+// Hex encoded HMAC-SHA256 of `timestamp.body`, receivers
+// compute the same with their secret to check the sender and
+// reject old timestamps so deliveries can't be replayed
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    policy: RetryPolicy,
+}
+
+impl WebhookDispatcher {
+    pub fn new(policy: RetryPolicy) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(policy.timeout)
+            .build()
+            .unwrap_or_default();
+
+        Self { client, policy }
+    }
+
+    // Sends the event until the receiver answers with a
+    // success status or the attempts run out, every
+    // attempt is written to the delivery log
+    pub async fn deliver(&self, state: &AppState, webhook: &Webhook, event: &str, payload: &str) {
+        let delivery_id = Uuid::new_v4();
+        let mut backoff = self.policy.backoff;
+
+        for attempt in 1..=self.policy.attempts {
+            // Signed again so retries carry a fresh timestamp
+            let timestamp = Utc::now().timestamp();
+            let signature = sign(&webhook.secret, timestamp, payload.as_bytes());
+
+            let response = self
+                .client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(EVENT_HEADER, event)
+                .header(DELIVERY_HEADER, delivery_id.to_string())
+                .body(payload.to_string())
+                .send()
+                .await;
+
+            let (status_code, error) = match &response {
+                Ok(response) if response.status().is_success() => {
+                    (Some(response.status().as_u16()), None)
+                }
+                Ok(response) => (
+                    Some(response.status().as_u16()),
+                    Some(format!("Receiver answered {}", response.status())),
+                ),
+                Err(err) => (None, Some(err.to_string())),
+            };
+
+            let failed = error.is_some();
+
+            let delivery = WebhookDelivery {
+                id: Uuid::new_v4(),
+                webhook_id: webhook.id,
+                event: event.to_string(),
+                payload: payload.to_string(),
+                attempt,
+                status_code,
+                error,
+                created_at: Utc::now(),
+            };
+
+            if let Err(err) = state.database.log_webhook_delivery(delivery).await {
+                log::warn!("Failed to log webhook delivery: {}", err);
+            }
+
+            if !failed {
+                return;
+            }
+
+            if attempt < self.policy.attempts {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(self.policy.max_backoff);
+            }
+        }
+    }
+}
+
+// Delivers the live events of a user to every webhook
+// subscribed to them until the session is detached
+pub async fn forward(state: Arc<AppState>, user_id: Uuid, mut receiver: Receiver<LiveEvent>) {
+    let dispatcher = Arc::new(WebhookDispatcher::new(RetryPolicy::default()));
+    let mut cached: Option<(Instant, Vec<Webhook>)> = None;
+
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+
+        // Ticks and anything else no webhook can subscribe to
+        // never reach the database
+        let kind = event.kind();
+        if !WEBHOOK_EVENTS.contains(&kind) {
+            continue;
+        }
+
+        let stale = cached
+            .as_ref()
+            .is_none_or(|(loaded, _)| loaded.elapsed() >= WEBHOOK_REFRESH);
+
+        if stale {
+            match state.database.get_user_webhooks(user_id).await {
+                Ok(webhooks) => cached = Some((Instant::now(), webhooks)),
+                // The last list that loaded is still good enough
+                Err(err) => log::warn!("Failed to load webhooks of {}: {}", user_id, err),
+            }
+        }
+
+        let Some((_, webhooks)) = &cached else {
+            continue;
+        };
+
+        let Ok(payload) = serde_json::to_string(&event) else {
+            continue;
+        };
+
+        for webhook in webhooks.iter().filter(|w| w.wants(kind)) {
+            let webhook = webhook.clone();
+            let state = state.clone();
+            let dispatcher = dispatcher.clone();
+            let payload = payload.clone();
+
+            // Retries of a slow receiver must not hold back the others
+            tokio::spawn(async move {
+                dispatcher.deliver(&state, &webhook, kind, &payload).await;
+            });
+        }
+    }
+}
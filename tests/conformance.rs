@@ -0,0 +1,761 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use scheduler::database::{
+    client::{Database, DatabaseUser},
+    config::DatabaseConfiguration,
+    data::{AuditAction, AuditContext, AuditEntry, Credentials, User, Webhook, WebhookDelivery},
+    error::Error,
+    postgres::Postgres,
+    sqlite::Sqlite,
+};
+use scheduler::goal::{Goal, GoalScope};
+use scheduler::session::{ActiveBlock, SessionState};
+use scheduler::task::{ScheduleTask, Task, TaskConfiguration, TaskRecord};
+use std::time::Duration;
+use uuid::Uuid;
+
+// The same checks for every backend, so SQLite and Postgres
+// can't drift apart. Everything is created with fresh ids and
+// removed again, the suite can run against a shared database
+async fn run(database: &dyn Database) -> Result<(), String> {
+    let first = create_user(database).await?;
+    let second = create_user(database).await?;
+
+    let result = async {
+        users(database, &first, &second).await?;
+        webhooks(database, &first, &second).await?;
+        schedule(database, &first, &second).await?;
+        transactions(database, &second).await?;
+        audit(database, &first).await
+    }
+    .await;
+
+    // Only fails if a check already deleted the user
+    let _ = database
+        .delete_user(first.id, &AuditContext::default())
+        .await;
+    let _ = database
+        .delete_user(second.id, &AuditContext::default())
+        .await;
+
+    result
+}
+
+#[tokio::test]
+async fn sqlite() {
+    let database = Sqlite::connect(&DatabaseConfiguration::in_memory())
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+
+    run(&database).await.unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn memory() {
+    let database = scheduler::testing::database::MemoryDatabase::default();
+
+    run(&database).await.unwrap();
+}
+
+// Needs a server, only runs when DATABASE_URL points to one
+#[tokio::test]
+async fn postgres() {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set, skipping Postgres");
+        return;
+    };
+
+    let database = Postgres::connect(&DatabaseConfiguration::file(url))
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+
+    run(&database).await.unwrap();
+}
+
+fn ensure(condition: bool, check: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(format!("Check failed: {}", check))
+    }
+}
+
+fn failed(check: &str) -> impl Fn(Error) -> String + '_ {
+    move |err| format!("{} failed: {}", check, err)
+}
+
+// Postgres keeps microseconds, so anything stored is
+// truncated to what both backends can round-trip
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+struct Account {
+    id: Uuid,
+    email: String,
+    username: String,
+}
+
+async fn create_user<D>(database: &D) -> Result<Account, String>
+where
+    D: DatabaseUser + Sync + ?Sized,
+{
+    create_user_with_id(database, Uuid::new_v4()).await
+}
+
+async fn create_user_with_id<D>(database: &D, id: Uuid) -> Result<Account, String>
+where
+    D: DatabaseUser + Sync + ?Sized,
+{
+    let user = User {
+        id,
+        name: String::from("Conformance"),
+    };
+    let suffix = Uuid::new_v4().simple().to_string();
+
+    let account = Account {
+        id: user.id,
+        email: format!("{}@conformance.test", suffix),
+        username: format!("conformance-{}", suffix),
+    };
+
+    let creds = Credentials::new(user.id, account.email.clone(), account.username.clone())
+        .add_password_and_salt("conformance".as_bytes())
+        .map_err(failed("Hashing password"))?;
+
+    database
+        .create_user(user, creds, &AuditContext::default())
+        .await
+        .map_err(failed("Creating user"))?;
+
+    Ok(account)
+}
+
+async fn users(database: &dyn Database, first: &Account, second: &Account) -> Result<(), String> {
+    let user = database
+        .get_user_by_email(&first.email)
+        .await
+        .map_err(failed("Getting user by email"))?;
+    ensure(user.id == first.id, "user by email")?;
+    ensure(user.name == "Conformance", "user name")?;
+
+    let user = database
+        .get_user_by_username(&second.username)
+        .await
+        .map_err(failed("Getting user by username"))?;
+    ensure(user.id == second.id, "user by username")?;
+
+    ensure(
+        database
+            .get_user_by_email("missing@conformance.test")
+            .await
+            .is_err(),
+        "missing user",
+    )?;
+
+    let creds = database
+        .get_user_creds(&first.id.to_string())
+        .await
+        .map_err(failed("Getting credentials"))?;
+    ensure(creds.user_id == first.id, "credentials owner")?;
+    ensure(creds.username == first.username, "credentials username")?;
+    ensure(
+        creds
+            .check_password("conformance".as_bytes())
+            .unwrap_or(false),
+        "password",
+    )?;
+
+    let email = format!("renamed-{}", first.email);
+    database
+        .update_user_email(first.id, email.clone(), &AuditContext::default())
+        .await
+        .map_err(failed("Updating email"))?;
+    let user = database
+        .get_user_by_email(&email)
+        .await
+        .map_err(failed("Getting user by new email"))?;
+    ensure(user.id == first.id, "updated email")?;
+
+    // Usernames are unique across users
+    ensure(
+        database
+            .update_user_username(second.id, first.username.clone(), &AuditContext::default())
+            .await
+            .is_err(),
+        "duplicate username",
+    )?;
+
+    ensure(
+        matches!(
+            database
+                .update_user_username(
+                    Uuid::new_v4(),
+                    String::from("nobody"),
+                    &AuditContext::default()
+                )
+                .await,
+            Err(Error::DbNoEffect)
+        ),
+        "updating missing user",
+    )?;
+
+    let throwaway = create_user(database).await?;
+    database
+        .delete_user(throwaway.id, &AuditContext::default())
+        .await
+        .map_err(failed("Deleting user"))?;
+    ensure(
+        database.get_user_by_email(&throwaway.email).await.is_err(),
+        "deleted user",
+    )?;
+    ensure(
+        matches!(
+            database
+                .delete_user(throwaway.id, &AuditContext::default())
+                .await,
+            Err(Error::DbNoEffect)
+        ),
+        "deleting missing user",
+    )
+}
+
+async fn webhooks(
+    database: &dyn Database,
+    first: &Account,
+    second: &Account,
+) -> Result<(), String> {
+    let mut webhook = Webhook::new(
+        first.id,
+        "http://localhost/hook",
+        vec![String::from("changed"), String::from("replanned")],
+    );
+    webhook.created_at = now();
+    let webhook_id = webhook.id;
+
+    database
+        .create_webhook(webhook)
+        .await
+        .map_err(failed("Creating webhook"))?;
+
+    let webhooks = database
+        .get_user_webhooks(first.id)
+        .await
+        .map_err(failed("Getting webhooks"))?;
+    ensure(webhooks.len() == 1, "webhook count")?;
+    ensure(webhooks[0].id == webhook_id, "webhook id")?;
+    ensure(
+        webhooks[0].events == ["changed", "replanned"],
+        "webhook events",
+    )?;
+
+    ensure(
+        database
+            .get_user_webhooks(second.id)
+            .await
+            .map_err(failed("Getting other webhooks"))?
+            .is_empty(),
+        "webhooks of other user",
+    )?;
+
+    let start = now();
+    for attempt in 1..=2 {
+        database
+            .log_webhook_delivery(WebhookDelivery {
+                id: Uuid::new_v4(),
+                webhook_id,
+                event: String::from("changed"),
+                payload: String::from("{}"),
+                attempt,
+                status_code: (attempt == 2).then_some(204),
+                error: (attempt == 1).then(|| String::from("timed out")),
+                created_at: start + chrono::Duration::seconds(attempt as i64),
+            })
+            .await
+            .map_err(failed("Logging delivery"))?;
+    }
+
+    let deliveries = database
+        .get_webhook_deliveries(webhook_id)
+        .await
+        .map_err(failed("Getting deliveries"))?;
+    ensure(deliveries.len() == 2, "delivery count")?;
+    ensure(deliveries[0].attempt == 1, "delivery order")?;
+    ensure(
+        deliveries[0].error.as_deref() == Some("timed out"),
+        "delivery error",
+    )?;
+    ensure(deliveries[1].status_code == Some(204), "delivery status")?;
+
+    ensure(
+        matches!(
+            database.delete_webhook(second.id, webhook_id).await,
+            Err(Error::DbNoEffect)
+        ),
+        "deleting webhook of other user",
+    )?;
+
+    database
+        .delete_webhook(first.id, webhook_id)
+        .await
+        .map_err(failed("Deleting webhook"))?;
+    ensure(
+        database
+            .get_user_webhooks(first.id)
+            .await
+            .map_err(failed("Getting webhooks"))?
+            .is_empty(),
+        "deleted webhook",
+    )
+}
+
+fn task(name: &str, created: DateTime<Utc>) -> Task {
+    Task {
+        name: String::from(name),
+        group: String::from("Conformance"),
+        config: TaskConfiguration {
+            time: Duration::from_secs(60 * 30),
+            repeat: false,
+            effort: Some(Duration::from_secs(60 * 60 * 4)),
+        },
+        created,
+        closed: None,
+    }
+}
+
+fn record(name: &str, started: DateTime<Utc>) -> TaskRecord {
+    TaskRecord {
+        id: Uuid::new_v4(),
+        origin_name: String::from(name),
+        origin_group: String::from("Conformance"),
+        time: Duration::from_secs(60 * 25),
+        planned: Some(Duration::from_secs(60 * 30)),
+        started: Some(started),
+        finished: Some(started + chrono::Duration::minutes(25)),
+        interruptions: vec![],
+    }
+}
+
+async fn schedule(
+    database: &dyn Database,
+    first: &Account,
+    second: &Account,
+) -> Result<(), String> {
+    let created = now();
+    let mut closed = task("Writing", created);
+    closed.closed = Some(created + chrono::Duration::hours(1));
+    let tasks = vec![task("Reading", created), closed];
+
+    database
+        .set_user_tasks(first.id, tasks.clone())
+        .await
+        .map_err(failed("Storing tasks"))?;
+
+    let stored = database
+        .get_user_tasks(first.id)
+        .await
+        .map_err(failed("Getting tasks"))?;
+    ensure(stored.len() == 2, "task count")?;
+    for task in &tasks {
+        let found = stored
+            .iter()
+            .find(|t| t.name == task.name && t.group == task.group);
+        ensure(
+            found.is_some_and(|t| {
+                t.created == task.created
+                    && t.closed == task.closed
+                    && t.config.time == task.config.time
+                    && t.config.repeat == task.config.repeat
+                    && t.config.effort == task.config.effort
+            }),
+            "task round-trip",
+        )?;
+    }
+
+    // Storing replaces instead of adding
+    database
+        .set_user_tasks(first.id, vec![task("Reading", created)])
+        .await
+        .map_err(failed("Replacing tasks"))?;
+    ensure(
+        database
+            .get_user_tasks(first.id)
+            .await
+            .map_err(failed("Getting tasks"))?
+            .len()
+            == 1,
+        "replaced tasks",
+    )?;
+
+    let ratios = vec![
+        ((String::from("Reading"), String::from("Conformance")), 0.25),
+        ((String::from("Writing"), String::from("Conformance")), 0.75),
+    ];
+    database
+        .set_user_ratios(first.id, ratios.clone())
+        .await
+        .map_err(failed("Storing ratios"))?;
+    let mut stored = database
+        .get_user_ratios(first.id)
+        .await
+        .map_err(failed("Getting ratios"))?;
+    stored.sort_by(|a, b| a.0.cmp(&b.0));
+    ensure(stored == ratios, "ratio round-trip")?;
+
+    // Records keep the order they were stored in, even when
+    // it isn't chronological
+    let records = vec![
+        record("Writing", created),
+        record("Reading", created - chrono::Duration::hours(2)),
+        record("Reading", created - chrono::Duration::hours(1)),
+    ];
+    database
+        .set_user_records(first.id, records.clone())
+        .await
+        .map_err(failed("Storing records"))?;
+    let stored = database
+        .get_user_records(first.id)
+        .await
+        .map_err(failed("Getting records"))?;
+    ensure(stored == records, "record round-trip")?;
+
+    database
+        .set_user_records(first.id, records[1..].to_vec())
+        .await
+        .map_err(failed("Replacing records"))?;
+    let stored = database
+        .get_user_records(first.id)
+        .await
+        .map_err(failed("Getting records"))?;
+    ensure(stored == records[1..], "replaced records")?;
+
+    // Updates only touch the records they name
+    let mut records = records[1..].to_vec();
+    records[0].time = Duration::from_secs(60 * 20);
+    let added = record("Writing", created + chrono::Duration::hours(1));
+    database
+        .update_user_records(
+            first.id,
+            vec![(0, records[0].clone()), (1, added.clone())],
+            vec![records[1].id],
+        )
+        .await
+        .map_err(failed("Updating records"))?;
+    let stored = database
+        .get_user_records(first.id)
+        .await
+        .map_err(failed("Getting records"))?;
+    ensure(stored == vec![records[0].clone(), added], "updated records")?;
+
+    // A session is only there once stored
+    ensure(
+        database
+            .get_user_session(first.id)
+            .await
+            .map_err(failed("Getting session"))?
+            .is_none(),
+        "missing session",
+    )?;
+    let block = ActiveBlock {
+        task: ScheduleTask {
+            origin_name: String::from("Reading"),
+            origin_group: String::from("Conformance"),
+            time: Duration::from_secs(60 * 25),
+            explanation: None,
+        },
+        started: created,
+        planned: Duration::from_secs(60 * 30),
+        worked: Duration::from_secs(60 * 5),
+        segment_start: created,
+        interruptions: vec![],
+    };
+    for state in [
+        SessionState::Running(block.clone()),
+        SessionState::Paused(block),
+    ] {
+        database
+            .set_user_session(first.id, state.clone())
+            .await
+            .map_err(failed("Storing session"))?;
+        let stored = database
+            .get_user_session(first.id)
+            .await
+            .map_err(failed("Getting session"))?;
+        ensure(
+            serde_json::to_value(&stored).ok() == serde_json::to_value(Some(&state)).ok(),
+            "session round-trip",
+        )?;
+    }
+
+    // Goals keep their order too
+    let goals = vec![
+        Goal {
+            name: String::from("Read a lot"),
+            task_name: String::from("Reading"),
+            task_group: String::from("Conformance"),
+            target: Duration::from_secs(30 * 3600),
+            scope: GoalScope::Period,
+            start: created,
+            deadline: created + chrono::Duration::days(30),
+        },
+        Goal {
+            name: String::from("Finish the draft"),
+            task_name: String::from("Writing"),
+            task_group: String::from("Conformance"),
+            target: Duration::from_secs_f64(5400.5),
+            scope: GoalScope::Lifetime,
+            start: created - chrono::Duration::days(1),
+            deadline: created + chrono::Duration::days(7),
+        },
+    ];
+    database
+        .set_user_goals(first.id, goals.clone())
+        .await
+        .map_err(failed("Storing goals"))?;
+    let stored = database
+        .get_user_goals(first.id)
+        .await
+        .map_err(failed("Getting goals"))?;
+    ensure(stored == goals, "goal round-trip")?;
+
+    database
+        .set_user_goals(first.id, goals[1..].to_vec())
+        .await
+        .map_err(failed("Replacing goals"))?;
+    let stored = database
+        .get_user_goals(first.id)
+        .await
+        .map_err(failed("Getting goals"))?;
+    ensure(stored == goals[1..], "replaced goals")?;
+
+    // Nothing leaks into other users
+    ensure(
+        database
+            .get_user_tasks(second.id)
+            .await
+            .map_err(failed("Getting other tasks"))?
+            .is_empty(),
+        "tasks of other user",
+    )?;
+    ensure(
+        database
+            .get_user_ratios(second.id)
+            .await
+            .map_err(failed("Getting other ratios"))?
+            .is_empty(),
+        "ratios of other user",
+    )?;
+    ensure(
+        database
+            .get_user_records(second.id)
+            .await
+            .map_err(failed("Getting other records"))?
+            .is_empty(),
+        "records of other user",
+    )?;
+    ensure(
+        database
+            .get_user_goals(second.id)
+            .await
+            .map_err(failed("Getting other goals"))?
+            .is_empty(),
+        "goals of other user",
+    )?;
+    ensure(
+        database
+            .get_user_session(second.id)
+            .await
+            .map_err(failed("Getting other session"))?
+            .is_none(),
+        "session of other user",
+    )
+}
+
+// Only works if nothing of an earlier user with the id is left
+async fn ensure_free(database: &dyn Database, id: Uuid, check: &str) -> Result<(), String> {
+    create_user_with_id(database, id)
+        .await
+        .map_err(|err| format!("Check failed: {} ({})", check, err))?;
+
+    database
+        .delete_user(id, &AuditContext::default())
+        .await
+        .map_err(failed("Deleting user"))
+}
+
+async fn transactions(database: &dyn Database, other: &Account) -> Result<(), String> {
+    let transaction = database
+        .begin()
+        .await
+        .map_err(failed("Beginning transaction"))?;
+    let account = create_user(&*transaction).await?;
+    transaction
+        .rollback()
+        .await
+        .map_err(failed("Rolling back"))?;
+    ensure_free(database, account.id, "rolled back user").await?;
+
+    let transaction = database
+        .begin()
+        .await
+        .map_err(failed("Beginning transaction"))?;
+    let account = create_user(&*transaction).await?;
+    drop(transaction);
+    ensure_free(database, account.id, "dropped transaction").await?;
+
+    // Several writes become visible together
+    let transaction = database
+        .begin()
+        .await
+        .map_err(failed("Beginning transaction"))?;
+    let account = create_user(&*transaction).await?;
+    transaction
+        .set_user_tasks(account.id, vec![task("Reading", now())])
+        .await
+        .map_err(failed("Storing tasks in transaction"))?;
+    transaction.commit().await.map_err(failed("Committing"))?;
+
+    let user = database
+        .get_user_by_email(&account.email)
+        .await
+        .map_err(failed("Getting committed user"))?;
+    ensure(user.id == account.id, "committed user")?;
+    ensure(
+        database
+            .get_user_tasks(account.id)
+            .await
+            .map_err(failed("Getting committed tasks"))?
+            .len()
+            == 1,
+        "committed tasks",
+    )?;
+    database
+        .delete_user(account.id, &AuditContext::default())
+        .await
+        .map_err(failed("Deleting user"))?;
+
+    // The credentials can't be stored with a taken email, the
+    // user inserted before them has to go as well
+    let user = User::new("Conformance");
+    let id = user.id;
+    let creds = Credentials::new(id, other.email.clone(), format!("conformance-{}", id))
+        .add_password_and_salt("conformance".as_bytes())
+        .map_err(failed("Hashing password"))?;
+    ensure(
+        database
+            .create_user(user, creds, &AuditContext::default())
+            .await
+            .is_err(),
+        "duplicate email",
+    )?;
+    ensure_free(database, id, "partially created user").await
+}
+
+async fn audit(database: &dyn Database, first: &Account) -> Result<(), String> {
+    let context = AuditContext {
+        actor: Some(first.id),
+        ip: Some(String::from("127.0.0.1")),
+    };
+
+    // Account changes are logged by the backend, together with
+    // the change
+    let account = create_user(database).await?;
+    let email = format!("renamed-{}", account.email);
+    database
+        .update_user_email(account.id, email.clone(), &context)
+        .await
+        .map_err(failed("Updating email"))?;
+    ensure(
+        database
+            .update_user_username(account.id, first.username.clone(), &context)
+            .await
+            .is_err(),
+        "duplicate username",
+    )?;
+
+    let trail = database
+        .get_user_audit(account.id, 10)
+        .await
+        .map_err(failed("Getting audit trail"))?;
+    ensure(trail.len() == 2, "account change count")?;
+    ensure(
+        trail.iter().any(|entry| {
+            entry.action == AuditAction::UserCreated
+                && entry.after == Some(format!("{} <{}>", account.username, account.email))
+        }),
+        "user created entry",
+    )?;
+    ensure(
+        trail.iter().any(|entry| {
+            entry.action == AuditAction::EmailChanged
+                && entry.actor_id == Some(first.id)
+                && entry.before.as_deref() == Some(account.email.as_str())
+                && entry.after.as_deref() == Some(email.as_str())
+        }),
+        "email changed entry",
+    )?;
+
+    let start = now();
+    let entries = [
+        AuditEntry::new(account.id, AuditAction::LoggedIn, &context),
+        AuditEntry::new(
+            account.id,
+            AuditAction::EmailChanged,
+            &AuditContext::default(),
+        )
+        .change(Some(String::from("old")), Some(String::from("new"))),
+    ];
+
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        entry.created_at = start + chrono::Duration::seconds(i as i64);
+        database
+            .log_audit(entry)
+            .await
+            .map_err(failed("Logging audit entry"))?;
+    }
+
+    let trail = database
+        .get_user_audit(account.id, 10)
+        .await
+        .map_err(failed("Getting audit trail"))?;
+    ensure(trail.len() == 4, "audit count")?;
+    ensure(trail[0].action == AuditAction::EmailChanged, "audit order")?;
+    ensure(trail[0].actor_id.is_none(), "audit without actor")?;
+    ensure(
+        trail[0].before.as_deref() == Some("old") && trail[0].after.as_deref() == Some("new"),
+        "audit summaries",
+    )?;
+    ensure(trail[1].actor_id == Some(first.id), "audit actor")?;
+    ensure(trail[1].ip.as_deref() == Some("127.0.0.1"), "audit ip")?;
+    ensure(trail[1].created_at == start, "audit timestamp")?;
+
+    ensure(
+        database
+            .get_user_audit(account.id, 1)
+            .await
+            .map_err(failed("Getting audit trail"))?
+            .len()
+            == 1,
+        "audit limit",
+    )?;
+
+    // The trail is kept after the user is gone
+    database
+        .delete_user(account.id, &context)
+        .await
+        .map_err(failed("Deleting user"))?;
+    let trail = database
+        .get_user_audit(account.id, 10)
+        .await
+        .map_err(failed("Getting audit trail"))?;
+    ensure(trail.len() == 5, "audit of deleted user")?;
+    ensure(
+        trail.iter().any(|entry| {
+            entry.action == AuditAction::UserDeleted
+                && entry.before == Some(format!("{} <{}>", account.username, email))
+        }),
+        "user deleted entry",
+    )
+}
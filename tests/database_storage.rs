@@ -0,0 +1,195 @@
+use std::fs;
+use std::sync::Arc;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use scheduler::{
+    database::{
+        client::Database,
+        config::DatabaseConfiguration,
+        data::{AuditAction, AuditContext, AuditEntry, Credentials, User},
+        sqlite::Sqlite,
+        storage::{DatabaseStorage, PendingAudit},
+    },
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    session::{Session, SessionError, SessionState},
+    storage::Storable,
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+use uuid::Uuid;
+
+// Stores wait for the database, which they can't do on the
+// async threads
+async fn blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    tokio::task::spawn_blocking(f).await.unwrap()
+}
+
+async fn session(database: Arc<dyn Database>, user_id: Uuid) -> Session<DatabaseStorage> {
+    let storage = DatabaseStorage::load(database, user_id).await.unwrap();
+    let tasks = ExpectedRatioTasks::from_ratios(
+        vec![Task::new("Reading", "Study", TaskConfiguration::default())],
+        &[((String::from("Reading"), String::from("Study")), 1.0)],
+    )
+    .unwrap();
+    let scheduler = Scheduler::new(tasks, vec![], ScheduleConfiguration::default());
+
+    Session::new(scheduler, storage).unwrap()
+}
+
+#[tokio::test]
+async fn running_blocks_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("scheduler-session-{}.db", Uuid::new_v4()));
+    let database = Sqlite::connect(&DatabaseConfiguration::file(path.to_string_lossy()))
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+    let database: Arc<dyn Database> = Arc::new(database);
+
+    let user = User::new("Restarted");
+    let creds = Credentials::new(user.id, "restarted@example.com", "restarted")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+
+    let mut first = session(database.clone(), user.id).await;
+    blocking(move || first.start(Utc::now()).unwrap()).await;
+
+    let state = database.get_user_session(user.id).await.unwrap();
+    assert!(matches!(state, Some(SessionState::Running(_))));
+
+    // The block is picked up where it was left and the
+    // record it ends with is written on its own
+    let mut second = session(database.clone(), user.id).await;
+    assert!(matches!(second.state, SessionState::Running(_)));
+    blocking(move || second.finish(Utc::now()).unwrap()).await;
+
+    let records = database.get_user_records(user.id).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].origin_name, "Reading");
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[tokio::test]
+async fn failed_writes_are_returned_and_not_kept() {
+    let path = std::env::temp_dir().join(format!("scheduler-session-{}.db", Uuid::new_v4()));
+    let database = Sqlite::connect(&DatabaseConfiguration::file(path.to_string_lossy()))
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+    let database: Arc<dyn Database> = Arc::new(database);
+
+    // Nobody the rows can belong to, so every write fails
+    let storage = DatabaseStorage::load(database.clone(), Uuid::new_v4())
+        .await
+        .unwrap();
+
+    let storage = blocking(move || {
+        let tasks = [Task::new("Reading", "Study", TaskConfiguration::default())];
+        assert!(storage.store(&tasks).is_err());
+        assert!(Storable::<SessionState>::store(&storage, &[SessionState::Idle]).is_err());
+        storage
+    })
+    .await;
+
+    let tasks: Vec<Task> = storage.get().unwrap();
+    let state: Vec<SessionState> = storage.get().unwrap();
+    assert!(tasks.is_empty());
+    assert!(state.is_empty());
+
+    // A session can't start a block it can't write
+    let tasks = ExpectedRatioTasks::from_ratios(
+        vec![Task::new("Reading", "Study", TaskConfiguration::default())],
+        &[((String::from("Reading"), String::from("Study")), 1.0)],
+    )
+    .unwrap();
+    let scheduler = Scheduler::new(tasks, vec![], ScheduleConfiguration::default());
+    let mut session = Session::new(scheduler, storage).unwrap();
+
+    let (session, started) = blocking(move || {
+        let started = session.start(Utc::now());
+        (session, started)
+    })
+    .await;
+    assert!(matches!(started, Err(SessionError::Storage(_))));
+    assert!(matches!(session.state, SessionState::Idle));
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[tokio::test]
+async fn audit_entries_are_written_with_the_history() {
+    let path = std::env::temp_dir().join(format!("scheduler-session-{}.db", Uuid::new_v4()));
+    let database = Sqlite::connect(&DatabaseConfiguration::file(path.to_string_lossy()))
+        .await
+        .unwrap();
+    database.migrate().await.unwrap();
+    let database: Arc<dyn Database> = Arc::new(database);
+
+    let user = User::new("Audited");
+    let creds = Credentials::new(user.id, "audited@example.com", "audited")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+
+    let audit = PendingAudit::default();
+    let storage = DatabaseStorage::load(database.clone(), user.id)
+        .await
+        .unwrap()
+        .with_audit(audit.clone());
+    let logged = || AuditEntry::new(user.id, AuditAction::RecordLogged, &AuditContext::default());
+    let records = [TaskRecord::manual(
+        "Reading",
+        "Study",
+        Duration::from_secs(600),
+        Utc::now(),
+    )];
+
+    // The second entry can't be written, so neither is the
+    // history it came with
+    let entry = logged();
+    audit.push(entry.clone());
+    audit.push(entry);
+    let (storage, failed) = blocking(move || {
+        let failed = storage.store(&records).is_err();
+        (storage, failed)
+    })
+    .await;
+    assert!(failed);
+    assert!(database.get_user_records(user.id).await.unwrap().is_empty());
+
+    let records = [TaskRecord::manual(
+        "Reading",
+        "Study",
+        Duration::from_secs(600),
+        Utc::now(),
+    )];
+    audit.push(logged());
+    blocking(move || {
+        storage.store(&records).unwrap();
+        // Nothing changed, nothing is written
+        storage.store(&records).unwrap();
+    })
+    .await;
+
+    let trail = database.get_user_audit(user.id, 10).await.unwrap();
+    assert_eq!(database.get_user_records(user.id).await.unwrap().len(), 1);
+    assert_eq!(trail.len(), 2);
+    assert_eq!(trail[0].action, AuditAction::RecordLogged);
+    assert_eq!(trail[1].action, AuditAction::UserCreated);
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
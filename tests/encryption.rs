@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
+use scheduler::{
+    encryption::EncryptedStorage,
+    storage::{FileStorage, Storable},
+    task::TaskRecord,
+};
+use uuid::Uuid;
+
+// Scratch directory removed again when the test is done
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("scheduler-encryption-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn storage(&self) -> FileStorage<PathBuf> {
+        let path = |name: &str| self.0.join(format!("{}.json", name));
+        FileStorage::new(
+            path("tasks"),
+            path("records"),
+            path("ratios"),
+            path("session"),
+        )
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn ratios() -> Vec<((String, String), f32)> {
+    vec![((String::from("Reading"), String::from("Study")), 1.0)]
+}
+
+#[test]
+fn plaintext_files_are_sealed_when_read() {
+    let dir = TempDir::new();
+    dir.storage().store(&ratios()).unwrap();
+
+    let encrypted = EncryptedStorage::new(dir.storage(), "secret");
+    let stored: Vec<((String, String), f32)> = encrypted.get().unwrap();
+    assert_eq!(stored, ratios());
+
+    // The plaintext is gone from disk but still readable
+    let buf = fs::read_to_string(dir.0.join("ratios.json")).unwrap();
+    assert!(!buf.contains("Reading"));
+    let stored: Vec<((String, String), f32)> = encrypted.get().unwrap();
+    assert_eq!(stored, ratios());
+}
+
+#[test]
+fn rotation_skips_plaintext_files() {
+    let dir = TempDir::new();
+    dir.storage().store(&ratios()).unwrap();
+
+    let encrypted =
+        EncryptedStorage::new(dir.storage(), "secret").with_previous_passphrase("old secret");
+    assert_eq!(encrypted.rotate().unwrap(), 0);
+
+    let stored: Vec<((String, String), f32)> = encrypted.get().unwrap();
+    assert_eq!(stored, ratios());
+}
+
+#[test]
+fn plaintext_backups_are_removed_once_sealed() {
+    let dir = TempDir::new();
+    dir.storage().store(&ratios()).unwrap();
+
+    // As left by an upgrade, next to something that isn't one
+    let backup = dir.0.join("ratios.json.v1.bak");
+    let other = dir.0.join("ratios.json.notes.bak");
+    fs::write(
+        &backup,
+        r#"{"version":1,"entries":[[["Reading","Study"],1.0]]}"#,
+    )
+    .unwrap();
+    fs::write(&other, "kept").unwrap();
+
+    let encrypted = EncryptedStorage::new(dir.storage(), "secret");
+    let stored: Vec<((String, String), f32)> = encrypted.get().unwrap();
+
+    assert_eq!(stored, ratios());
+    assert!(!backup.exists());
+    assert!(other.exists());
+}
+
+#[test]
+fn records_without_ids_keep_the_ones_they_get() {
+    let dir = TempDir::new();
+    let mut record = TaskRecord::manual("Reading", "Study", Duration::from_secs(600), Utc::now());
+    record.id = Uuid::nil();
+
+    let encrypted = EncryptedStorage::new(dir.storage(), "secret");
+    encrypted.store(&[record]).unwrap();
+
+    let first: Vec<TaskRecord> = encrypted.get().unwrap();
+    let second: Vec<TaskRecord> = encrypted.get().unwrap();
+
+    assert!(!first[0].id.is_nil());
+    assert_eq!(first[0].id, second[0].id);
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use scheduler::{
+    estimation::Estimator,
+    interruption::{Interruption, InterruptionKind},
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+
+fn now() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(60 * minutes)
+}
+
+// Blocks of 40 minutes, done after 10 hours
+fn task() -> Task {
+    let config = TaskConfiguration {
+        time: minutes(40),
+        effort: Some(minutes(600)),
+        ..TaskConfiguration::default()
+    };
+
+    Task::new("Reading", "Study", config)
+}
+
+// A block planned for 40 minutes that ended `ago` before now
+fn block(time: Duration, ago: TimeDelta) -> TaskRecord {
+    let mut record = TaskRecord::manual("Reading", "Study", time, now() - ago);
+    record.planned = Some(minutes(40));
+    record
+}
+
+#[test]
+fn empty_history_has_no_projection() {
+    let estimator = Estimator::new(&[]);
+    let estimate = estimator.task_estimate(&task(), now()).unwrap();
+
+    assert!(estimator.group_estimates().is_empty());
+    assert_eq!(estimator.overrun_factor(), 1.0);
+    assert_eq!(estimate.done, Duration::ZERO);
+    assert_eq!(estimate.remaining, minutes(600));
+    assert_eq!(estimate.remaining_blocks, 15);
+    assert_eq!(estimate.daily_rate, Duration::ZERO);
+    assert_eq!(estimate.projected_completion, None);
+    assert_eq!(estimate.can_finish_by(now() + TimeDelta::days(365)), None);
+}
+
+#[test]
+fn tasks_without_effort_are_not_estimated() {
+    let mut task = task();
+    task.config.effort = None;
+
+    assert!(Estimator::new(&[]).task_estimate(&task, now()).is_none());
+}
+
+#[test]
+fn single_record_sets_the_overrun_and_the_rate() {
+    let history = [block(minutes(60), TimeDelta::hours(1))];
+    let estimator = Estimator::new(&history);
+    let estimate = estimator.task_estimate(&task(), now()).unwrap();
+
+    let groups = estimator.group_estimates();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].samples, 1);
+    assert_eq!(groups[0].factor(), 1.5);
+
+    // Blocks run to an hour, and an hour a day is done
+    assert_eq!(estimate.done, minutes(60));
+    assert_eq!(estimate.remaining, minutes(540));
+    assert_eq!(estimate.remaining_blocks, 9);
+    assert_eq!(estimate.daily_rate, minutes(60));
+    assert_eq!(
+        estimate.projected_completion,
+        Some(now() + TimeDelta::days(9))
+    );
+    assert_eq!(
+        estimate.can_finish_by(now() + TimeDelta::days(10)),
+        Some(true)
+    );
+    assert_eq!(
+        estimate.can_finish_by(now() + TimeDelta::days(8)),
+        Some(false)
+    );
+}
+
+#[test]
+fn interrupted_time_is_not_counted_as_done() {
+    // 40 minute block with 10 minutes lost, `time` already
+    // has them taken out
+    let mut record = block(minutes(30), TimeDelta::hours(1));
+    record.interruptions.push(Interruption {
+        kind: InterruptionKind::External,
+        duration: minutes(10),
+        note: None,
+        at: now() - TimeDelta::minutes(80),
+    });
+
+    let history = [record];
+    let estimator = Estimator::new(&history);
+    let estimate = estimator.task_estimate(&task(), now()).unwrap();
+
+    assert_eq!(estimator.overrun_factor(), 0.75);
+    assert_eq!(estimate.done, minutes(30));
+    assert_eq!(estimate.remaining, minutes(570));
+    assert_eq!(estimate.remaining_blocks, 19);
+    assert_eq!(estimate.daily_rate, minutes(30));
+}
+
+#[test]
+fn breaks_and_old_records_do_not_count_towards_the_rate() {
+    let mut minibreak = TaskRecord::manual("Minibreak", "system/minibreak", minutes(10), now());
+    minibreak.planned = Some(minutes(10));
+
+    let history = [
+        block(minutes(40), TimeDelta::days(30)),
+        block(minutes(40), TimeDelta::days(1)),
+        minibreak,
+    ];
+    let estimator = Estimator::new(&history);
+    let estimate = estimator.task_estimate(&task(), now()).unwrap();
+
+    // Both blocks are done, only the recent one is averaged
+    // over the two weeks looked at
+    assert_eq!(estimator.group_estimates().len(), 1);
+    assert_eq!(estimate.done, minutes(80));
+    assert_eq!(estimate.daily_rate, minutes(40).div_f64(14.0));
+}
+
+#[test]
+fn finished_tasks_are_done_now() {
+    let history = [block(minutes(600), TimeDelta::hours(1))];
+    let estimate = Estimator::new(&history)
+        .task_estimate(&task(), now())
+        .unwrap();
+
+    assert_eq!(estimate.remaining, Duration::ZERO);
+    assert_eq!(estimate.remaining_blocks, 0);
+    assert_eq!(estimate.projected_completion, Some(now()));
+    assert_eq!(estimate.can_finish_by(now()), Some(true));
+}
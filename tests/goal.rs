@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use scheduler::{
+    goal::{Goal, GoalScope, Pace},
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+
+fn start() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn hours(hours: u64) -> Duration {
+    Duration::from_secs(60 * 60 * hours)
+}
+
+// 10 hours of Writing over 10 days
+fn goal(scope: GoalScope) -> Goal {
+    Goal {
+        name: String::from("Draft"),
+        task_name: String::from("Writing"),
+        task_group: String::from("Study"),
+        target: hours(10),
+        scope,
+        start: start(),
+        deadline: start() + TimeDelta::days(10),
+    }
+}
+
+fn record(name: &str, hours: u64, day: i64) -> TaskRecord {
+    TaskRecord::manual(
+        name,
+        "Study",
+        self::hours(hours),
+        start() + TimeDelta::days(day),
+    )
+}
+
+fn halfway() -> DateTime<Utc> {
+    start() + TimeDelta::days(5)
+}
+
+#[test]
+fn pace_follows_the_time_elapsed() {
+    let goal = goal(GoalScope::Period);
+
+    let progress = goal.progress(&[record("Writing", 5, 1)], halfway());
+    assert_eq!(progress.expected, hours(5));
+    assert_eq!(progress.pace, Pace::OnTrack);
+    assert_eq!(progress.percent(), 50.0);
+    assert_eq!(progress.behind(), 0.0);
+
+    let progress = goal.progress(&[record("Writing", 2, 1)], halfway());
+    assert_eq!(progress.pace, Pace::Behind);
+    assert!((progress.behind() - 0.3).abs() < 1e-4);
+
+    let progress = goal.progress(&[record("Writing", 8, 1)], halfway());
+    assert_eq!(progress.pace, Pace::Ahead);
+    assert_eq!(progress.behind(), 0.0);
+}
+
+#[test]
+fn expected_progress_stays_within_the_goal() {
+    let goal = goal(GoalScope::Period);
+
+    let before = goal.progress(&[], start() - TimeDelta::days(1));
+    assert_eq!(before.expected, Duration::ZERO);
+    assert_eq!(before.pace, Pace::OnTrack);
+
+    let after = goal.progress(&[], start() + TimeDelta::days(20));
+    assert_eq!(after.expected, hours(10));
+    assert_eq!(after.pace, Pace::Behind);
+    assert_eq!(after.behind(), 1.0);
+}
+
+#[test]
+fn reaching_the_target_is_ahead_and_complete() {
+    let progress = goal(GoalScope::Period).progress(&[record("Writing", 12, 2)], halfway());
+
+    assert_eq!(progress.pace, Pace::Ahead);
+    assert_eq!(progress.percent(), 100.0);
+}
+
+#[test]
+fn only_the_period_counts_for_period_goals() {
+    let history = [
+        record("Writing", 3, -2),
+        record("Writing", 2, 1),
+        record("Writing", 4, 12),
+        record("Reading", 5, 1),
+    ];
+
+    let period = goal(GoalScope::Period).progress(&history, halfway());
+    let lifetime = goal(GoalScope::Lifetime).progress(&history, halfway());
+
+    assert_eq!(period.done, hours(2));
+    assert_eq!(lifetime.done, hours(9));
+}
+
+fn scheduler(goals: Vec<Goal>) -> Scheduler {
+    let tasks = ExpectedRatioTasks::new(vec![
+        (
+            Task::new("Reading", "Study", TaskConfiguration::default()),
+            0.5,
+        ),
+        (
+            Task::new("Writing", "Study", TaskConfiguration::default()),
+            0.5,
+        ),
+    ])
+    .unwrap();
+
+    // Rested, so the next block is a task
+    let minibreak = TaskRecord::manual(
+        "Minibreak",
+        "system/minibreak",
+        Duration::from_secs(600),
+        start() + TimeDelta::days(1),
+    );
+    let history = vec![record("Reading", 1, 1), record("Writing", 1, 1), minibreak];
+
+    Scheduler::new(tasks, history, ScheduleConfiguration::default()).with_goals(goals)
+}
+
+#[test]
+fn tasks_behind_their_goal_are_picked_first() {
+    let next = |goals| {
+        scheduler(goals)
+            .compute_task(&vec![], halfway())
+            .unwrap()
+            .origin_name
+    };
+
+    // Otherwise even, the tie goes to the name
+    assert_eq!(next(vec![]), "Reading");
+    assert_eq!(next(vec![goal(GoalScope::Period)]), "Writing");
+}
+
+#[test]
+fn goal_progress_is_reported_per_goal() {
+    let progress = scheduler(vec![goal(GoalScope::Period)]).goal_progress(halfway());
+
+    assert_eq!(progress.len(), 1);
+    assert_eq!(progress[0].0.name, "Draft");
+    assert_eq!(progress[0].1.done, hours(1));
+    assert_eq!(progress[0].1.pace, Pace::Behind);
+}
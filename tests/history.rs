@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
+use scheduler::{
+    history::HistoryLog,
+    storage::{Storable, StorageError},
+    task::TaskRecord,
+};
+use uuid::Uuid;
+
+// Log file removed again when the test is done
+struct TempLog(PathBuf);
+
+impl TempLog {
+    fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("scheduler-history-{}.jsonl", Uuid::new_v4())))
+    }
+
+    fn log(&self) -> HistoryLog<PathBuf> {
+        HistoryLog::new(self.0.clone())
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        for extension in ["jsonl.lock", "jsonl.v1.bak"] {
+            let _ = fs::remove_file(self.0.with_extension(extension));
+        }
+    }
+}
+
+fn record(name: &str) -> TaskRecord {
+    TaskRecord::manual(name, "Study", Duration::from_secs(30 * 60), Utc::now())
+}
+
+fn line(record: &TaskRecord) -> String {
+    serde_json::to_string(record).unwrap()
+}
+
+#[test]
+fn truncated_line_is_skipped_and_cut_off_on_append() {
+    let file = TempLog::new();
+    let first = record("Reading");
+    fs::write(&file.0, format!("{}\n{{\"id\":\"trunc", line(&first))).unwrap();
+
+    let log = file.log();
+    let records = log.read().unwrap();
+    assert_eq!(records, vec![first.clone()]);
+
+    // Reading leaves the file alone
+    assert!(fs::read_to_string(&file.0).unwrap().ends_with("trunc"));
+
+    let second = record("Writing");
+    log.store(&[first.clone(), second.clone()]).unwrap();
+
+    let buf = fs::read_to_string(&file.0).unwrap();
+    assert_eq!(buf, format!("{}\n{}\n", line(&first), line(&second)));
+    assert_eq!(file.log().read().unwrap(), vec![first, second]);
+}
+
+#[test]
+fn missing_final_newline_is_added_before_appending() {
+    let file = TempLog::new();
+    let first = record("Reading");
+    fs::write(&file.0, line(&first)).unwrap();
+
+    let log = file.log();
+    let second = record("Writing");
+    log.store(&[first.clone(), second.clone()]).unwrap();
+
+    assert_eq!(file.log().read().unwrap(), vec![first, second]);
+}
+
+#[test]
+fn unreadable_log_is_rewritten() {
+    let file = TempLog::new();
+    fs::write(&file.0, "not json\n").unwrap();
+
+    let log = file.log();
+    assert!(log.read().is_err());
+
+    let records = vec![record("Reading")];
+    log.store(&records).unwrap();
+
+    assert_eq!(file.log().read().unwrap(), records);
+}
+
+// Version 1 called the task name `task`
+fn renamed_task(entries: serde_json::Value) -> Result<serde_json::Value, StorageError> {
+    let mut entries = entries.as_array().cloned().unwrap_or_default();
+
+    for entry in entries.iter_mut() {
+        if let Some(entry) = entry.as_object_mut()
+            && let Some(task) = entry.remove("task")
+        {
+            entry.insert(String::from("origin_name"), task);
+        }
+    }
+
+    Ok(serde_json::Value::Array(entries))
+}
+
+fn old_line(record: &TaskRecord) -> String {
+    let mut value = serde_json::to_value(record).unwrap();
+    let entry = value.as_object_mut().unwrap();
+    let name = entry.remove("origin_name").unwrap();
+    entry.insert(String::from("task"), name);
+    value.to_string()
+}
+
+#[test]
+fn old_logs_are_folded_and_upgraded() {
+    let file = TempLog::new();
+    let kept = record("Reading");
+    let deleted = record("Writing");
+    let mut updated = kept.clone();
+    updated.time = Duration::from_secs(20 * 60);
+
+    fs::write(
+        &file.0,
+        format!(
+            "{}\n{}\n{}\n{{\"deleted\":\"{}\"}}\n",
+            old_line(&kept),
+            old_line(&deleted),
+            old_line(&updated),
+            deleted.id
+        ),
+    )
+    .unwrap();
+
+    let log = file.log().with_upgrades(vec![renamed_task]);
+    assert_eq!(log.read().unwrap(), vec![updated.clone()]);
+
+    let buf = fs::read_to_string(&file.0).unwrap();
+    assert_eq!(buf, format!("{{\"version\":2}}\n{}\n", line(&updated)));
+
+    // Appends keep the version line first
+    let added = record("Running");
+    log.store(&[updated.clone(), added.clone()]).unwrap();
+    assert_eq!(
+        file.log().with_upgrades(vec![renamed_task]).read().unwrap(),
+        vec![updated, added]
+    );
+}
+
+fn finished_at(name: &str, minutes: i64) -> TaskRecord {
+    TaskRecord::manual(
+        name,
+        "Study",
+        Duration::from_secs(30 * 60),
+        Utc::now() - chrono::TimeDelta::minutes(minutes),
+    )
+}
+
+#[test]
+fn rewrites_keep_what_another_writer_added() {
+    let file = TempLog::new();
+    let reading = finished_at("Reading", 60);
+    let writing = finished_at("Writing", 30);
+    let drawing = finished_at("Drawing", 10);
+    let early = finished_at("Running", 120);
+
+    let first = file.log();
+    let second = file.log();
+    first.store(std::slice::from_ref(&reading)).unwrap();
+    assert_eq!(second.read().unwrap(), vec![reading.clone()]);
+
+    first.store(&[reading.clone(), writing.clone()]).unwrap();
+
+    // Logged before everything else, so the log has to be
+    // rewritten from a history that misses `writing`
+    second.store(&[early.clone(), reading.clone()]).unwrap();
+    first
+        .store(&[reading.clone(), writing.clone(), drawing.clone()])
+        .unwrap();
+
+    assert_eq!(
+        file.log().read().unwrap(),
+        vec![early, reading, writing, drawing]
+    );
+}
+
+#[test]
+fn rewrites_keep_what_another_writer_changed_or_deleted() {
+    let file = TempLog::new();
+    let reading = finished_at("Reading", 60);
+    let writing = finished_at("Writing", 30);
+    let early = finished_at("Running", 120);
+
+    let first = file.log();
+    let second = file.log();
+    first.store(&[reading.clone(), writing.clone()]).unwrap();
+    second.read().unwrap();
+
+    let mut longer = reading.clone();
+    longer.time = Duration::from_secs(45 * 60);
+    first.store(&[longer.clone()]).unwrap();
+
+    second
+        .store(&[early.clone(), reading.clone(), writing.clone()])
+        .unwrap();
+
+    assert_eq!(file.log().read().unwrap(), vec![early, longer]);
+}
+
+#[test]
+fn both_writers_changing_a_record_keeps_the_last() {
+    let file = TempLog::new();
+    let reading = finished_at("Reading", 60);
+    let early = finished_at("Running", 120);
+
+    let first = file.log();
+    let second = file.log();
+    first.store(std::slice::from_ref(&reading)).unwrap();
+    second.read().unwrap();
+
+    let mut theirs = reading.clone();
+    theirs.time = Duration::from_secs(45 * 60);
+    first.store(&[theirs]).unwrap();
+
+    let mut ours = reading.clone();
+    ours.time = Duration::from_secs(10 * 60);
+    second.store(&[early.clone(), ours.clone()]).unwrap();
+
+    assert_eq!(file.log().read().unwrap(), vec![early, ours]);
+}
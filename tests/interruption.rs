@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use scheduler::{
+    interruption::{Interruption, InterruptionKind, InterruptionReport},
+    schedule::Scheduler,
+    task::TaskRecord,
+};
+
+fn start() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(60 * minutes)
+}
+
+fn interruption(kind: InterruptionKind, lost: u64, after: i64) -> Interruption {
+    Interruption {
+        kind,
+        duration: minutes(lost),
+        note: None,
+        at: start() + TimeDelta::minutes(after),
+    }
+}
+
+// 60 minutes starting at `start()`, `time` has the
+// interruptions already taken out
+fn record(name: &str, interruptions: Vec<Interruption>) -> TaskRecord {
+    let lost = interruptions
+        .iter()
+        .fold(Duration::ZERO, |acc, i| acc + i.duration);
+
+    let mut record = TaskRecord::manual(name, "Study", minutes(60), start() + TimeDelta::hours(1));
+    record.time -= lost;
+    record.interruptions = interruptions;
+    record
+}
+
+#[test]
+fn report_counts_interruptions_by_kind_task_and_hour() {
+    let history = [
+        record(
+            "Reading",
+            vec![
+                interruption(InterruptionKind::Internal, 2, 10),
+                interruption(InterruptionKind::External, 5, 70),
+            ],
+        ),
+        record(
+            "Writing",
+            vec![interruption(InterruptionKind::Internal, 3, 20)],
+        ),
+        record("Drawing", vec![]),
+    ];
+
+    let report = InterruptionReport::from_history(&history);
+
+    assert_eq!(report.total.internal, 2);
+    assert_eq!(report.total.external, 1);
+    assert_eq!(report.total.count(), 3);
+    assert_eq!(report.total.lost, minutes(10));
+
+    // Tasks without interruptions are left out
+    assert_eq!(report.tasks.len(), 2);
+    let reading = report.tasks.iter().find(|t| t.name == "Reading").unwrap();
+    assert_eq!(reading.stats.count(), 2);
+    assert_eq!(reading.stats.lost, minutes(7));
+
+    let hour = |at: DateTime<Utc>| at.hour() as usize;
+    assert_eq!(report.hours.len(), 24);
+    assert_eq!(report.hours[hour(start())].count(), 2);
+    assert_eq!(
+        report.hours[hour(start() + TimeDelta::hours(1))].external,
+        1
+    );
+}
+
+#[test]
+fn empty_history_reports_nothing() {
+    let report = InterruptionReport::from_history(&[]);
+
+    assert_eq!(report.total.count(), 0);
+    assert_eq!(report.total.lost, Duration::ZERO);
+    assert!(report.tasks.is_empty());
+    assert_eq!(report.hours.len(), 24);
+}
+
+#[test]
+fn splitting_keeps_interruptions_with_their_half() {
+    let mut scheduler = Scheduler::default();
+    let id = scheduler.log_record(record(
+        "Reading",
+        vec![
+            interruption(InterruptionKind::Internal, 2, 10),
+            interruption(InterruptionKind::External, 3, 40),
+        ],
+    ));
+
+    let (first, second) = scheduler.split_record(id, minutes(30)).unwrap();
+    let history = scheduler.history();
+
+    assert_eq!(history[0].id, first);
+    assert_eq!(history[0].time, minutes(30));
+    assert_eq!(history[0].interruptions.len(), 1);
+    assert_eq!(history[0].interruptions[0].duration, minutes(2));
+
+    assert_eq!(history[1].id, second);
+    assert_eq!(history[1].time, minutes(25));
+    assert_eq!(history[1].interruptions.len(), 1);
+    assert_eq!(history[1].interruptions[0].duration, minutes(3));
+
+    // Nothing is lost or counted twice
+    assert_eq!(scheduler.interruption_report().total.lost, minutes(5));
+}
+
+#[test]
+fn merging_keeps_the_interruptions_of_both() {
+    let mut scheduler = Scheduler::default();
+    let first = scheduler.log_record(record(
+        "Reading",
+        vec![interruption(InterruptionKind::External, 3, 40)],
+    ));
+    let second = scheduler.log_record(record(
+        "Reading",
+        vec![interruption(InterruptionKind::Internal, 2, 10)],
+    ));
+
+    scheduler.merge_records(first, second).unwrap();
+    let merged = &scheduler.history()[0];
+
+    assert_eq!(scheduler.history().len(), 1);
+    assert_eq!(merged.time, minutes(115));
+    assert_eq!(merged.interruptions.len(), 2);
+    assert!(merged.interruptions[0].at < merged.interruptions[1].at);
+}
@@ -0,0 +1,374 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use poem::{
+    EndpointExt, Route, Server,
+    listener::{Acceptor, Listener, TcpListener},
+    middleware::AddData,
+};
+use scheduler::{
+    AppState,
+    cache::local::LocalStorage,
+    database::{
+        config::DatabaseConfiguration,
+        data::{AuditContext, Credentials, User},
+        sqlite::Sqlite,
+    },
+    live::{LiveCommand, LiveEvent, LiveSessions},
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    session::{Session, SessionEvent},
+    storage::FileStorage,
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+// Ticks come every second, this is plenty
+const WAIT: Duration = Duration::from_secs(5);
+
+// Directory removed again when the test is done
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("scheduler-live-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    fn storage(&self) -> FileStorage<PathBuf> {
+        let file = |name: &str| self.0.join(name);
+        FileStorage::new(
+            file("tasks.json"),
+            file("history.json"),
+            file("task-ratio.json"),
+            file("session.json"),
+        )
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn reading() -> Task {
+    Task::new("Reading", "Study", TaskConfiguration::default())
+}
+
+fn session(dir: &TempDir) -> Session<FileStorage<PathBuf>> {
+    let tasks = ExpectedRatioTasks::new(vec![(reading(), 1.0)]).unwrap();
+    let scheduler = Scheduler::new(tasks, vec![], ScheduleConfiguration::default());
+
+    Session::new(scheduler, dir.storage()).unwrap()
+}
+
+// Skips everything else until an event of `kind` comes
+async fn next(receiver: &mut Receiver<LiveEvent>, kind: &str) -> LiveEvent {
+    tokio::time::timeout(WAIT, async {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.kind() == kind => return event,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!("Closed while waiting for {}", kind),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("No {} event", kind))
+}
+
+#[tokio::test]
+async fn commands_and_ticks_are_broadcast() {
+    let dir = TempDir::new();
+    let live = LiveSessions::default();
+    let user_id = Uuid::new_v4();
+
+    assert!(live.attach(user_id, session(&dir)).await);
+    assert!(!live.attach(user_id, session(&dir)).await);
+
+    let mut events = live.subscribe(user_id).await.unwrap();
+    live.command(user_id, LiveCommand::Start).await.unwrap();
+
+    let LiveEvent::BlockStarted { task, .. } = next(&mut events, "block_started").await else {
+        unreachable!();
+    };
+    assert_eq!(task.origin_name, "Reading");
+
+    let LiveEvent::Tick { remaining, .. } = next(&mut events, "tick").await else {
+        unreachable!();
+    };
+    assert!(remaining <= task.time);
+
+    live.command(user_id, LiveCommand::Pause).await.unwrap();
+    let LiveEvent::Changed { event, .. } = next(&mut events, "changed").await else {
+        unreachable!();
+    };
+    assert_eq!(event, SessionEvent::Paused);
+
+    live.command(user_id, LiveCommand::Pause).await.unwrap();
+    next(&mut events, "rejected").await;
+
+    live.command(user_id, LiveCommand::Finish).await.unwrap();
+    let LiveEvent::BlockEnded { event, record, .. } = next(&mut events, "block_ended").await else {
+        unreachable!();
+    };
+    assert_eq!(event, SessionEvent::FinishedEarly);
+    assert!(record.is_some());
+
+    let LiveEvent::Replanned { upcoming } = next(&mut events, "replanned").await else {
+        unreachable!();
+    };
+    assert!(!upcoming.is_empty());
+}
+
+#[tokio::test]
+async fn history_edits_replan_and_detaching_ends_the_stream() {
+    let dir = TempDir::new();
+    let live = LiveSessions::default();
+    let user_id = Uuid::new_v4();
+
+    live.attach(user_id, session(&dir)).await;
+    let mut events = live.subscribe(user_id).await.unwrap();
+
+    let record = TaskRecord::manual(
+        "Reading",
+        "Study",
+        Duration::from_secs(600),
+        chrono::Utc::now(),
+    );
+    live.edit_history(user_id, move |scheduler| scheduler.log_record(record))
+        .await
+        .unwrap();
+    next(&mut events, "replanned").await;
+
+    live.detach(user_id).await;
+    let closed = tokio::time::timeout(WAIT, async {
+        loop {
+            if let Err(RecvError::Closed) = events.recv().await {
+                return;
+            }
+        }
+    })
+    .await;
+
+    assert!(closed.is_ok());
+    assert!(live.subscribe(user_id).await.is_none());
+    assert!(live.command(user_id, LiveCommand::Start).await.is_err());
+}
+
+struct TestServer {
+    address: SocketAddr,
+    session_id: String,
+    _dir: TempDir,
+}
+
+// Serves the live routes for a user with one task, the
+// user's session is started but no block is running yet
+async fn serve() -> TestServer {
+    let dir = TempDir::new();
+
+    let state = AppState::connect::<Sqlite, LocalStorage>(&DatabaseConfiguration::file(
+        dir.0.join("scheduler.db").to_string_lossy(),
+    ))
+    .await
+    .unwrap();
+    state.database.migrate().await.unwrap();
+
+    let user = User::new("Live");
+    let creds = Credentials::new(user.id, "live@example.com", "live")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    state
+        .database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+    state
+        .database
+        .set_user_tasks(user.id, vec![reading()])
+        .await
+        .unwrap();
+
+    let session_id = state
+        .authenticate(
+            &None,
+            &Some(String::from("live@example.com")),
+            &String::from("password"),
+            &AuditContext::default(),
+        )
+        .await
+        .unwrap();
+
+    let acceptor = TcpListener::bind("127.0.0.1:0")
+        .into_acceptor()
+        .await
+        .unwrap();
+    let address = *acceptor.local_addr()[0].as_socket_addr().unwrap();
+    let app = Route::new()
+        .nest("/live", scheduler::server::live::route())
+        .with(AddData::new(Arc::new(state)));
+    tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+
+    let server = TestServer {
+        address,
+        session_id,
+        _dir: dir,
+    };
+
+    let started = server
+        .client()
+        .post(server.url("/live/start"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(started.status(), 201);
+
+    server
+}
+
+impl TestServer {
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    fn client(&self) -> ClientWithSession {
+        ClientWithSession(self.session_id.clone())
+    }
+}
+
+struct ClientWithSession(String);
+
+impl ClientWithSession {
+    fn get(&self, url: String) -> reqwest::RequestBuilder {
+        reqwest::Client::new().get(url).bearer_auth(&self.0)
+    }
+
+    fn post(&self, url: String) -> reqwest::RequestBuilder {
+        reqwest::Client::new().post(url).bearer_auth(&self.0)
+    }
+}
+
+// Reads `data:` lines off the stream until one of `kind` comes
+async fn next_sse(response: &mut reqwest::Response, buffer: &mut String, kind: &str) -> String {
+    tokio::time::timeout(WAIT, async {
+        loop {
+            while let Some(end) = buffer.find('\n') {
+                let line: String = buffer.drain(..=end).collect();
+                if let Some(data) = line.trim_end().strip_prefix("data: ")
+                    && data.contains(&format!(r#""type":"{}""#, kind))
+                {
+                    return data.to_string();
+                }
+            }
+
+            let chunk = response.chunk().await.unwrap().expect("Stream ended");
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("No {} event", kind))
+}
+
+#[tokio::test]
+async fn events_are_streamed_over_sse() {
+    let server = serve().await;
+
+    let mut response = server
+        .client()
+        .get(server.url("/live/events"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Started over the socket, the stream sees it all the same
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!(
+        "ws://{}/live/ws?session_id={}",
+        server.address, server.session_id
+    ))
+    .await
+    .unwrap();
+    socket
+        .send(Message::Text(r#"{"command":"start"}"#.into()))
+        .await
+        .unwrap();
+
+    let mut buffer = String::new();
+    let started = next_sse(&mut response, &mut buffer, "block_started").await;
+    assert!(started.contains(r#""origin_name":"Reading""#));
+    next_sse(&mut response, &mut buffer, "tick").await;
+}
+
+#[tokio::test]
+async fn events_are_streamed_over_websockets() {
+    let server = serve().await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!(
+        "ws://{}/live/ws?session_id={}",
+        server.address, server.session_id
+    ))
+    .await
+    .unwrap();
+
+    // Bad commands are ignored, the good one goes through
+    socket
+        .send(Message::Text(r#"{"command":"dance"}"#.into()))
+        .await
+        .unwrap();
+    socket
+        .send(Message::Text(r#"{"command":"start"}"#.into()))
+        .await
+        .unwrap();
+
+    let mut seen = Vec::new();
+    tokio::time::timeout(WAIT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+
+            let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+            seen.push(event["type"].as_str().unwrap().to_string());
+
+            if seen.iter().any(|kind| kind == "tick") {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(seen[0], "block_started");
+    assert!(seen.iter().any(|kind| kind == "tick"));
+}
+
+#[tokio::test]
+async fn streams_need_a_session_and_a_user() {
+    let server = serve().await;
+
+    let anonymous = reqwest::get(server.url("/live/events")).await.unwrap();
+    assert_eq!(anonymous.status(), 401);
+
+    let stopped = server
+        .client()
+        .post(server.url("/live/stop"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stopped.status(), 204);
+
+    let detached = server
+        .client()
+        .get(server.url("/live/events"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(detached.status(), 404);
+}
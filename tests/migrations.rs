@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+
+use sqlx::{
+    Acquire, Pool,
+    migrate::{Migrate, Migrator},
+    postgres::{PgPool, PgPoolOptions},
+    sqlite::SqlitePoolOptions,
+};
+use uuid::Uuid;
+
+static SQLITE: Migrator = sqlx::migrate!("./migrations_sqlite");
+static POSTGRES: Migrator = sqlx::migrate!("./migrations_postgres");
+
+// Only the migrations up to and including `version`
+fn up_to(migrator: &Migrator, version: i64) -> Migrator {
+    Migrator {
+        migrations: Cow::Owned(
+            migrator
+                .iter()
+                .filter(|m| m.version <= version)
+                .cloned()
+                .collect(),
+        ),
+        ignore_missing: false,
+        locking: migrator.locking,
+    }
+}
+
+// Applies every migration on its own, then checks that its
+// down migration leaves the schema exactly as it was before
+async fn check_reverts<DB, S, F>(migrator: &Migrator, pool: &Pool<DB>, schema: S)
+where
+    DB: sqlx::Database,
+    for<'a> &'a mut DB::Connection: Acquire<'a, Database = DB>,
+    DB::Connection: Migrate,
+    S: Fn() -> F,
+    F: Future<Output = Vec<String>>,
+{
+    let versions: Vec<i64> = migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .collect();
+
+    let mut before = schema().await;
+    let mut previous = 0;
+
+    for version in versions {
+        let migrations = up_to(migrator, version);
+        migrations.run(pool).await.unwrap();
+        let after = schema().await;
+        assert_ne!(after, before, "{} changed nothing", version);
+
+        migrator.undo(pool, previous).await.unwrap();
+        assert_eq!(schema().await, before, "{} down", version);
+
+        // Nothing is left behind that gets in the way of
+        // applying it again
+        migrations.run(pool).await.unwrap();
+        assert_eq!(schema().await, after, "{} up again", version);
+
+        before = after;
+        previous = version;
+    }
+}
+
+#[tokio::test]
+async fn sqlite_down_migrations_revert_their_up_migration() {
+    // One connection, every other would be a database of its own
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    let schema = || async {
+        sqlx::query_scalar(
+            r#"
+        SELECT type || ' ' || name || ' ' || COALESCE(sql, '') FROM sqlite_master
+        WHERE name NOT LIKE '\_sqlx%' ESCAPE '\' AND name NOT LIKE 'sqlite\_%' ESCAPE '\'
+        ORDER BY name;
+        "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+    };
+
+    check_reverts(&SQLITE, &pool, schema).await;
+}
+
+// Needs a server, only runs when DATABASE_URL points to one.
+// Everything happens in a schema of its own, which is dropped
+// again
+#[tokio::test]
+async fn postgres_down_migrations_revert_their_up_migration() {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set, skipping Postgres");
+        return;
+    };
+
+    let name = format!("migrations_{}", Uuid::new_v4().simple());
+    let admin = PgPool::connect(&url).await.unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {};", name))
+        .execute(&admin)
+        .await
+        .unwrap();
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&format!(
+            "{}{}options[search_path]={}",
+            url, separator, name
+        ))
+        .await
+        .unwrap();
+
+    let schema = || async {
+        sqlx::query_scalar(
+            r#"
+        SELECT table_name || '.' || column_name || ' ' || data_type || ' ' || is_nullable
+        FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name <> '_sqlx_migrations'
+        UNION ALL
+        SELECT indexdef FROM pg_indexes
+        WHERE schemaname = current_schema() AND tablename <> '_sqlx_migrations'
+        ORDER BY 1;
+        "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+    };
+
+    check_reverts(&POSTGRES, &pool, schema).await;
+
+    pool.close().await;
+    sqlx::query(&format!("DROP SCHEMA {} CASCADE;", name))
+        .execute(&admin)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,490 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use poem::{
+    EndpointExt, Route, Server,
+    listener::{Acceptor, Listener, TcpListener},
+    middleware::AddData,
+};
+use reqwest::{Method, StatusCode};
+use scheduler::{
+    AppState,
+    cache::local::LocalStorage,
+    database::{
+        config::DatabaseConfiguration,
+        data::{AuditAction, AuditContext, Credentials, User},
+        sqlite::Sqlite,
+    },
+    schedule::Scheduler,
+    task::{RecordError, Task, TaskConfiguration, TaskRecord},
+};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+fn start() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(60 * minutes)
+}
+
+// `time` minutes of Reading, finished `hour` hours after `start()`
+fn record(time: u64, hour: i64) -> TaskRecord {
+    TaskRecord::manual(
+        "Reading",
+        "Study",
+        minutes(time),
+        start() + TimeDelta::hours(hour),
+    )
+}
+
+fn ids(scheduler: &Scheduler) -> Vec<Uuid> {
+    scheduler.history().iter().map(|r| r.id).collect()
+}
+
+#[test]
+fn logged_records_go_in_finishing_order() {
+    let mut scheduler = Scheduler::default();
+
+    let late = scheduler.log_record(record(30, 5));
+    let early = scheduler.log_record(record(30, 1));
+    let middle = scheduler.log_record(record(30, 3));
+
+    // Nothing to place it by, so it goes last
+    let mut unfinished = record(30, 0);
+    unfinished.finished = None;
+    let unfinished = scheduler.log_record(unfinished);
+
+    assert_eq!(ids(&scheduler), vec![early, middle, late, unfinished]);
+}
+
+#[test]
+fn updated_records_keep_their_id_and_move() {
+    let mut scheduler = Scheduler::default();
+    let first = scheduler.log_record(record(30, 1));
+    let second = scheduler.log_record(record(30, 2));
+
+    scheduler.update_record(first, record(45, 3)).unwrap();
+
+    assert_eq!(ids(&scheduler), vec![second, first]);
+    assert_eq!(scheduler.history()[1].time, minutes(45));
+    assert!(matches!(
+        scheduler.update_record(Uuid::new_v4(), record(10, 1)),
+        Err(RecordError::NotFound(_))
+    ));
+}
+
+#[test]
+fn deleted_records_are_handed_back() {
+    let mut scheduler = Scheduler::default();
+    let id = scheduler.log_record(record(30, 1));
+
+    assert_eq!(scheduler.delete_record(id).unwrap().id, id);
+    assert!(scheduler.history().is_empty());
+    assert!(matches!(
+        scheduler.delete_record(id),
+        Err(RecordError::NotFound(_))
+    ));
+}
+
+#[test]
+fn split_records_follow_each_other() {
+    let mut scheduler = Scheduler::default();
+    let id = scheduler.log_record(record(60, 1));
+    let later = scheduler.log_record(record(30, 4));
+
+    assert!(matches!(
+        scheduler.split_record(id, Duration::ZERO),
+        Err(RecordError::InvalidSplit)
+    ));
+    assert!(matches!(
+        scheduler.split_record(id, minutes(60)),
+        Err(RecordError::InvalidSplit)
+    ));
+
+    let (first, second) = scheduler.split_record(id, minutes(20)).unwrap();
+    let history = scheduler.history();
+
+    assert_eq!(first, id);
+    assert_eq!(ids(&scheduler), vec![first, second, later]);
+    assert_eq!(history[0].time, minutes(20));
+    assert_eq!(history[1].time, minutes(40));
+    assert_eq!(history[0].finished, history[1].started);
+    assert_eq!(history[1].finished, Some(start() + TimeDelta::hours(1)));
+}
+
+#[test]
+fn merged_records_cover_both() {
+    let mut scheduler = Scheduler::default();
+    let first = scheduler.log_record(record(30, 1));
+    let between = scheduler.log_record(record(10, 2));
+    let second = scheduler.log_record(record(30, 3));
+    let other = scheduler.log_record(TaskRecord::manual("Writing", "Study", minutes(30), start()));
+
+    assert!(matches!(
+        scheduler.merge_records(first, first),
+        Err(RecordError::SameRecord)
+    ));
+    assert!(matches!(
+        scheduler.merge_records(first, other),
+        Err(RecordError::DifferentTasks)
+    ));
+
+    assert_eq!(scheduler.merge_records(first, second).unwrap(), first);
+
+    // Placed by the later finish time
+    assert_eq!(ids(&scheduler), vec![other, between, first]);
+    let merged = &scheduler.history()[2];
+    assert_eq!(merged.time, minutes(60));
+    assert_eq!(merged.started, Some(start() + TimeDelta::minutes(30)));
+    assert_eq!(merged.finished, Some(start() + TimeDelta::hours(3)));
+}
+
+struct TestServer {
+    address: SocketAddr,
+    session_id: String,
+    state: Arc<AppState>,
+    user_id: Uuid,
+    dir: PathBuf,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl TestServer {
+    async fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("scheduler-records-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let state = AppState::connect::<Sqlite, LocalStorage>(&DatabaseConfiguration::file(
+            dir.join("scheduler.db").to_string_lossy(),
+        ))
+        .await
+        .unwrap();
+        state.database.migrate().await.unwrap();
+        let state = Arc::new(state);
+
+        let user = User::new("Records");
+        let creds = Credentials::new(user.id, "records@example.com", "records")
+            .add_password_and_salt("password".as_bytes())
+            .unwrap();
+        state
+            .database
+            .create_user(user.clone(), creds, &AuditContext::default())
+            .await
+            .unwrap();
+        state
+            .database
+            .set_user_tasks(
+                user.id,
+                vec![Task::new("Reading", "Study", TaskConfiguration::default())],
+            )
+            .await
+            .unwrap();
+
+        let session_id = state
+            .authenticate(
+                &None,
+                &Some(String::from("records@example.com")),
+                &String::from("password"),
+                &AuditContext::default(),
+            )
+            .await
+            .unwrap();
+
+        let acceptor = TcpListener::bind("127.0.0.1:0")
+            .into_acceptor()
+            .await
+            .unwrap();
+        let address = *acceptor.local_addr()[0].as_socket_addr().unwrap();
+        let app = Route::new()
+            .nest("/live", scheduler::server::live::route())
+            .nest("/records", scheduler::server::records::route())
+            .nest("/tasks", scheduler::server::tasks::route())
+            .with(AddData::new(state.clone()));
+        tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+
+        Self {
+            address,
+            session_id,
+            state,
+            user_id: user.id,
+            dir,
+        }
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = reqwest::Client::new()
+            .request(method, format!("http://{}{}", self.address, path))
+            .bearer_auth(&self.session_id);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        let text = response.text().await.unwrap();
+
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    async fn log(&self, time: u64, hour: i64) -> String {
+        let (status, body) = self
+            .send(
+                Method::POST,
+                "/records",
+                Some(json!({
+                    "name": "Reading",
+                    "group": "Study",
+                    "minutes": time,
+                    "finished": start() + TimeDelta::hours(hour),
+                })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        body["id"].as_str().unwrap().to_string()
+    }
+
+    async fn listed(&self) -> Vec<(String, u64)> {
+        let (status, body) = self.send(Method::GET, "/records", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        body.as_array()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                (
+                    r["id"].as_str().unwrap().to_string(),
+                    r["time"]["secs"].as_u64().unwrap() / 60,
+                )
+            })
+            .collect()
+    }
+
+    // Oldest first, logins left out
+    async fn audited(&self) -> Vec<AuditAction> {
+        let mut entries = self
+            .state
+            .database
+            .get_user_audit(self.user_id, 50)
+            .await
+            .unwrap();
+        entries.reverse();
+
+        entries
+            .into_iter()
+            .map(|entry| entry.action)
+            .filter(|action| {
+                *action != AuditAction::LoggedIn && *action != AuditAction::UserCreated
+            })
+            .collect()
+    }
+
+    async fn stored(&self) -> Vec<TaskRecord> {
+        self.state
+            .database
+            .get_user_records(self.user_id)
+            .await
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn records_are_edited_without_a_live_session() {
+    let server = TestServer::new().await;
+
+    let late = server.log(30, 5).await;
+    let early = server.log(60, 1).await;
+    assert_eq!(
+        server.listed().await,
+        vec![(early.clone(), 60), (late.clone(), 30)]
+    );
+
+    let (status, _) = server
+        .send(
+            Method::PUT,
+            &format!("/records/{}", late),
+            Some(json!({ "name": "Reading", "group": "Study", "minutes": 45 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = server
+        .send(
+            Method::POST,
+            &format!("/records/{}/split", early),
+            Some(json!({ "minutes": 20 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let second = body["second"].as_str().unwrap().to_string();
+    assert_eq!(
+        server.listed().await,
+        vec![
+            (early.clone(), 20),
+            (second.clone(), 40),
+            (late.clone(), 45)
+        ]
+    );
+
+    let (status, body) = server
+        .send(
+            Method::POST,
+            "/records/merge",
+            Some(json!({ "first": early, "second": second })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"].as_str(), Some(early.as_str()));
+
+    let (status, _) = server
+        .send(Method::DELETE, &format!("/records/{}", late), None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let stored = server.stored().await;
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].id.to_string(), early);
+    assert_eq!(stored[0].time, minutes(60));
+
+    assert_eq!(
+        server.audited().await,
+        vec![
+            AuditAction::RecordLogged,
+            AuditAction::RecordLogged,
+            AuditAction::RecordEdited,
+            AuditAction::RecordSplit,
+            AuditAction::RecordsMerged,
+            AuditAction::RecordDeleted,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn bad_edits_are_rejected() {
+    let server = TestServer::new().await;
+
+    let id = server.log(30, 1).await;
+    let missing = Uuid::new_v4();
+
+    let (status, _) = server
+        .send(Method::DELETE, &format!("/records/{}", missing), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = server
+        .send(
+            Method::POST,
+            &format!("/records/{}/split", id),
+            Some(json!({ "minutes": 30 })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = server
+        .send(
+            Method::POST,
+            "/records/merge",
+            Some(json!({ "first": id, "second": id })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = server
+        .send(
+            Method::POST,
+            "/records",
+            Some(json!({ "name": "Reading", "group": "Study", "minutes": u64::MAX })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Only the one that went through
+    assert_eq!(server.stored().await.len(), 1);
+    assert_eq!(server.audited().await, vec![AuditAction::RecordLogged]);
+}
+
+#[tokio::test]
+async fn live_sessions_see_the_edits() {
+    let server = TestServer::new().await;
+
+    let (status, _) = server.send(Method::POST, "/live/start", None).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let id = server.log(30, 1).await;
+
+    let live = server
+        .state
+        .live
+        .inspect_history(server.user_id, |scheduler| scheduler.history().to_vec())
+        .await
+        .unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].id.to_string(), id);
+    assert_eq!(server.stored().await, live);
+
+    let (status, _) = server
+        .send(Method::DELETE, &format!("/records/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Rejected by the session, nothing to audit
+    let (status, _) = server
+        .send(Method::DELETE, &format!("/records/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    assert!(server.stored().await.is_empty());
+    assert_eq!(
+        server.audited().await,
+        vec![AuditAction::RecordLogged, AuditAction::RecordDeleted]
+    );
+
+    server.state.live.detach(server.user_id).await;
+}
+
+#[tokio::test]
+async fn goal_changes_are_audited() {
+    let server = TestServer::new().await;
+    let goal = |days: i64| {
+        json!([{
+            "name": "Draft",
+            "task_name": "Reading",
+            "task_group": "Study",
+            "target": { "secs": 36_000, "nanos": 0 },
+            "scope": "Period",
+            "start": start(),
+            "deadline": start() + TimeDelta::days(days),
+        }])
+    };
+
+    let (status, _) = server
+        .send(Method::PUT, "/tasks/goals", Some(goal(10)))
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = server
+        .send(Method::PUT, "/tasks/goals", Some(goal(-1)))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let entries = server
+        .state
+        .database
+        .get_user_audit(server.user_id, 1)
+        .await
+        .unwrap();
+    assert_eq!(entries[0].action, AuditAction::GoalsChanged);
+    assert_eq!(entries[0].before.as_deref(), Some(""));
+    assert_eq!(entries[0].after.as_deref(), Some("Draft Study/Reading"));
+    assert_eq!(entries[0].actor_id, Some(server.user_id));
+}
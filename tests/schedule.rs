@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use scheduler::{
+    explanation::{BreakKind, ExclusionReason, ScheduleExplanation},
+    schedule::{ExpectedRatioTasks, SamplingConfiguration, ScheduleConfiguration, Scheduler},
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+
+fn now() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn task(name: &'static str) -> Task {
+    Task::new(name, "Study", TaskConfiguration::default())
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(60 * minutes)
+}
+
+fn record(name: &str, minutes: u64) -> TaskRecord {
+    TaskRecord::manual(name, "Study", self::minutes(minutes), now())
+}
+
+fn explaining(tasks: Vec<(Task, f32)>, history: Vec<TaskRecord>) -> Scheduler {
+    let config = ScheduleConfiguration {
+        explain: true,
+        ..ScheduleConfiguration::default()
+    };
+
+    Scheduler::new(ExpectedRatioTasks::new(tasks).unwrap(), history, config)
+}
+
+fn explanation(scheduler: &Scheduler) -> (String, ScheduleExplanation) {
+    let task = scheduler.compute_task(&vec![], now()).unwrap();
+    (task.origin_name, task.explanation.unwrap())
+}
+
+fn cost(explanation: &ScheduleExplanation, name: &str) -> f32 {
+    explanation
+        .candidates
+        .iter()
+        .find(|candidate| candidate.name == name)
+        .unwrap()
+        .cost
+}
+
+#[test]
+fn explains_the_cost_of_every_candidate() {
+    let scheduler = explaining(
+        vec![(task("Reading"), 0.75), (task("Writing"), 0.25)],
+        vec![record("Writing", 30)],
+    );
+
+    let (chosen, explanation) = explanation(&scheduler);
+
+    // Reading next gives 45/75 and 30/75, Writing next puts
+    // all of the time on Writing
+    assert_eq!(chosen, "Reading");
+    assert_eq!(explanation.candidates.len(), 2);
+    assert!((cost(&explanation, "Reading") - 0.3).abs() < 1e-4);
+    assert!((cost(&explanation, "Writing") - 1.5).abs() < 1e-4);
+    assert!(explanation.break_trigger.is_none());
+    assert!(explanation.excluded.is_empty());
+}
+
+#[test]
+fn explains_the_current_and_target_ratio_of_the_chosen_task() {
+    let scheduler = explaining(
+        vec![(task("Reading"), 0.5), (task("Writing"), 0.5)],
+        vec![record("Reading", 10), record("Writing", 30)],
+    );
+
+    let (chosen, explanation) = explanation(&scheduler);
+
+    assert_eq!(chosen, "Reading");
+    assert!((explanation.current_ratio - 0.25).abs() < 1e-4);
+    assert_eq!(explanation.target_ratio, 0.5);
+}
+
+#[test]
+fn explains_which_break_was_due() {
+    let scheduler = explaining(
+        vec![(task("Reading"), 0.5), (task("Writing"), 0.5)],
+        vec![record("Reading", 25), record("Writing", 25)],
+    );
+
+    let (chosen, explanation) = explanation(&scheduler);
+    let trigger = explanation.break_trigger.unwrap();
+
+    assert_eq!(chosen, "Minibreak");
+    assert_eq!(trigger.kind, BreakKind::Minibreak);
+    assert_eq!(trigger.elapsed, minutes(50));
+    assert_eq!(trigger.frequency, minutes(45));
+    assert!(explanation.candidates.is_empty());
+    assert_eq!(explanation.excluded.len(), 2);
+    assert!(
+        explanation
+            .excluded
+            .iter()
+            .all(|task| task.reason == ExclusionReason::BreakDue)
+    );
+}
+
+#[test]
+fn explains_why_tasks_were_excluded() {
+    let mut closed = task("Closed");
+    closed.closed = Some(now() - TimeDelta::days(1));
+
+    let mut once = task("Once");
+    once.config.repeat = false;
+
+    let mut instant = task("Instant");
+    instant.config.time = Duration::ZERO;
+
+    // Closing later on doesn't matter yet
+    let mut closing = task("Closing");
+    closing.closed = Some(now() + TimeDelta::days(1));
+
+    let scheduler = explaining(
+        vec![
+            (task("Reading"), 0.4),
+            (closing, 0.1),
+            (closed, 0.1),
+            (task("Unwanted"), 0.0),
+            (instant, 0.2),
+            (once, 0.2),
+        ],
+        vec![record("Once", 10)],
+    );
+
+    let (chosen, explanation) = explanation(&scheduler);
+    let reason = |name: &str| {
+        explanation
+            .excluded
+            .iter()
+            .find(|task| task.name == name)
+            .map(|task| task.reason)
+    };
+
+    assert_eq!(chosen, "Reading");
+    assert_eq!(reason("Closed"), Some(ExclusionReason::Closed));
+    assert_eq!(reason("Unwanted"), Some(ExclusionReason::NoRatio));
+    assert_eq!(reason("Instant"), Some(ExclusionReason::NoTime));
+    assert_eq!(reason("Once"), Some(ExclusionReason::Completed));
+    assert_eq!(explanation.excluded.len(), 4);
+
+    let mut candidates: Vec<&str> = explanation
+        .candidates
+        .iter()
+        .map(|candidate| candidate.name.as_str())
+        .collect();
+    candidates.sort();
+    assert_eq!(candidates, ["Closing", "Reading"]);
+}
+
+#[test]
+fn nothing_is_scheduled_when_every_task_is_excluded() {
+    let mut closed = task("Closed");
+    closed.closed = Some(now());
+
+    let scheduler = explaining(vec![(closed, 1.0)], vec![]);
+
+    assert!(scheduler.compute_task(&vec![], now()).is_none());
+}
+
+#[test]
+fn explanations_are_only_attached_when_asked_for() {
+    let tasks = ExpectedRatioTasks::new(vec![(task("Reading"), 1.0)]).unwrap();
+    let scheduler = Scheduler::new(tasks, vec![], ScheduleConfiguration::default());
+
+    let task = scheduler.compute_task(&vec![], now()).unwrap();
+
+    assert!(task.explanation.is_none());
+}
+
+fn sampled(seed: u64) -> Vec<String> {
+    let config = ScheduleConfiguration {
+        sampling: Some(SamplingConfiguration {
+            seed,
+            temperature: 10.0,
+        }),
+        ..ScheduleConfiguration::default()
+    };
+
+    let tasks = ["Reading", "Writing", "Drawing", "Coding"]
+        .into_iter()
+        .map(|name| (task(name), 0.25))
+        .collect();
+    let scheduler = Scheduler::new(ExpectedRatioTasks::new(tasks).unwrap(), vec![], config);
+
+    scheduler
+        .compute_tasks(&vec![], 24, now())
+        .into_iter()
+        .map(|task| task.origin_name)
+        .filter(|name| name != "Minibreak" && name != "Break")
+        .collect()
+}
+
+#[test]
+fn the_same_seed_gives_the_same_schedule() {
+    assert_eq!(sampled(7), sampled(7));
+    assert_eq!(sampled(20_261_019), sampled(20_261_019));
+}
+
+#[test]
+fn different_seeds_give_different_schedules() {
+    assert_ne!(sampled(1), sampled(2));
+    assert_ne!(sampled(20_261_019), sampled(20_261_020));
+}
+
+// Consecutive seeds, e.g. from dates, are not the same
+// sequence shifted by one
+#[test]
+fn consecutive_seeds_are_not_shifted_copies() {
+    for seed in 0..8 {
+        let first = sampled(seed);
+        let second = sampled(seed + 1);
+
+        assert_ne!(first[1..], second[..second.len() - 1]);
+    }
+}
@@ -0,0 +1,339 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use scheduler::{
+    interruption::InterruptionKind,
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    session::{Session, SessionError, SessionEvent, SessionState, TRANSITION_GROUP},
+    storage::{Storable, StorageError},
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+
+// Kept in memory, writes fail while `failing` is set. Clones
+// share the same data so a test can look at what was written
+#[derive(Clone, Default)]
+struct MemoryStorage(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    state: Mutex<Vec<SessionState>>,
+    history: Mutex<Vec<TaskRecord>>,
+    failing: AtomicBool,
+    // Only the history can't be written, the state can
+    failing_history: AtomicBool,
+}
+
+impl MemoryStorage {
+    fn fail(&self, failing: bool) {
+        self.0.failing.store(failing, Ordering::SeqCst);
+    }
+
+    fn fail_history(&self, failing: bool) {
+        self.0.failing_history.store(failing, Ordering::SeqCst);
+    }
+
+    fn check(&self, failing: &AtomicBool) -> Result<(), StorageError> {
+        if failing.load(Ordering::SeqCst) {
+            return Err(StorageError::Io(io::Error::other("Injected")));
+        }
+
+        Ok(())
+    }
+
+    fn state(&self) -> Vec<SessionState> {
+        self.0.state.lock().unwrap().clone()
+    }
+
+    fn history(&self) -> Vec<TaskRecord> {
+        self.0.history.lock().unwrap().clone()
+    }
+}
+
+impl Storable<SessionState> for MemoryStorage {
+    fn store(&self, data: &[SessionState]) -> Result<(), StorageError> {
+        self.check(&self.0.failing)?;
+        *self.0.state.lock().unwrap() = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<SessionState>, StorageError> {
+        Ok(self.state())
+    }
+}
+
+impl Storable<TaskRecord> for MemoryStorage {
+    fn store(&self, data: &[TaskRecord]) -> Result<(), StorageError> {
+        self.check(&self.0.failing)?;
+        self.check(&self.0.failing_history)?;
+        *self.0.history.lock().unwrap() = data.to_vec();
+        Ok(())
+    }
+
+    fn get(&self) -> Result<Vec<TaskRecord>, StorageError> {
+        Ok(self.history())
+    }
+}
+
+fn start() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH + TimeDelta::days(20_000)
+}
+
+fn at(minutes: i64) -> DateTime<Utc> {
+    start() + TimeDelta::minutes(minutes)
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(60 * minutes)
+}
+
+fn session() -> (Session<MemoryStorage>, MemoryStorage) {
+    let tasks = ExpectedRatioTasks::new(vec![(
+        Task::new("Reading", "Study", TaskConfiguration::default()),
+        1.0,
+    )])
+    .unwrap();
+    let scheduler = Scheduler::new(tasks, vec![], ScheduleConfiguration::default());
+
+    let storage = MemoryStorage::default();
+    let session = Session::new(scheduler, storage.clone()).unwrap();
+
+    (session, storage)
+}
+
+fn block_name(session: &Session<MemoryStorage>) -> Option<String> {
+    session
+        .state
+        .block()
+        .map(|block| block.task.origin_name.clone())
+}
+
+#[test]
+fn start_runs_the_next_task() {
+    let (mut session, storage) = session();
+
+    let started = session.start(at(0)).unwrap();
+
+    assert_eq!(started.event, SessionEvent::Started);
+    assert_eq!(started.task.origin_name, "Reading");
+    assert!(matches!(session.state, SessionState::Running(_)));
+    assert!(matches!(storage.state()[..], [SessionState::Running(_)]));
+    assert!(matches!(
+        session.start(at(1)),
+        Err(SessionError::AlreadyActive)
+    ));
+}
+
+#[test]
+fn changes_need_a_block_in_the_right_state() {
+    let (mut session, _storage) = session();
+
+    assert!(matches!(
+        session.pause(at(0)),
+        Err(SessionError::NotRunning)
+    ));
+    assert!(matches!(
+        session.resume(at(0)),
+        Err(SessionError::NotPaused)
+    ));
+    assert!(matches!(
+        session.extend(minutes(5), at(0)),
+        Err(SessionError::NoActiveBlock)
+    ));
+    assert!(matches!(
+        session.interrupt(InterruptionKind::Internal, minutes(1), None, at(0)),
+        Err(SessionError::NoActiveBlock)
+    ));
+    assert!(matches!(
+        session.skip(at(0)),
+        Err(SessionError::NoActiveBlock)
+    ));
+    assert!(matches!(
+        session.finish(at(0)),
+        Err(SessionError::NoActiveBlock)
+    ));
+
+    session.start(at(0)).unwrap();
+    assert!(matches!(
+        session.resume(at(1)),
+        Err(SessionError::NotPaused)
+    ));
+
+    session.pause(at(1)).unwrap();
+    assert!(matches!(
+        session.pause(at(2)),
+        Err(SessionError::NotRunning)
+    ));
+}
+
+#[test]
+fn pauses_are_not_worked_time() {
+    let (mut session, storage) = session();
+
+    session.start(at(0)).unwrap();
+    assert_eq!(session.pause(at(10)).unwrap().event, SessionEvent::Paused);
+    assert_eq!(session.state.remaining(at(15)), Some(minutes(35)));
+    assert_eq!(session.resume(at(20)).unwrap().event, SessionEvent::Resumed);
+
+    let finished = session.finish(at(30)).unwrap();
+    let record = finished.record.unwrap();
+
+    assert_eq!(finished.event, SessionEvent::FinishedEarly);
+    assert_eq!(record.time, minutes(20));
+    assert_eq!(record.planned, Some(minutes(45)));
+    assert!(matches!(session.state, SessionState::Idle));
+    assert_eq!(storage.history(), vec![record]);
+}
+
+#[test]
+fn extending_moves_the_end_of_the_block() {
+    let (mut session, _storage) = session();
+
+    session.start(at(0)).unwrap();
+    assert_eq!(
+        session.extend(minutes(15), at(10)).unwrap().event,
+        SessionEvent::Extended
+    );
+    assert_eq!(session.state.remaining(at(10)), Some(minutes(50)));
+
+    assert!(matches!(
+        session.extend(Duration::MAX, at(10)),
+        Err(SessionError::InvalidDuration)
+    ));
+    assert_eq!(session.state.remaining(at(10)), Some(minutes(50)));
+}
+
+#[test]
+fn interruptions_are_taken_out_of_the_record() {
+    let (mut session, _storage) = session();
+
+    session.start(at(0)).unwrap();
+    session
+        .interrupt(
+            InterruptionKind::External,
+            minutes(5),
+            Some(String::from("Call")),
+            at(10),
+        )
+        .unwrap();
+
+    let record = session.finish(at(30)).unwrap().record.unwrap();
+
+    assert_eq!(record.time, minutes(25));
+    assert_eq!(record.interruptions.len(), 1);
+    assert_eq!(record.interruptions[0].note.as_deref(), Some("Call"));
+}
+
+#[test]
+fn interruptions_never_make_the_record_negative() {
+    let (mut session, _storage) = session();
+
+    session.start(at(0)).unwrap();
+    session.pause(at(10)).unwrap();
+    session
+        .interrupt(InterruptionKind::Internal, minutes(15), None, at(12))
+        .unwrap();
+
+    let record = session.finish(at(20)).unwrap().record.unwrap();
+
+    assert_eq!(record.time, Duration::ZERO);
+    assert_eq!(record.interruptions.len(), 1);
+}
+
+#[test]
+fn skipping_only_records_time_already_spent() {
+    let (mut session, _storage) = session();
+
+    session.start(at(0)).unwrap();
+    let skipped = session.skip(at(0)).unwrap();
+    assert_eq!(skipped.event, SessionEvent::Skipped);
+    assert!(skipped.record.is_none());
+
+    session.start(at(1)).unwrap();
+    let record = session.skip(at(6)).unwrap().record.unwrap();
+    assert_eq!(record.time, minutes(5));
+    assert_eq!(session.scheduler.history().len(), 1);
+}
+
+#[test]
+fn ticks_complete_blocks_and_move_on() {
+    let (mut session, _storage) = session();
+
+    session.start(at(0)).unwrap();
+    assert!(session.tick(at(44)).unwrap().is_empty());
+
+    // Ended at its planned time even though the tick is late
+    let transitions = session.tick(at(46)).unwrap();
+    assert_eq!(transitions.len(), 2);
+    assert_eq!(transitions[0].event, SessionEvent::Completed);
+    assert_eq!(transitions[0].at, at(45));
+    assert_eq!(transitions[0].record.as_ref().unwrap().time, minutes(45));
+    assert_eq!(transitions[1].task.origin_group, TRANSITION_GROUP);
+
+    // Transitions stay out of the history, after 45 minutes
+    // of work a minibreak is due
+    let transitions = session.tick(at(50)).unwrap();
+    assert!(transitions[0].record.is_none());
+    assert_eq!(block_name(&session).as_deref(), Some("Minibreak"));
+    assert_eq!(session.scheduler.history().len(), 1);
+}
+
+#[test]
+fn stored_state_is_picked_up_again() {
+    let (mut session, storage) = session();
+    session.start(at(0)).unwrap();
+    session.pause(at(10)).unwrap();
+
+    let restored = Session::new(session.scheduler.clone(), storage).unwrap();
+
+    assert!(matches!(restored.state, SessionState::Paused(_)));
+    assert_eq!(restored.state.remaining(at(60)), Some(minutes(35)));
+}
+
+#[test]
+fn failed_writes_leave_the_session_as_it_was() {
+    let (mut session, storage) = session();
+
+    storage.fail(true);
+    assert!(matches!(
+        session.start(at(0)),
+        Err(SessionError::Storage(_))
+    ));
+    assert!(matches!(session.state, SessionState::Idle));
+    assert!(storage.state().is_empty());
+
+    storage.fail(false);
+    session.start(at(0)).unwrap();
+
+    storage.fail(true);
+    assert!(matches!(
+        session.pause(at(10)),
+        Err(SessionError::Storage(_))
+    ));
+    assert!(matches!(session.state, SessionState::Running(_)));
+    assert_eq!(session.state.remaining(at(10)), Some(minutes(35)));
+}
+
+#[test]
+fn failed_history_writes_keep_the_block_running() {
+    let (mut session, storage) = session();
+    session.start(at(0)).unwrap();
+
+    // The idle state is written before the history fails,
+    // it is put back to the running block
+    storage.fail_history(true);
+    assert!(matches!(
+        session.finish(at(30)),
+        Err(SessionError::Storage(_))
+    ));
+    assert!(matches!(session.state, SessionState::Running(_)));
+    assert!(session.scheduler.history().is_empty());
+    assert!(matches!(storage.state()[..], [SessionState::Running(_)]));
+    assert!(storage.history().is_empty());
+
+    storage.fail_history(false);
+    let record = session.finish(at(30)).unwrap().record.unwrap();
+    assert_eq!(storage.history(), vec![record]);
+}
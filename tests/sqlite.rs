@@ -0,0 +1,170 @@
+use std::fs;
+use std::time::Duration;
+
+use chrono::Utc;
+use scheduler::database::{
+    client::{Database, DatabaseSchedule, DatabaseUser},
+    config::DatabaseConfiguration,
+    data::{AuditContext, Credentials, User},
+    generic::Scope,
+    sqlite::Sqlite,
+};
+use scheduler::task::TaskRecord;
+use sqlx::sqlite::SqlitePool;
+use uuid::Uuid;
+
+// Database file removed again when the test is done
+struct TempDatabase(String);
+
+impl TempDatabase {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("scheduler-sqlite-{}.db", Uuid::new_v4()));
+        Self(path.to_string_lossy().into_owned())
+    }
+
+    async fn connect(&self) -> Sqlite {
+        let database = Sqlite::connect(&DatabaseConfiguration::file(&self.0))
+            .await
+            .unwrap();
+        database.migrate().await.unwrap();
+        database
+    }
+
+    // Changes the file behind the backend's back, like a hand
+    // edit would
+    async fn execute(&self, statement: &str) {
+        let pool = SqlitePool::connect(&format!("sqlite://{}", self.0))
+            .await
+            .unwrap();
+        sqlx::query(statement).execute(&pool).await.unwrap();
+        pool.close().await;
+    }
+
+    async fn count(&self, table: &str) -> i64 {
+        let pool = SqlitePool::connect(&format!("sqlite://{}", self.0))
+            .await
+            .unwrap();
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        count
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", self.0, suffix));
+        }
+    }
+}
+
+async fn create_user(database: &Sqlite) -> Uuid {
+    let user = User::new("Sqlite");
+    let creds = Credentials::new(user.id, "sqlite@example.com", "sqlite")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+    user.id
+}
+
+#[tokio::test]
+async fn invalid_durations_are_errors() {
+    let file = TempDatabase::new();
+    let database = file.connect().await;
+    let user_id = create_user(&database).await;
+
+    let record = TaskRecord::manual("Reading", "Study", Duration::from_secs(60), Utc::now());
+    database
+        .set_user_records(user_id, vec![record])
+        .await
+        .unwrap();
+
+    file.execute("UPDATE TaskRecords SET time = -60").await;
+    assert!(database.get_user_records(user_id).await.is_err());
+
+    file.execute("UPDATE TaskRecords SET time = 60, planned = -1")
+        .await;
+    assert!(database.get_user_records(user_id).await.is_err());
+}
+
+// The user is inserted before the credentials, when those
+// fail the user has to go again
+#[tokio::test]
+async fn failed_create_user_leaves_nothing() {
+    let file = TempDatabase::new();
+    let database = file.connect().await;
+    file.execute(
+        "CREATE TRIGGER FailCredentials BEFORE INSERT ON UserCredentials \
+         BEGIN SELECT RAISE(ABORT, 'failed'); END;",
+    )
+    .await;
+
+    let user = User::new("Sqlite");
+    let creds = Credentials::new(user.id, "sqlite@example.com", "sqlite")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    assert!(
+        database
+            .create_user(user, creds, &AuditContext::default())
+            .await
+            .is_err()
+    );
+
+    assert_eq!(file.count("Users").await, 0);
+}
+
+// The credentials are deleted before the user, when that
+// fails they have to come back
+#[tokio::test]
+async fn failed_delete_user_keeps_everything() {
+    let file = TempDatabase::new();
+    let database = file.connect().await;
+    let user_id = create_user(&database).await;
+    file.execute(
+        "CREATE TRIGGER FailUsers BEFORE DELETE ON Users \
+         BEGIN SELECT RAISE(ABORT, 'failed'); END;",
+    )
+    .await;
+
+    assert!(
+        database
+            .delete_user(user_id, &AuditContext::default())
+            .await
+            .is_err()
+    );
+
+    assert_eq!(file.count("Users").await, 1);
+    assert_eq!(file.count("UserCredentials").await, 1);
+    assert!(database.get_user_creds(&user_id.to_string()).await.is_ok());
+}
+
+// A transaction has one connection, asking for it twice fails
+// instead of waiting for itself
+#[tokio::test]
+async fn held_transaction_connection_is_not_waited_for() {
+    let file = TempDatabase::new();
+    file.connect().await;
+    let pool = SqlitePool::connect(&format!("sqlite://{}", file.0))
+        .await
+        .unwrap();
+
+    let scope = Scope::new(pool);
+    let transaction = scope.begin().await.unwrap();
+    let joined = transaction.begin().await.unwrap();
+
+    let conn = transaction.conn().await.unwrap();
+    let second = tokio::time::timeout(Duration::from_secs(5), joined.conn())
+        .await
+        .expect("Waited for the held connection");
+    assert!(second.is_err());
+    assert!(transaction.commit().await.is_err());
+
+    drop(conn);
+    assert!(joined.conn().await.is_ok());
+}
@@ -0,0 +1,356 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use scheduler::{
+    interruption::{Interruption, InterruptionKind},
+    session::SessionState,
+    storage::{FileStorage, Storable, StorageError},
+    task::{Task, TaskConfiguration, TaskRecord},
+};
+use uuid::Uuid;
+
+// Scratch directory removed again when the test is done
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("scheduler-storage-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn storage(&self, extension: &str) -> FileStorage<PathBuf> {
+        let path = |name: &str| self.0.join(format!("{}.{}", name, extension));
+        FileStorage::new(
+            path("tasks"),
+            path("records"),
+            path("ratios"),
+            path("session"),
+        )
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn records() -> Vec<TaskRecord> {
+    let started = Utc.with_ymd_and_hms(2026, 10, 1, 9, 0, 0).unwrap();
+
+    vec![
+        TaskRecord {
+            id: Uuid::new_v4(),
+            origin_name: String::from("Reading"),
+            origin_group: String::from("Study"),
+            time: Duration::from_secs(40 * 60),
+            planned: Some(Duration::from_secs(45 * 60)),
+            started: Some(started),
+            finished: Some(started + chrono::TimeDelta::minutes(45)),
+            interruptions: vec![Interruption {
+                kind: InterruptionKind::External,
+                duration: Duration::from_secs(5 * 60),
+                note: Some(String::from("Call, \"urgent\"")),
+                at: started + chrono::TimeDelta::minutes(10),
+            }],
+        },
+        TaskRecord::manual("Writing", "Work", Duration::from_millis(90_500), started),
+    ]
+}
+
+fn tasks() -> Vec<Task> {
+    vec![
+        Task::new("Reading", "Study", TaskConfiguration::default()),
+        Task::new(
+            "Writing",
+            "Work",
+            TaskConfiguration {
+                time: Duration::from_secs(25 * 60),
+                repeat: false,
+                effort: Some(Duration::from_secs(10 * 60 * 60)),
+            },
+        ),
+    ]
+}
+
+fn ratios() -> Vec<((String, String), f32)> {
+    vec![
+        ((String::from("Reading"), String::from("Study")), 0.25),
+        ((String::from("Writing"), String::from("Work")), 0.75),
+    ]
+}
+
+// Types without PartialEq are compared by what they
+// serialize to
+fn json<T: serde::Serialize>(data: &T) -> serde_json::Value {
+    serde_json::to_value(data).unwrap()
+}
+
+fn round_trip(extension: &str) {
+    let dir = TempDir::new();
+    let storage = dir.storage(extension);
+
+    let tasks = tasks();
+    storage.store(&tasks).unwrap();
+    let stored: Vec<Task> = storage.get().unwrap();
+    assert_eq!(json(&stored), json(&tasks), "tasks in {}", extension);
+
+    storage.store(&ratios()).unwrap();
+    let stored: Vec<((String, String), f32)> = storage.get().unwrap();
+    assert_eq!(stored, ratios(), "ratios in {}", extension);
+
+    let records = records();
+    storage.store(&records).unwrap();
+    let stored: Vec<TaskRecord> = storage.get().unwrap();
+    assert_eq!(stored, records, "records in {}", extension);
+
+    let state = vec![SessionState::Idle];
+    storage.store(&state).unwrap();
+    let stored: Vec<SessionState> = storage.get().unwrap();
+    assert_eq!(json(&stored), json(&state), "session in {}", extension);
+}
+
+#[test]
+fn json_round_trip() {
+    round_trip("json");
+}
+
+#[test]
+fn json_lines_round_trip() {
+    round_trip("jsonl");
+}
+
+#[test]
+fn yaml_round_trip() {
+    round_trip("yaml");
+}
+
+#[test]
+fn toml_round_trip() {
+    round_trip("toml");
+}
+
+#[test]
+fn csv_round_trip() {
+    let dir = TempDir::new();
+    let storage = dir.storage("csv");
+
+    let records = records();
+    storage.store(&records).unwrap();
+    let stored: Vec<TaskRecord> = storage.get().unwrap();
+    assert_eq!(stored, records);
+
+    // Anything but task history has no rows to go in
+    let result = storage.store(&tasks());
+    assert!(matches!(result, Err(StorageError::Unsupported(..))));
+}
+
+#[test]
+fn csv_rejects_invalid_durations() {
+    let dir = TempDir::new();
+    let storage = dir.storage("csv");
+
+    fs::write(
+        dir.0.join("records.csv"),
+        format!(
+            "id,name,group,seconds,planned_seconds,started,finished,interruptions\n\
+             {},Reading,Study,-60,,,,\n",
+            Uuid::new_v4()
+        ),
+    )
+    .unwrap();
+
+    let result: Result<Vec<TaskRecord>, _> = storage.get();
+    assert!(matches!(result, Err(StorageError::InvalidDuration(_))));
+}
+
+// Version 1 kept ratios as objects
+fn ratio_objects(entries: serde_json::Value) -> Result<serde_json::Value, StorageError> {
+    let entries = entries.as_array().cloned().unwrap_or_default();
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| serde_json::json!([[entry["name"], entry["group"]], entry["ratio"]]))
+        .collect())
+}
+
+#[test]
+fn json_lines_files_are_upgraded_in_place() {
+    let dir = TempDir::new();
+    let path = dir.0.join("ratios.jsonl");
+    let original = "{\"name\":\"Reading\",\"group\":\"Study\",\"ratio\":0.25}\n\
+                    {\"name\":\"Writing\",\"group\":\"Work\",\"ratio\":0.75}\n";
+    fs::write(&path, original).unwrap();
+
+    let storage = dir
+        .storage("jsonl")
+        .with_upgrades::<((String, String), f32)>(vec![ratio_objects]);
+
+    let stored: Vec<((String, String), f32)> = storage.get().unwrap();
+    assert_eq!(stored, ratios());
+
+    let upgraded = fs::read_to_string(&path).unwrap();
+    assert!(upgraded.starts_with("{\"version\":2}\n"));
+    assert_eq!(
+        fs::read_to_string(dir.0.join("ratios.jsonl.v1.bak")).unwrap(),
+        original
+    );
+
+    // Appends go after the version line
+    storage
+        .append(&[((String::from("Running"), String::from("Sport")), 0.0f32)])
+        .unwrap();
+    let stored: Vec<((String, String), f32)> = storage.get().unwrap();
+    assert_eq!(stored.len(), 3);
+}
+
+#[test]
+fn newer_files_are_rejected() {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("ratios.jsonl"), "{\"version\":9}\n").unwrap();
+    fs::write(
+        dir.0.join("records.csv"),
+        "# version 9\nid,name,group,seconds,planned_seconds,started,finished,interruptions\n",
+    )
+    .unwrap();
+
+    let storage = dir.storage("jsonl");
+    let result: Result<Vec<((String, String), f32)>, _> = storage.get();
+    assert!(matches!(
+        result,
+        Err(StorageError::UnsupportedVersion(9, 1))
+    ));
+
+    let storage = dir.storage("csv");
+    let result: Result<Vec<TaskRecord>, _> = storage.get();
+    assert!(matches!(
+        result,
+        Err(StorageError::UnsupportedVersion(9, 1))
+    ));
+}
+
+#[test]
+fn csv_files_carry_their_version() {
+    let dir = TempDir::new();
+    let storage = dir.storage("csv");
+
+    storage.store(&records()).unwrap();
+
+    let buf = fs::read_to_string(dir.0.join("records.csv")).unwrap();
+    assert!(buf.starts_with("# version 1\nid,"));
+}
+
+#[test]
+fn missing_files_are_empty_collections() {
+    let dir = TempDir::new();
+
+    for extension in ["json", "jsonl", "yaml", "toml", "csv"] {
+        let storage = dir.storage(extension);
+
+        let records: Vec<TaskRecord> = storage.get().unwrap();
+        assert!(records.is_empty(), "records in {}", extension);
+    }
+
+    let tasks: Vec<Task> = dir.storage("json").get().unwrap();
+    assert!(tasks.is_empty());
+}
+
+#[test]
+fn empty_files_are_empty_collections() {
+    let dir = TempDir::new();
+
+    for extension in ["json", "jsonl", "yaml", "toml", "csv"] {
+        let storage = dir.storage(extension);
+        fs::write(dir.0.join(format!("records.{}", extension)), "").unwrap();
+        fs::write(dir.0.join(format!("tasks.{}", extension)), "\n  \n").unwrap();
+
+        let records: Vec<TaskRecord> = storage.get().unwrap();
+        assert!(records.is_empty(), "records in {}", extension);
+
+        if extension != "csv" {
+            let tasks: Vec<Task> = storage.get().unwrap();
+            assert!(tasks.is_empty(), "tasks in {}", extension);
+        }
+    }
+}
+
+// Cut off halfway, as if the process died while writing
+fn truncate(path: PathBuf) {
+    let buf = fs::read(&path).unwrap();
+    fs::write(&path, &buf[..buf.len() / 2]).unwrap();
+}
+
+#[test]
+fn truncated_files_are_errors() {
+    let dir = TempDir::new();
+
+    for extension in ["json", "yaml", "toml"] {
+        let storage = dir.storage(extension);
+        storage.store(&tasks()).unwrap();
+        truncate(dir.0.join(format!("tasks.{}", extension)));
+
+        let result: Result<Vec<Task>, _> = storage.get();
+        assert!(result.is_err(), "tasks in {}", extension);
+    }
+
+    let storage = dir.storage("csv");
+    storage.store(&records()).unwrap();
+    truncate(dir.0.join("records.csv"));
+
+    let result: Result<Vec<TaskRecord>, _> = storage.get();
+    assert!(result.is_err());
+}
+
+#[test]
+fn partially_written_lines_are_skipped_and_cut_off() {
+    let dir = TempDir::new();
+    let storage = dir.storage("jsonl");
+    let records = records();
+    storage.store(&records).unwrap();
+
+    // A crash in the middle of an append
+    let path = dir.0.join("records.jsonl");
+    let mut buf = fs::read_to_string(&path).unwrap();
+    buf.push_str(r#"{"id":"#);
+    fs::write(&path, buf).unwrap();
+
+    let stored: Vec<TaskRecord> = storage.get().unwrap();
+    assert_eq!(stored, records);
+
+    let more = TaskRecord::manual("Drawing", "Art", Duration::from_secs(600), Utc::now());
+    storage.append(std::slice::from_ref(&more)).unwrap();
+
+    let stored: Vec<TaskRecord> = storage.get().unwrap();
+    assert_eq!(stored.len(), records.len() + 1);
+    assert_eq!(stored.last(), Some(&more));
+    assert!(!fs::read_to_string(&path).unwrap().contains(r#"{"id":{"#));
+}
+
+#[test]
+fn unknown_extensions_are_read_as_json() {
+    let dir = TempDir::new();
+    let path = |name: &str| dir.0.join(format!("{}.dat", name));
+    let storage = FileStorage::new(
+        path("tasks"),
+        path("records"),
+        path("ratios"),
+        path("session"),
+    );
+
+    let tasks = tasks();
+    storage.store(&tasks).unwrap();
+    let stored: Vec<Task> = storage.get().unwrap();
+    assert_eq!(json(&stored), json(&tasks));
+
+    let written: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(path("tasks")).unwrap()).unwrap();
+    assert!(written.is_object());
+
+    // Something else under that name is not guessed at
+    fs::write(path("tasks"), "version = 1\n").unwrap();
+    let result: Result<Vec<Task>, _> = storage.get();
+    assert!(matches!(result, Err(StorageError::Json(_))));
+}
@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use chrono::{TimeDelta, Utc};
+use scheduler::{
+    cache::client::CacheStorage,
+    database::{
+        client::{Database, DatabaseAudit, DatabaseSchedule, DatabaseUser, DatabaseWebhook},
+        data::{AuditAction, AuditContext, AuditEntry, Credentials, User, Webhook},
+        error::Error,
+    },
+    task::{Task, TaskConfiguration},
+    testing::{
+        cache::{Clock, MemoryCache},
+        database::MemoryDatabase,
+    },
+};
+use uuid::Uuid;
+
+async fn create_user(database: &MemoryDatabase) -> Uuid {
+    let user = User::new("Memory");
+    let creds = Credentials::new(user.id, "memory@example.com", "memory")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+    user.id
+}
+
+fn failure() -> Error {
+    Error::Message(String::from("Injected"))
+}
+
+#[tokio::test]
+async fn commit_keeps_writes_made_next_to_it() {
+    let database = MemoryDatabase::default();
+    let user_id = create_user(&database).await;
+
+    let transaction = database.begin().await.unwrap();
+    transaction
+        .set_user_tasks(
+            user_id,
+            vec![Task::new("Reading", "Study", TaskConfiguration::default())],
+        )
+        .await
+        .unwrap();
+
+    database
+        .log_audit(AuditEntry::new(
+            user_id,
+            AuditAction::LoggedIn,
+            &AuditContext::default(),
+        ))
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    // Next to the entry of the user being created
+    assert_eq!(database.get_user_tasks(user_id).await.unwrap().len(), 1);
+    assert_eq!(database.get_user_audit(user_id, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn commit_fails_when_a_write_no_longer_applies() {
+    let database = MemoryDatabase::default();
+    let user_id = create_user(&database).await;
+
+    let transaction = database.begin().await.unwrap();
+    transaction
+        .create_webhook(Webhook::new(user_id, "http://localhost/hook", vec![]))
+        .await
+        .unwrap();
+
+    database
+        .delete_user(user_id, &AuditContext::default())
+        .await
+        .unwrap();
+
+    assert!(transaction.commit().await.is_err());
+    assert!(
+        database
+            .get_user_webhooks(user_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn faults_hit_the_calls_they_target() {
+    let database = MemoryDatabase::default();
+    let user_id = create_user(&database).await;
+
+    database
+        .faults()
+        .fail_next_call("get_user_tasks", failure());
+    assert!(database.get_user_ratios(user_id).await.is_ok());
+    assert!(database.get_user_tasks(user_id).await.is_err());
+    assert!(database.get_user_tasks(user_id).await.is_ok());
+
+    // A failed write changes nothing
+    database.faults().fail_next(failure());
+    let result = database
+        .set_user_tasks(
+            user_id,
+            vec![Task::new("Reading", "Study", TaskConfiguration::default())],
+        )
+        .await;
+    assert!(result.is_err());
+    assert!(database.get_user_tasks(user_id).await.unwrap().is_empty());
+
+    database.faults().fail_next(failure());
+    database.faults().clear();
+    assert!(database.get_user_tasks(user_id).await.is_ok());
+
+    database.faults().set_latency(Duration::from_millis(50));
+    let started = Instant::now();
+    database.get_user_tasks(user_id).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn entries_expire_when_the_clock_passes_them() {
+    let clock = Clock::starting_at(Utc::now());
+    let cache = MemoryCache::with_clock(clock.clone());
+
+    cache
+        .set(String::from("session"), String::from("user"))
+        .await
+        .unwrap();
+    cache
+        .expire(String::from("session"), TimeDelta::minutes(10))
+        .await
+        .unwrap();
+
+    clock.advance(TimeDelta::minutes(9));
+    assert_eq!(cache.get("session").await.as_deref(), Some("user"));
+
+    clock.advance(TimeDelta::minutes(1));
+    assert_eq!(cache.get("session").await, None);
+
+    // Entries without an expiry stay
+    cache
+        .set(String::from("forever"), String::from("user"))
+        .await
+        .unwrap();
+    clock.advance(TimeDelta::days(365));
+    assert!(cache.get("forever").await.is_some());
+}
+
+#[tokio::test]
+async fn cache_faults_find_nothing_and_keep_keys() {
+    let cache = MemoryCache::default();
+    cache
+        .set(String::from("session"), String::from("user"))
+        .await
+        .unwrap();
+
+    cache.faults().fail_next_call("get", ());
+    assert_eq!(cache.get("session").await, None);
+
+    cache.faults().fail_next_call("delete", ());
+    cache.delete("session").await;
+    assert!(cache.get("session").await.is_some());
+
+    cache.faults().fail_next_call("set", ());
+    assert!(
+        cache
+            .set(String::from("other"), String::from("user"))
+            .await
+            .is_err()
+    );
+}
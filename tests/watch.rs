@@ -0,0 +1,107 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use scheduler::{
+    AppState,
+    cache::local::LocalStorage,
+    database::{
+        config::DatabaseConfiguration,
+        data::{AuditContext, Credentials, User},
+        sqlite::Sqlite,
+        storage::DatabaseStorage,
+    },
+    schedule::{ExpectedRatioTasks, ScheduleConfiguration, Scheduler},
+    session::Session,
+    storage::{FileStorage, Storable},
+    task::{Task, TaskConfiguration},
+    watch::{WATCH_INTERVAL, reload_live},
+};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn edited_task_files_are_stored_and_reloaded() {
+    let dir = std::env::temp_dir().join(format!("scheduler-watch-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let state = AppState::connect::<Sqlite, LocalStorage>(&DatabaseConfiguration::file(
+        dir.join("scheduler.db").to_string_lossy(),
+    ))
+    .await
+    .unwrap();
+    state.database.migrate().await.unwrap();
+    let state = Arc::new(state);
+
+    let user = User::new("Watcher");
+    let creds = Credentials::new(user.id, "watcher@example.com", "watcher")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    state
+        .database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+
+    let storage = DatabaseStorage::load(state.database.clone(), user.id)
+        .await
+        .unwrap();
+    let scheduler = Scheduler::new(
+        ExpectedRatioTasks::default(),
+        vec![],
+        ScheduleConfiguration::default(),
+    );
+    state
+        .attach_session(user.id, Session::new(scheduler, storage).unwrap())
+        .await;
+
+    let files = |name: &str| dir.join(name);
+    reload_live(
+        state.clone(),
+        user.id,
+        FileStorage::new(
+            files("tasks.json"),
+            files("history.json"),
+            files("task-ratio.json"),
+            files("session.json"),
+        ),
+    );
+
+    // The watcher only reports changes after it started
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Written by hand, as far as the watcher can tell
+    let edited = FileStorage::new(
+        files("tasks.json"),
+        files("history.json"),
+        files("task-ratio.json"),
+        files("session.json"),
+    );
+    edited
+        .store(&[Task::new("Reading", "Study", TaskConfiguration::default())])
+        .unwrap();
+    edited
+        .store(&[((String::from("Reading"), String::from("Study")), 1.0f32)])
+        .unwrap();
+
+    let mut stored = vec![];
+    for _ in 0..10 {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        stored = state.database.get_user_tasks(user.id).await.unwrap();
+        if !stored.is_empty() {
+            break;
+        }
+    }
+
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].name, "Reading");
+
+    let live = state
+        .live
+        .inspect_history(user.id, |scheduler| scheduler.tasks.clone())
+        .await
+        .unwrap();
+    assert_eq!(live.0.len(), 1);
+
+    state.live.detach(user.id).await;
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poem::{
+    EndpointExt, Request, Route, Server, handler,
+    http::StatusCode,
+    listener::{Acceptor, Listener, TcpListener},
+    middleware::AddData,
+    post,
+    web::Data,
+};
+use scheduler::{
+    AppState,
+    cache::local::LocalStorage,
+    database::{
+        config::DatabaseConfiguration,
+        data::{AuditContext, Credentials, User, Webhook},
+        sqlite::Sqlite,
+    },
+    webhook::{
+        DELIVERY_HEADER, EVENT_HEADER, RetryPolicy, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+        WebhookDispatcher, sign,
+    },
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+struct Received {
+    signature: String,
+    timestamp: i64,
+    event: String,
+    delivery: String,
+    body: String,
+}
+
+type Inbox = Arc<Mutex<Vec<Received>>>;
+
+// Fails the first request so the retry is exercised too
+#[handler]
+fn receive(req: &Request, body: String, inbox: Data<&Inbox>) -> StatusCode {
+    let header = |name: &str| req.header(name).map(String::from).unwrap_or_default();
+
+    let mut inbox = inbox.lock().unwrap();
+    inbox.push(Received {
+        signature: header(SIGNATURE_HEADER),
+        timestamp: header(TIMESTAMP_HEADER).parse().unwrap_or_default(),
+        event: header(EVENT_HEADER),
+        delivery: header(DELIVERY_HEADER),
+        body,
+    });
+
+    if inbox.len() == 1 {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    }
+}
+
+#[tokio::test]
+async fn delivers_signed_events_to_a_local_receiver() {
+    let inbox: Inbox = Arc::default();
+
+    let acceptor = TcpListener::bind("127.0.0.1:0")
+        .into_acceptor()
+        .await
+        .unwrap();
+    let address = *acceptor.local_addr()[0].as_socket_addr().unwrap();
+    let app = Route::new()
+        .at("/hook", post(receive))
+        .with(AddData::new(inbox.clone()));
+    tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+
+    let path = std::env::temp_dir().join(format!("scheduler-webhook-{}.db", Uuid::new_v4()));
+    let state = AppState::connect::<Sqlite, LocalStorage>(&DatabaseConfiguration::file(
+        path.to_string_lossy(),
+    ))
+    .await
+    .unwrap();
+    state.database.migrate().await.unwrap();
+
+    let user = User::new("Receiver");
+    let creds = Credentials::new(user.id, "receiver@example.com", "receiver")
+        .add_password_and_salt("password".as_bytes())
+        .unwrap();
+    state
+        .database
+        .create_user(user.clone(), creds, &AuditContext::default())
+        .await
+        .unwrap();
+
+    let webhook = Webhook::new(
+        user.id,
+        format!("http://{}/hook", address),
+        vec![String::from("block_started")],
+    );
+    state
+        .database
+        .create_webhook(webhook.clone())
+        .await
+        .unwrap();
+
+    let dispatcher = WebhookDispatcher::new(RetryPolicy {
+        attempts: 3,
+        backoff: Duration::from_millis(10),
+        ..RetryPolicy::default()
+    });
+    let payload = r#"{"type":"block_started"}"#;
+    dispatcher
+        .deliver(&state, &webhook, "block_started", payload)
+        .await;
+
+    let received = inbox.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+
+    for request in &received {
+        assert_eq!(request.body, payload);
+        assert_eq!(request.event, "block_started");
+        assert_eq!(request.delivery, received[0].delivery);
+        assert_eq!(
+            request.signature,
+            sign(&webhook.secret, request.timestamp, payload.as_bytes())
+        );
+        assert!((chrono::Utc::now().timestamp() - request.timestamp).abs() < 60);
+    }
+
+    // The signature covers the timestamp, a replay with a
+    // newer one doesn't check out
+    assert_ne!(
+        received[0].signature,
+        sign(
+            &webhook.secret,
+            received[0].timestamp + 1,
+            payload.as_bytes()
+        )
+    );
+
+    let deliveries = state
+        .database
+        .get_webhook_deliveries(webhook.id)
+        .await
+        .unwrap();
+    let mut attempts: Vec<(u32, Option<u16>)> = deliveries
+        .iter()
+        .map(|d| (d.attempt, d.status_code))
+        .collect();
+    attempts.sort();
+    assert_eq!(attempts, vec![(1, Some(500)), (2, Some(200))]);
+
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}